@@ -6,6 +6,16 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let out_dir = PathBuf::from(std::env::var("OUT_DIR")?);
     let mut compiler = shaderc::Compiler::new().ok_or("shaderc not found!")?;
+    let mut options = shaderc::CompileOptions::new().ok_or("shaderc not found!")?;
+    // `#include "output.glsl"` resolves against src/shaders, the only include directory
+    options.set_include_callback(|requested, _, _, _| {
+        let path = PathBuf::from("src/shaders").join(requested);
+        let content = std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(shaderc::ResolvedInclude {
+            resolved_name: path.to_str().unwrap().to_string(),
+            content,
+        })
+    });
 
     for entry in std::fs::read_dir("src/shaders")? {
         let entry = entry?;
@@ -13,20 +23,21 @@ fn main() -> Result<(), Box<dyn Error>> {
 
         if in_path.is_file() {
             if let Some(ext) = in_path.extension().and_then(|s| s.to_str()) {
-                if ext == "vert" || ext == "frag" {
+                let kind = match ext {
+                    "vert" => Some(shaderc::ShaderKind::Vertex),
+                    "frag" => Some(shaderc::ShaderKind::Fragment),
+                    "comp" => Some(shaderc::ShaderKind::Compute),
+                    _ => None,
+                };
+                if let Some(kind) = kind {
                     let source = std::fs::read_to_string(&in_path)?;
-                    let kind = if ext == "vert" {
-                        shaderc::ShaderKind::Vertex
-                    } else {
-                        shaderc::ShaderKind::Fragment
-                    };
 
                     let compiled = compiler.compile_into_spirv(
                         &source,
                         kind,
                         in_path.to_str().unwrap(),
                         "main",
-                        None,
+                        Some(&options),
                     )?;
 
                     let file_name = in_path.file_name().unwrap().to_str().unwrap();
@@ -35,7 +46,13 @@ fn main() -> Result<(), Box<dyn Error>> {
 
                     std::fs::write(&out_path, compiled.as_binary_u8())?;
 
-                    let env_var_name = format!("{}_SHADER_PATH", ext.to_uppercase());
+                    // shader.vert -> VERT_SHADER_PATH, cull.comp -> CULL_COMP_SHADER_PATH
+                    let stem = in_path.file_stem().unwrap().to_str().unwrap();
+                    let env_var_name = if stem == "shader" {
+                        format!("{}_SHADER_PATH", ext.to_uppercase())
+                    } else {
+                        format!("{}_{}_SHADER_PATH", stem.to_uppercase(), ext.to_uppercase())
+                    };
                     println!("cargo:rustc-env={}={}", env_var_name, out_path.to_str().unwrap());
                 }
             }
@@ -0,0 +1,83 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::camera::CameraPose;
+
+pub const BOOKMARKS_PATH: &str = "bookmarks.toml";
+
+pub const SLOTS: usize = 9;
+
+#[derive(Debug)]
+pub struct BookmarkError {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl fmt::Display for BookmarkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
+
+impl std::error::Error for BookmarkError {}
+
+// Camera poses in numbered slots, written back to the file whenever one is saved. The file
+// holds one table per slot, named by its number.
+pub struct Bookmarks {
+    path: PathBuf,
+    poses: BTreeMap<usize, CameraPose>,
+}
+
+impl Bookmarks {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            poses: BTreeMap::new(),
+        }
+    }
+
+    // A missing file is an empty set of bookmarks.
+    pub fn load(path: &Path) -> Result<Self, BookmarkError> {
+        let error = |message: String| BookmarkError {
+            path: path.to_path_buf(),
+            message,
+        };
+
+        let mut bookmarks = Self::new(path);
+        if !path.exists() {
+            return Ok(bookmarks);
+        }
+
+        let text = std::fs::read_to_string(path).map_err(|e| error(e.to_string()))?;
+        let tables: BTreeMap<String, CameraPose> = toml::from_str(&text).map_err(|e| error(e.to_string()))?;
+        for (name, pose) in tables {
+            match name.parse::<usize>() {
+                Ok(slot) if (1..=SLOTS).contains(&slot) => {
+                    bookmarks.poses.insert(slot, pose);
+                }
+                _ => return Err(error(format!("[{}] is not a bookmark slot, expected 1 to {}", name, SLOTS))),
+            }
+        }
+        Ok(bookmarks)
+    }
+
+    pub fn get(&self, slot: usize) -> Option<CameraPose> {
+        self.poses.get(&slot).copied()
+    }
+
+    pub fn save(&mut self, slot: usize, pose: CameraPose) -> Result<(), BookmarkError> {
+        self.poses.insert(slot, pose);
+        let tables: BTreeMap<String, CameraPose> = self
+            .poses
+            .iter()
+            .map(|(slot, pose)| (slot.to_string(), *pose))
+            .collect();
+        let error = |message: String| BookmarkError {
+            path: self.path.clone(),
+            message,
+        };
+        let text = toml::to_string(&tables).map_err(|e| error(e.to_string()))?;
+        std::fs::write(&self.path, text).map_err(|e| error(e.to_string()))
+    }
+}
@@ -1,59 +1,413 @@
-use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Rad, Vector3};
-
-pub enum CameraMovement {
-    Forward,
-    Backward,
-    Left,
-    Right,
-    Up,
-    Down,
+use cgmath::{Deg, EuclideanSpace, InnerSpace, Matrix4, Point3, Rad, Vector3, VectorSpace};
+use serde::{Deserialize, Serialize};
+
+use crate::input::{Action, ActionState, Axis};
+
+const SPRINT_MULTIPLIER: f32 = 3.0;
+pub const MIN_SPEED: f32 = 0.1;
+pub const MAX_SPEED: f32 = 100.0;
+// Degrees either way; view_matrix handles looking straight up or down.
+pub const MAX_PITCH: f32 = 90.0;
+// Speed factor per line scrolled in fly mode.
+const SPEED_STEP: f32 = 1.2;
+// Below these the camera counts as settled and stops asking for frames.
+const REST_VELOCITY: f32 = 1e-3;
+const REST_LOOK: f32 = 1e-3;
+// Orbit distance used when switching from fly mode with nothing framed yet.
+const DEFAULT_ORBIT_DISTANCE: f32 = 3.5;
+const MIN_ORBIT_DISTANCE: f32 = 0.05;
+// Orbit distance or orthographic height factor per line scrolled.
+const ZOOM_STEP: f32 = 0.9;
+const MIN_ORTHO_HEIGHT: f32 = 0.01;
+// Target movement per pixel dragged, relative to the orbit distance.
+const PAN_SPEED: f32 = 0.002;
+// Room left around a framed box.
+const FRAME_MARGIN: f32 = 1.1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CameraMode {
+    // Free flight with the movement keys, looking around while the cursor is captured. The
+    // scroll wheel changes the speed.
+    Fly,
+    // Rotates around `target` while dragging, zooms with the scroll wheel.
+    Orbit,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Projection {
+    Perspective,
+    // Parallel lines stay parallel; `ortho_height` world units fit the view vertically.
+    Orthographic,
+}
+
+// Fixed directions to look at the orbit target from, in the Z-up world.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ViewPreset {
+    // Looking down along -Z.
+    Top,
+    // Looking along +Y.
+    Front,
+    // Looking along -X.
+    Side,
+    // Looking down the (-1, -1, -1) diagonal.
+    Isometric,
+}
+
+impl ViewPreset {
+    // Yaw and pitch in degrees.
+    fn orientation(self) -> (f32, f32) {
+        match self {
+            ViewPreset::Top => (90.0, -90.0),
+            ViewPreset::Front => (90.0, 0.0),
+            ViewPreset::Side => (180.0, 0.0),
+            ViewPreset::Isometric => (-135.0, -(1.0f32 / 3.0f32.sqrt()).asin().to_degrees()),
+        }
+    }
+}
+
+// What a bookmark stores: where the camera is, where it looks and how it projects.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraPose {
+    pub position: [f32; 3],
+    pub yaw: f32,
+    pub pitch: f32,
+    pub projection: Projection,
+    pub fov: f32,
+    pub ortho_height: f32,
+}
+
+impl CameraPose {
+    // Yaw turns the short way round, the projection is `to`'s throughout.
+    fn lerp(&self, to: &CameraPose, t: f32) -> CameraPose {
+        let mix = |a: f32, b: f32| a + (b - a) * t;
+        let yaw_delta = (to.yaw - self.yaw + 180.0).rem_euclid(360.0) - 180.0;
+        let from = Vector3::from(self.position);
+        CameraPose {
+            position: from.lerp(Vector3::from(to.position), t).into(),
+            yaw: self.yaw + yaw_delta * t,
+            pitch: mix(self.pitch, to.pitch),
+            projection: to.projection,
+            fov: mix(self.fov, to.fov),
+            ortho_height: mix(self.ortho_height, to.ortho_height),
+        }
+    }
+}
+
+struct Transition {
+    from: CameraPose,
+    to: CameraPose,
+    elapsed: f32,
+    duration: f32,
 }
 
 pub struct Camera {
+    pub mode: CameraMode,
     pub position: Vector3<f32>,
     pub yaw: f32,
     pub pitch: f32,
+    // Orbit center, `distance` in front of the camera.
+    pub target: Vector3<f32>,
+    pub distance: f32,
+    // Fly mode velocity, eased towards `speed` in the held direction.
+    pub velocity: Vector3<f32>,
     pub speed: f32,
+    // Rates in 1/s at which the velocity approaches the wanted one, while moving and at rest.
+    pub acceleration: f32,
+    pub damping: f32,
     pub sensitivity: f32,
+    pub invert_y: bool,
+    // Time constant in seconds over which mouse motion is spread, 0 to apply it at once.
+    pub mouse_smoothing: f32,
+    // Yaw and pitch in degrees still to be applied by the smoothing.
+    look_pending: (f32, f32),
+    // Animation towards a recalled pose, cut short by any camera input.
+    transition: Option<Transition>,
+    // Vertical field of view in degrees.
+    pub fov: f32,
+    pub projection: Projection,
+    pub ortho_height: f32,
+    pub near: f32,
+    pub far: f32,
 }
 
 impl Camera {
     pub fn new(position: Vector3<f32>, yaw: f32, pitch: f32) -> Self {
-        Self {
+        let mut camera = Self {
+            mode: CameraMode::Fly,
             position,
             yaw,
             pitch,
+            target: position,
+            distance: DEFAULT_ORBIT_DISTANCE,
+            velocity: Vector3::new(0.0, 0.0, 0.0),
             speed: 2.5,
+            acceleration: 10.0,
+            damping: 8.0,
             sensitivity: 0.1,
+            invert_y: false,
+            mouse_smoothing: 0.0,
+            look_pending: (0.0, 0.0),
+            transition: None,
+            fov: 45.0,
+            projection: Projection::Perspective,
+            ortho_height: 1.0,
+            near: 0.1,
+            far: 100.0,
+        };
+        camera.retarget();
+        camera.ortho_height = camera.target_height();
+        camera
+    }
+
+    // Both modes share position and direction, so switching keeps the view as it is.
+    pub fn set_mode(&mut self, mode: CameraMode) {
+        if mode == CameraMode::Orbit && self.mode != CameraMode::Orbit {
+            self.retarget();
+            self.velocity = Vector3::new(0.0, 0.0, 0.0);
         }
+        self.mode = mode;
+    }
+
+    // Keeps the target the same size on screen: entering orthographic fits the height visible
+    // at the target distance, leaving it moves the camera to where that height fits again.
+    pub fn set_projection(&mut self, projection: Projection) {
+        if projection == self.projection {
+            return;
+        }
+        match projection {
+            Projection::Orthographic => self.ortho_height = self.target_height(),
+            Projection::Perspective => {
+                self.retarget();
+                let half_fov = (self.fov * 0.5).to_radians();
+                self.distance = (self.ortho_height * 0.5 / half_fov.tan()).max(MIN_ORBIT_DISTANCE);
+                self.position = self.target - self.front() * self.distance;
+            }
+        }
+        self.projection = projection;
+    }
+
+    // Turns to look at the target from the preset's direction, keeping the distance.
+    pub fn set_view(&mut self, preset: ViewPreset) {
+        if self.mode == CameraMode::Fly {
+            self.retarget();
+        }
+        (self.yaw, self.pitch) = preset.orientation();
+        self.look_pending = (0.0, 0.0);
+        self.position = self.target - self.front() * self.distance;
+    }
+
+    // Applies this frame's input. `cursor_captured` enables mouse look in fly mode.
+    pub fn update(&mut self, input: &ActionState, cursor_captured: bool, dt: f32) {
+        let (dx, dy) = input.mouse_delta();
+        if self.transition.is_some() {
+            let steering = self.uses_mouse(input, cursor_captured) && (dx, dy) != (0.0, 0.0);
+            if !input.moving() && !steering && input.scroll() == 0.0 {
+                self.advance_transition(dt);
+                return;
+            }
+            self.transition = None;
+        }
+        match self.mode {
+            CameraMode::Fly => {
+                if cursor_captured {
+                    self.process_mouse(-dx, -dy);
+                }
+                self.apply_look(dt);
+                self.process_movement(input, dt);
+                self.speed = (self.speed * SPEED_STEP.powf(input.scroll())).clamp(MIN_SPEED, MAX_SPEED);
+            }
+            CameraMode::Orbit => {
+                if input.held(Action::OrbitRotate) {
+                    self.process_mouse(-dx, -dy);
+                }
+                self.apply_look(dt);
+                if input.held(Action::OrbitPan) {
+                    self.pan(dx, dy);
+                }
+                let zoom = ZOOM_STEP.powf(input.scroll());
+                match self.projection {
+                    Projection::Perspective => self.distance = (self.distance * zoom).max(MIN_ORBIT_DISTANCE),
+                    Projection::Orthographic => self.ortho_height = (self.ortho_height * zoom).max(MIN_ORTHO_HEIGHT),
+                }
+                self.position = self.target - self.front() * self.distance;
+            }
+        }
+    }
+
+    // Whether the camera is still easing to a stop or through smoothed mouse motion.
+    pub fn is_moving(&self) -> bool {
+        self.velocity != Vector3::new(0.0, 0.0, 0.0) || self.look_pending != (0.0, 0.0) || self.transition.is_some()
+    }
+
+    pub fn pose(&self) -> CameraPose {
+        CameraPose {
+            position: self.position.into(),
+            yaw: self.yaw,
+            pitch: self.pitch,
+            projection: self.projection,
+            fov: self.fov,
+            ortho_height: self.ortho_height,
+        }
+    }
+
+    // Moves to `pose` over `duration` seconds, easing in and out; at once if it is 0.
+    pub fn animate_to(&mut self, pose: CameraPose, duration: f32) {
+        // switched up front, set_projection keeps the view from jumping
+        self.set_projection(pose.projection);
+        self.transition = None;
+        if duration <= 0.0 {
+            self.set_pose(pose);
+            return;
+        }
+        self.transition = Some(Transition {
+            from: self.pose(),
+            to: pose,
+            elapsed: 0.0,
+            duration,
+        });
+    }
+
+    fn advance_transition(&mut self, dt: f32) {
+        let Some(transition) = &mut self.transition else { return };
+        transition.elapsed += dt;
+        let t = (transition.elapsed / transition.duration).min(1.0);
+        let pose = transition.from.lerp(&transition.to, t * t * (3.0 - 2.0 * t));
+        if t >= 1.0 {
+            self.transition = None;
+        }
+        self.set_pose(pose);
+    }
+
+    fn set_pose(&mut self, pose: CameraPose) {
+        self.position = pose.position.into();
+        self.yaw = pose.yaw;
+        self.pitch = pose.pitch;
+        self.projection = pose.projection;
+        self.fov = pose.fov;
+        self.ortho_height = pose.ortho_height;
+        self.velocity = Vector3::new(0.0, 0.0, 0.0);
+        self.look_pending = (0.0, 0.0);
+        self.retarget();
+    }
+
+    // Whether mouse motion currently moves the camera.
+    pub fn uses_mouse(&self, input: &ActionState, cursor_captured: bool) -> bool {
+        match self.mode {
+            CameraMode::Fly => cursor_captured,
+            CameraMode::Orbit => input.held(Action::OrbitRotate) || input.held(Action::OrbitPan),
+        }
+    }
+
+    // Moves back along the view direction until the box fits the vertical field of view, and
+    // orbits around its center from then on.
+    pub fn frame_box(&mut self, min: Vector3<f32>, max: Vector3<f32>) {
+        let radius = ((max - min).magnitude() * 0.5).max(MIN_ORBIT_DISTANCE);
+        let half_fov = (self.fov * 0.5).to_radians();
+        self.target = (min + max) * 0.5;
+        self.distance = radius / half_fov.sin() * FRAME_MARGIN;
+        self.ortho_height = 2.0 * radius * FRAME_MARGIN;
+        self.position = self.target - self.front() * self.distance;
     }
 
     pub fn view_matrix(&self) -> Matrix4<f32> {
+        // up comes from yaw, so looking straight up or down still has a defined roll
         let front = self.front();
-        Matrix4::look_at_rh(
-            Point3::from_vec(self.position),
-            Point3::from_vec(self.position + front),
-            Vector3::new(0.0, 0.0, 1.0),
-        )
+        let up = self.right().cross(front);
+        Matrix4::look_to_rh(Point3::from_vec(self.position), front, up)
     }
 
-    pub fn process_keyboard(&mut self, direction: CameraMovement, dt: f32) {
-        let velocity = self.speed * dt;
-        let front = self.front();
-        let right = front.cross(Vector3::unit_z()).normalize();
-        match direction {
-            CameraMovement::Forward => self.position += front * velocity,
-            CameraMovement::Backward => self.position -= front * velocity,
-            CameraMovement::Left => self.position -= right * velocity,
-            CameraMovement::Right => self.position += right * velocity,
-            CameraMovement::Up => self.position += Vector3::unit_z() * velocity,
-            CameraMovement::Down => self.position -= Vector3::unit_z() * velocity,
+    pub fn projection_matrix(&self, aspect: f32) -> Matrix4<f32> {
+        let mut proj = match self.projection {
+            Projection::Perspective => cgmath::perspective(Deg(self.fov), aspect, self.near, self.far),
+            Projection::Orthographic => {
+                let half_height = self.ortho_height * 0.5;
+                let half_width = half_height * aspect;
+                cgmath::ortho(-half_width, half_width, -half_height, half_height, self.near, self.far)
+            }
+        };
+        // Vulkan's clip space Y points down
+        proj[1][1] *= -1.0;
+        proj
+    }
+
+    // Adopts the pose of a camera transform whose local -Z axis is the viewing direction.
+    pub fn set_from_matrix(&mut self, transform: Matrix4<f32>) {
+        self.position = transform.w.truncate();
+        let front = -transform.z.truncate().normalize();
+        self.yaw = front.y.atan2(front.x).to_degrees();
+        self.pitch = front.z.clamp(-1.0, 1.0).asin().to_degrees();
+        self.velocity = Vector3::new(0.0, 0.0, 0.0);
+        self.look_pending = (0.0, 0.0);
+        self.retarget();
+    }
+
+    // Flies along the view direction, sideways and along world Z as the movement axes ask. The
+    // velocity approaches the wanted one exponentially and the position follows its exact
+    // integral, so the path doesn't depend on how the time is split into frames.
+    fn process_movement(&mut self, input: &ActionState, dt: f32) {
+        let mut speed = self.speed;
+        if input.held(Action::Sprint) {
+            speed *= SPRINT_MULTIPLIER;
+        }
+        let direction = self.front() * input.axis(Axis::Forward)
+            + self.right() * input.axis(Axis::Right)
+            + Vector3::unit_z() * input.axis(Axis::Up);
+        let wanted = direction * speed;
+        let rate = if input.moving() { self.acceleration } else { self.damping };
+
+        let decay = (-rate * dt).exp();
+        let offset = self.velocity - wanted;
+        self.position += wanted * dt + offset * ((1.0 - decay) / rate);
+        self.velocity = wanted + offset * decay;
+        if !input.moving() && self.velocity.magnitude() < REST_VELOCITY {
+            self.velocity = Vector3::new(0.0, 0.0, 0.0);
         }
     }
 
+    // Queues a turn for `apply_look`, in pixels with positive dx turning left and dy up.
     pub fn process_mouse(&mut self, dx: f32, dy: f32) {
-        self.yaw += dx * self.sensitivity;
-        self.pitch = (self.pitch + dy * self.sensitivity).clamp(-89.0, 89.0);
+        let dy = if self.invert_y { -dy } else { dy };
+        self.look_pending.0 += dx * self.sensitivity;
+        self.look_pending.1 += dy * self.sensitivity;
+    }
+
+    // Turns by the share of the queued motion due after `dt`, all of it without smoothing.
+    fn apply_look(&mut self, dt: f32) {
+        let share = if self.mouse_smoothing > 0.0 {
+            1.0 - (-dt / self.mouse_smoothing).exp()
+        } else {
+            1.0
+        };
+        let (yaw, pitch) = self.look_pending;
+        self.yaw += yaw * share;
+        self.pitch = (self.pitch + pitch * share).clamp(-MAX_PITCH, MAX_PITCH);
+        self.look_pending = (yaw * (1.0 - share), pitch * (1.0 - share));
+        if self.look_pending.0.abs() < REST_LOOK && self.look_pending.1.abs() < REST_LOOK {
+            self.look_pending = (0.0, 0.0);
+        }
+    }
+
+    // Target moves opposite to the drag, so the scene follows the cursor.
+    fn pan(&mut self, dx: f32, dy: f32) {
+        let right = self.right();
+        let up = right.cross(self.front());
+        let scale = match self.projection {
+            Projection::Perspective => self.distance,
+            Projection::Orthographic => self.ortho_height,
+        } * PAN_SPEED;
+        self.target += (-right * dx + up * dy) * scale;
+    }
+
+    // Height of the perspective view at the target distance.
+    fn target_height(&self) -> f32 {
+        2.0 * self.distance * (self.fov * 0.5).to_radians().tan()
+    }
+
+    // Puts the target back in front of the camera at the current distance.
+    fn retarget(&mut self) {
+        self.target = self.position + self.front() * self.distance;
     }
 
     fn front(&self) -> Vector3<f32> {
@@ -66,4 +420,164 @@ impl Camera {
         )
         .normalize()
     }
+
+    // Horizontal, so it stays defined when looking straight up or down.
+    fn right(&self) -> Vector3<f32> {
+        let yaw = self.yaw.to_radians();
+        Vector3::new(yaw.sin(), -yaw.cos(), 0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use winit::event::{ElementState, VirtualKeyCode};
+
+    use super::*;
+    use crate::input;
+
+    const DURATION: f32 = 1.0;
+
+    fn camera() -> Camera {
+        Camera::new(Vector3::new(0.0, 0.0, 0.0), 0.0, 0.0)
+    }
+
+    // Runs `frames` equal steps covering DURATION seconds.
+    fn run(camera: &mut Camera, input: &mut ActionState, cursor_captured: bool, frames: u32) {
+        let dt = DURATION / frames as f32;
+        for _ in 0..frames {
+            camera.update(input, cursor_captured, dt);
+            input.end_frame();
+        }
+    }
+
+    fn assert_close(a: Vector3<f32>, b: Vector3<f32>) {
+        assert!((a - b).magnitude() < 1e-3, "{:?} != {:?}", a, b);
+    }
+
+    // Holds W for a second, then lets go for another.
+    fn fly_forward(frames: u32, sprint: bool) -> Camera {
+        let mut camera = camera();
+        let mut input = ActionState::new(input::default_bindings());
+        input.handle_key(VirtualKeyCode::W, ElementState::Pressed);
+        if sprint {
+            input.handle_key(VirtualKeyCode::LShift, ElementState::Pressed);
+        }
+        run(&mut camera, &mut input, false, frames);
+        input.release_all();
+        run(&mut camera, &mut input, false, frames);
+        camera
+    }
+
+    #[test]
+    fn movement_does_not_depend_on_frame_rate() {
+        let slow = fly_forward(20, false);
+        let fast = fly_forward(240, false);
+        assert_close(slow.position, fast.position);
+        assert_close(slow.velocity, fast.velocity);
+    }
+
+    #[test]
+    fn movement_eases_in_and_out() {
+        let mut camera = camera();
+        let mut input = ActionState::new(input::default_bindings());
+        input.handle_key(VirtualKeyCode::W, ElementState::Pressed);
+        camera.update(&input, false, 0.05);
+        assert!(camera.velocity.x > 0.0 && camera.velocity.x < camera.speed);
+
+        run(&mut camera, &mut input, false, 60);
+        assert!((camera.velocity.x - camera.speed).abs() < 1e-3);
+
+        // keeps gliding after the key is released, then settles
+        input.release_all();
+        let released_at = camera.position;
+        run(&mut camera, &mut input, false, 60);
+        assert!(camera.position.x > released_at.x);
+        run(&mut camera, &mut input, false, 60);
+        assert!(!camera.is_moving());
+    }
+
+    #[test]
+    fn sprint_multiplies_speed() {
+        let walk = fly_forward(60, false);
+        let sprint = fly_forward(60, true);
+        assert!((sprint.position.x - walk.position.x * SPRINT_MULTIPLIER).abs() < 1e-3);
+    }
+
+    #[test]
+    fn scroll_changes_speed() {
+        let mut camera = camera();
+        let mut input = ActionState::new(input::default_bindings());
+        input.handle_scroll(winit::event::MouseScrollDelta::LineDelta(0.0, 2.0));
+        camera.update(&input, false, 0.0);
+        assert!((camera.speed - 2.5 * SPEED_STEP * SPEED_STEP).abs() < 1e-4);
+    }
+
+    // Moves the mouse once, then lets the smoothing play out for a second.
+    fn look(frames: u32, smoothing: f32, invert_y: bool) -> Camera {
+        let mut camera = camera();
+        camera.mouse_smoothing = smoothing;
+        camera.invert_y = invert_y;
+        let mut input = ActionState::new(input::default_bindings());
+        input.handle_mouse_motion(-100.0, -50.0);
+        run(&mut camera, &mut input, true, frames);
+        camera
+    }
+
+    #[test]
+    fn mouse_smoothing_does_not_depend_on_frame_rate() {
+        let slow = look(10, 0.5, false);
+        let fast = look(300, 0.5, false);
+        assert!((slow.yaw - fast.yaw).abs() < 1e-3);
+        assert!((slow.pitch - fast.pitch).abs() < 1e-3);
+        // still catching up with the motion
+        assert!(slow.yaw > 0.0 && slow.yaw < 10.0);
+    }
+
+    #[test]
+    fn unsmoothed_mouse_applies_at_once() {
+        let camera = look(1, 0.0, false);
+        assert!((camera.yaw - 10.0).abs() < 1e-4);
+        assert!((camera.pitch - 5.0).abs() < 1e-4);
+        assert!(!camera.is_moving());
+    }
+
+    #[test]
+    fn invert_y_flips_pitch() {
+        let camera = look(1, 0.0, true);
+        assert!((camera.yaw - 10.0).abs() < 1e-4);
+        assert!((camera.pitch + 5.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn transition_turns_the_short_way() {
+        let mut camera = Camera::new(Vector3::new(0.0, 0.0, 0.0), 170.0, 0.0);
+        let mut to = camera.pose();
+        to.position = [4.0, 0.0, 0.0];
+        to.yaw = -170.0;
+        camera.animate_to(to, DURATION);
+
+        let mut input = ActionState::new(input::default_bindings());
+        camera.update(&input, false, DURATION * 0.5);
+        assert_close(camera.position, Vector3::new(2.0, 0.0, 0.0));
+        assert!((camera.yaw - 180.0).abs() < 1e-3);
+
+        run(&mut camera, &mut input, false, 10);
+        assert_close(camera.position, Vector3::new(4.0, 0.0, 0.0));
+        assert!((camera.yaw - 190.0).abs() < 1e-3);
+        assert!(!camera.is_moving());
+    }
+
+    #[test]
+    fn movement_interrupts_transition() {
+        let mut camera = camera();
+        let mut to = camera.pose();
+        to.position = [0.0, 4.0, 0.0];
+        camera.animate_to(to, DURATION);
+
+        let mut input = ActionState::new(input::default_bindings());
+        input.handle_key(VirtualKeyCode::W, ElementState::Pressed);
+        run(&mut camera, &mut input, false, 10);
+        assert!(camera.position.y.abs() < 1e-4);
+        assert!(camera.position.x > 0.0);
+    }
 }
@@ -0,0 +1,103 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use crate::settings::Settings;
+use crate::vulkan_app::PresentMode;
+
+// Command-line options. Anything given here takes precedence over the settings file.
+#[derive(Parser, Debug)]
+#[command(about = "Vulkan model viewer")]
+pub struct Cli {
+    /// Model or scene to open (.obj, .gltf or .glb)
+    pub model: Option<PathBuf>,
+
+    /// Equirectangular HDR image or cubemap directory to use as the environment
+    pub environment: Option<PathBuf>,
+
+    /// Settings file, created with the defaults if it does not exist
+    #[arg(long, value_name = "PATH", default_value = crate::settings::SETTINGS_PATH)]
+    pub settings: PathBuf,
+
+    /// Window width in logical pixels
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub width: Option<u32>,
+
+    /// Window height in logical pixels
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub height: Option<u32>,
+
+    /// GPU to render on, by index or by part of its name
+    #[arg(long, value_name = "INDEX|NAME")]
+    pub gpu: Option<String>,
+
+    /// Enable the Khronos validation layer
+    #[arg(long, overrides_with = "no_validation")]
+    pub validation: bool,
+
+    /// Disable the Khronos validation layer
+    #[arg(long, overrides_with = "validation")]
+    pub no_validation: bool,
+
+    /// Swapchain present mode
+    #[arg(long, value_enum)]
+    pub present_mode: Option<PresentMode>,
+
+    /// Render this many frames with the window hidden, then exit
+    #[arg(long, value_name = "COUNT", value_parser = clap::value_parser!(u32).range(1..))]
+    pub frames: Option<u32>,
+
+    /// Save the last frame as a PNG and exit; renders a single hidden frame unless --frames is given
+    #[arg(long, value_name = "PATH")]
+    pub screenshot: Option<PathBuf>,
+
+    /// Initial camera position
+    #[arg(long, value_name = "X,Y,Z", value_parser = parse_vector, allow_hyphen_values = true)]
+    pub camera_position: Option<[f32; 3]>,
+
+    /// Initial camera yaw in degrees, 0 looking along +X
+    #[arg(long, value_name = "DEGREES", allow_negative_numbers = true)]
+    pub camera_yaw: Option<f32>,
+
+    /// Initial camera pitch in degrees, positive looking up
+    #[arg(long, value_name = "DEGREES", allow_negative_numbers = true)]
+    pub camera_pitch: Option<f32>,
+}
+
+fn parse_vector(value: &str) -> Result<[f32; 3], String> {
+    let components = value
+        .split(',')
+        .map(|component| component.trim().parse::<f32>().map_err(|error| error.to_string()))
+        .collect::<Result<Vec<_>, _>>()?;
+    components
+        .try_into()
+        .map_err(|_| "expected three comma-separated numbers".to_string())
+}
+
+impl Cli {
+    // Frames to render before exiting, None to run until the window is closed.
+    pub fn frame_limit(&self) -> Option<u32> {
+        self.frames.or(self.screenshot.as_ref().map(|_| 1))
+    }
+
+    pub fn apply(&self, settings: &mut Settings) {
+        if let Some(width) = self.width {
+            settings.window.width = width;
+        }
+        if let Some(height) = self.height {
+            settings.window.height = height;
+        }
+        if let Some(gpu) = &self.gpu {
+            settings.renderer.gpu = Some(gpu.clone());
+        }
+        if self.validation {
+            settings.renderer.validation = true;
+        }
+        if self.no_validation {
+            settings.renderer.validation = false;
+        }
+        if let Some(present_mode) = self.present_mode {
+            settings.renderer.present_mode = present_mode;
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use std::time::{Duration, Instant};
+
+use winit::event_loop::ControlFlow;
+
+// Decides when the event loop draws: at most `target_fps` frames a second, and in idle mode
+// only when something changed since the last frame.
+pub struct FramePacing {
+    // None draws as fast as the present mode allows.
+    pub target_fps: Option<u32>,
+    pub idle: bool,
+    last_frame: Instant,
+    redraw_requested: bool,
+    // Set while idle skipped frames, so the next frame does not advance by the whole idle time.
+    resumed: bool,
+}
+
+impl FramePacing {
+    pub fn new() -> Self {
+        Self {
+            target_fps: None,
+            idle: false,
+            last_frame: Instant::now(),
+            redraw_requested: true,
+            resumed: false,
+        }
+    }
+
+    // Input arrived or something else changed the picture.
+    pub fn request_redraw(&mut self) {
+        self.redraw_requested = true;
+    }
+
+    // Returns the time step of the frame to draw now, or None with `control_flow` set to wake the
+    // loop again when one is due. `animating` keeps frames coming in idle mode.
+    pub fn begin_frame(&mut self, animating: bool, control_flow: &mut ControlFlow) -> Option<f32> {
+        if self.idle && !animating && !self.redraw_requested {
+            self.resumed = true;
+            *control_flow = ControlFlow::Wait;
+            return None;
+        }
+
+        let now = Instant::now();
+        if let Some(interval) = self.frame_interval() {
+            let next_frame = self.last_frame + interval;
+            if now < next_frame {
+                *control_flow = ControlFlow::WaitUntil(next_frame);
+                return None;
+            }
+        }
+
+        let dt = if std::mem::take(&mut self.resumed) {
+            0.0
+        } else {
+            now.duration_since(self.last_frame).as_secs_f32()
+        };
+        self.last_frame = now;
+        self.redraw_requested = false;
+        *control_flow = ControlFlow::Poll;
+        Some(dt)
+    }
+
+    fn frame_interval(&self) -> Option<Duration> {
+        self.target_fps
+            .filter(|&fps| fps > 0)
+            .map(|fps| Duration::from_secs_f64(1.0 / fps as f64))
+    }
+}
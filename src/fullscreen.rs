@@ -0,0 +1,39 @@
+use winit::monitor::{MonitorHandle, VideoMode};
+use winit::window::{Fullscreen, Window};
+
+use crate::settings::{FullscreenMode, VideoModeSettings, WindowSettings};
+
+// Fullscreen on the monitor the window is on. Exclusive mode falls back to borderless when the
+// monitor has no matching video mode.
+pub fn fullscreen(window: &Window, settings: &WindowSettings) -> Fullscreen {
+    if settings.fullscreen_mode == FullscreenMode::Exclusive {
+        let monitor = window.current_monitor().or_else(|| window.primary_monitor());
+        match monitor.and_then(|monitor| choose_video_mode(&monitor, settings.video_mode.as_ref())) {
+            Some(video_mode) => return Fullscreen::Exclusive(video_mode),
+            None => eprintln!("No matching video mode for exclusive fullscreen, using borderless"),
+        }
+    }
+    Fullscreen::Borderless(None)
+}
+
+// The requested size at the refresh rate closest to the requested one, or the highest. Without a
+// request, the largest mode at its highest refresh rate.
+fn choose_video_mode(monitor: &MonitorHandle, requested: Option<&VideoModeSettings>) -> Option<VideoMode> {
+    let Some(requested) = requested else {
+        return monitor.video_modes().max_by_key(|mode| {
+            let size = mode.size();
+            (size.width * size.height, mode.refresh_rate_millihertz(), mode.bit_depth())
+        });
+    };
+    monitor
+        .video_modes()
+        .filter(|mode| mode.size().width == requested.width && mode.size().height == requested.height)
+        .max_by_key(|mode| {
+            let refresh = mode.refresh_rate_millihertz() as i64;
+            let preference = match requested.refresh_rate {
+                Some(hz) => -(refresh - hz as i64 * 1000).abs(),
+                None => refresh,
+            };
+            (preference, mode.bit_depth())
+        })
+}
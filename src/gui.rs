@@ -0,0 +1,205 @@
+use winit::event::WindowEvent;
+use winit::event_loop::EventLoop;
+use winit::window::Window;
+
+use crate::camera::{Camera, CameraMode, Projection, MAX_SPEED, MIN_SPEED};
+use crate::frame_pacing::FramePacing;
+use crate::vulkan_app::{GuiFrame, RenderMode, VulkanApp};
+
+pub struct Gui {
+    context: egui::Context,
+    state: egui_winit::State,
+    pub visible: bool,
+    // egui is animating something and wants the next frame right away.
+    repaint: bool,
+}
+
+impl Gui {
+    pub fn new(event_loop: &EventLoop<()>, window: &Window) -> Self {
+        let mut state = egui_winit::State::new(event_loop);
+        state.set_pixels_per_point(window.scale_factor() as f32);
+        Self {
+            context: egui::Context::default(),
+            state,
+            visible: false,
+            repaint: false,
+        }
+    }
+
+    // Returns true when egui used the event, in which case it must not reach the camera.
+    pub fn handle_event(&mut self, event: &WindowEvent) -> bool {
+        if !self.visible {
+            return false;
+        }
+        self.state.on_event(&self.context, event).consumed
+    }
+
+    pub fn needs_repaint(&self) -> bool {
+        self.visible && self.repaint
+    }
+
+    // Runs the debug window for this frame and returns what there is to draw.
+    pub fn frame(
+        &mut self,
+        window: &Window,
+        camera: &mut Camera,
+        app: &mut VulkanApp,
+        pacing: &mut FramePacing,
+    ) -> GuiFrame {
+        let input = self.state.take_egui_input(window);
+        let output = self.context.run(input, |ctx| debug_window(ctx, camera, app, pacing));
+        self.repaint = output.repaint_after.is_zero();
+        self.state
+            .handle_platform_output(window, &self.context, output.platform_output);
+        GuiFrame {
+            primitives: self.context.tessellate(output.shapes),
+            textures_delta: output.textures_delta,
+            pixels_per_point: self.context.pixels_per_point(),
+        }
+    }
+}
+
+fn debug_window(ctx: &egui::Context, camera: &mut Camera, app: &mut VulkanApp, pacing: &mut FramePacing) {
+    egui::Window::new("Debug")
+        .anchor(egui::Align2::RIGHT_TOP, [-8.0, 8.0])
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.heading("Camera");
+            let mut camera_mode = camera.mode;
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut camera_mode, CameraMode::Fly, "Fly");
+                ui.radio_value(&mut camera_mode, CameraMode::Orbit, "Orbit");
+            });
+            if camera_mode != camera.mode {
+                camera.set_mode(camera_mode);
+            }
+            let mut projection = camera.projection;
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut projection, Projection::Perspective, "Perspective");
+                ui.radio_value(&mut projection, Projection::Orthographic, "Orthographic");
+            });
+            if projection != camera.projection {
+                camera.set_projection(projection);
+            }
+            match camera.projection {
+                Projection::Perspective => {
+                    ui.add(egui::Slider::new(&mut camera.fov, 5.0..=120.0).text("Field of view"));
+                }
+                Projection::Orthographic => {
+                    ui.add(
+                        egui::Slider::new(&mut camera.ortho_height, 0.01..=100.0)
+                            .logarithmic(true)
+                            .text("View height"),
+                    );
+                }
+            }
+            ui.add(
+                egui::Slider::new(&mut camera.speed, MIN_SPEED..=MAX_SPEED)
+                    .logarithmic(true)
+                    .text("Speed"),
+            );
+            ui.add(egui::Slider::new(&mut camera.acceleration, 1.0..=50.0).text("Acceleration"));
+            ui.add(egui::Slider::new(&mut camera.damping, 1.0..=50.0).text("Damping"));
+            ui.add(egui::Slider::new(&mut camera.sensitivity, 0.01..=1.0).text("Sensitivity"));
+            ui.add(egui::Slider::new(&mut camera.mouse_smoothing, 0.0..=0.2).text("Mouse smoothing"));
+            ui.checkbox(&mut camera.invert_y, "Invert Y");
+
+            ui.separator();
+            ui.heading("Rendering");
+            let mut mode = app.render_mode();
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut mode, RenderMode::Shaded, "Shaded");
+                ui.radio_value(&mut mode, RenderMode::Wireframe, "Wireframe");
+            });
+            if mode != app.render_mode() {
+                app.set_render_mode(mode);
+            }
+            let mut gpu_culling = app.gpu_culling_enabled();
+            if ui.checkbox(&mut gpu_culling, "GPU culling").changed() {
+                app.set_gpu_culling(gpu_culling);
+            }
+            let mut occlusion_culling = app.occlusion_culling_enabled();
+            if ui.checkbox(&mut occlusion_culling, "Occlusion culling").changed() {
+                app.set_occlusion_culling(occlusion_culling);
+            }
+            let mut overlay = app.overlay_enabled();
+            if ui.checkbox(&mut overlay, "Stats overlay").changed() {
+                app.toggle_overlay();
+            }
+            let mut divisions = app.grid_divisions();
+            if ui
+                .add(egui::Slider::new(&mut divisions, 1..=64).text("Grid divisions"))
+                .changed()
+            {
+                app.set_grid_divisions(divisions);
+            }
+
+            ui.separator();
+            ui.heading("Frame rate");
+            let mut present_mode = app.present_mode();
+            egui::ComboBox::from_label("Present mode")
+                .selected_text(present_mode.name())
+                .show_ui(ui, |ui| {
+                    for &mode in app.supported_present_modes() {
+                        ui.selectable_value(&mut present_mode, mode, mode.name());
+                    }
+                });
+            if present_mode != app.present_mode() {
+                app.set_present_mode(present_mode);
+            }
+            let mut limited = pacing.target_fps.is_some();
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut limited, "Frame limit");
+                let mut fps = pacing.target_fps.unwrap_or(60);
+                ui.add_enabled(limited, egui::Slider::new(&mut fps, 10..=240).suffix(" FPS"));
+                pacing.target_fps = limited.then_some(fps);
+            });
+            ui.checkbox(&mut pacing.idle, "Only redraw on changes");
+
+            ui.separator();
+            ui.heading("Output");
+            let mut output_mode = app.output_mode();
+            egui::ComboBox::from_label("Color space")
+                .selected_text(output_mode.name())
+                .show_ui(ui, |ui| {
+                    for &mode in app.supported_output_modes() {
+                        ui.selectable_value(&mut output_mode, mode, mode.name());
+                    }
+                });
+            if output_mode != app.output_mode() {
+                app.set_output_mode(output_mode);
+            }
+            if app.output_encoding().is_hdr() {
+                let hdr = app.hdr_settings_mut();
+                ui.add(egui::Slider::new(&mut hdr.paper_white_nits, 80.0..=500.0).text("Paper white (nits)"));
+                ui.add(egui::Slider::new(&mut hdr.peak_nits, 400.0..=4000.0).text("Peak (nits)"));
+                hdr.peak_nits = hdr.peak_nits.max(hdr.paper_white_nits);
+            }
+
+            ui.separator();
+            ui.heading("Lighting");
+            if app.environment_loaded() {
+                let lighting = app.lighting_mut();
+                ui.add(egui::Slider::new(&mut lighting.intensity, 0.0..=4.0).text("Intensity"));
+                ui.add(egui::Slider::new(&mut lighting.roughness, 0.0..=1.0).text("Roughness"));
+                ui.add(egui::Slider::new(&mut lighting.metallic, 0.0..=1.0).text("Metallic"));
+            } else {
+                ui.label("No environment loaded, start with --environment to light the scene");
+            }
+
+            if app.emitter_count() > 0 {
+                ui.separator();
+                ui.heading("Particles");
+            }
+            // the rate is fixed, an emitter's share of the particle buffer is sized from it
+            for index in 0..app.emitter_count() {
+                egui::CollapsingHeader::new(format!("Emitter {}", index + 1)).show(ui, |ui| {
+                    let emitter = app.emitter_mut(index);
+                    ui.add(egui::Slider::new(&mut emitter.speed, 0.0..=20.0).text("Speed"));
+                    ui.add(egui::Slider::new(&mut emitter.cone_angle.0, 0.0..=180.0).text("Cone angle"));
+                    ui.add(egui::Slider::new(&mut emitter.gravity.z, -20.0..=20.0).text("Gravity"));
+                    ui.add(egui::Slider::new(&mut emitter.size, 0.01..=0.5).text("Size"));
+                });
+            }
+        });
+}
@@ -0,0 +1,568 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use winit::event::{ElementState, ModifiersState, MouseButton, MouseScrollDelta, VirtualKeyCode};
+
+// Touchpads scroll in pixels, wheels in lines.
+const PIXELS_PER_SCROLL_LINE: f32 = 40.0;
+
+// Everything the viewer reacts to, named as in the [input] table of the settings file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    Sprint,
+    // Orbit mode drags.
+    OrbitRotate,
+    OrbitPan,
+    ToggleCameraMode,
+    ToggleProjection,
+    FrameScene,
+    ViewTop,
+    ViewFront,
+    ViewSide,
+    ViewIsometric,
+    CaptureCursor,
+    ReleaseCursor,
+    ToggleFullscreen,
+    ToggleGui,
+    ToggleOverlay,
+    ToggleGpuCulling,
+    ToggleWireframe,
+    Screenshot,
+    // Fly to the pose stored in a numbered bookmark.
+    RecallBookmark1,
+    RecallBookmark2,
+    RecallBookmark3,
+    RecallBookmark4,
+    RecallBookmark5,
+    RecallBookmark6,
+    RecallBookmark7,
+    RecallBookmark8,
+    RecallBookmark9,
+    // Store the current pose in a numbered bookmark.
+    SaveBookmark1,
+    SaveBookmark2,
+    SaveBookmark3,
+    SaveBookmark4,
+    SaveBookmark5,
+    SaveBookmark6,
+    SaveBookmark7,
+    SaveBookmark8,
+    SaveBookmark9,
+}
+
+// Bookmark actions by slot, slot 1 first.
+pub const RECALL_BOOKMARK: [Action; 9] = [
+    Action::RecallBookmark1,
+    Action::RecallBookmark2,
+    Action::RecallBookmark3,
+    Action::RecallBookmark4,
+    Action::RecallBookmark5,
+    Action::RecallBookmark6,
+    Action::RecallBookmark7,
+    Action::RecallBookmark8,
+    Action::RecallBookmark9,
+];
+pub const SAVE_BOOKMARK: [Action; 9] = [
+    Action::SaveBookmark1,
+    Action::SaveBookmark2,
+    Action::SaveBookmark3,
+    Action::SaveBookmark4,
+    Action::SaveBookmark5,
+    Action::SaveBookmark6,
+    Action::SaveBookmark7,
+    Action::SaveBookmark8,
+    Action::SaveBookmark9,
+];
+
+// Two opposing actions read as one value in [-1, 1].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Axis {
+    Forward,
+    Right,
+    Up,
+}
+
+impl Axis {
+    pub const ALL: [Axis; 3] = [Axis::Forward, Axis::Right, Axis::Up];
+
+    // Positive and negative action.
+    fn actions(self) -> (Action, Action) {
+        match self {
+            Axis::Forward => (Action::MoveForward, Action::MoveBackward),
+            Axis::Right => (Action::MoveRight, Action::MoveLeft),
+            Axis::Up => (Action::MoveUp, Action::MoveDown),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Input {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Modifiers {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+}
+
+impl Modifiers {
+    fn from_state(state: ModifiersState) -> Self {
+        Self {
+            ctrl: state.ctrl(),
+            shift: state.shift(),
+            alt: state.alt(),
+        }
+    }
+
+    // Whether every modifier of self is held in `held`; extra ones don't matter.
+    fn satisfied_by(self, held: Modifiers) -> bool {
+        (!self.ctrl || held.ctrl) && (!self.shift || held.shift) && (!self.alt || held.alt)
+    }
+
+    fn count(self) -> u32 {
+        self.ctrl as u32 + self.shift as u32 + self.alt as u32
+    }
+}
+
+// A key or mouse button with the modifiers that must be held with it, written like "Ctrl+S"
+// or "MouseRight" in the settings file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Binding {
+    pub input: Input,
+    pub modifiers: Modifiers,
+}
+
+impl Binding {
+    fn key(key: VirtualKeyCode) -> Self {
+        Self {
+            input: Input::Key(key),
+            modifiers: Modifiers::default(),
+        }
+    }
+
+    fn shift_key(key: VirtualKeyCode) -> Self {
+        Self {
+            input: Input::Key(key),
+            modifiers: Modifiers {
+                shift: true,
+                ..Modifiers::default()
+            },
+        }
+    }
+
+    fn mouse(button: MouseButton) -> Self {
+        Self {
+            input: Input::Mouse(button),
+            modifiers: Modifiers::default(),
+        }
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (held, name) in [(self.modifiers.ctrl, "Ctrl"), (self.modifiers.shift, "Shift"), (self.modifiers.alt, "Alt")] {
+            if held {
+                write!(f, "{}+", name)?;
+            }
+        }
+        match self.input {
+            Input::Key(key) => write!(f, "{:?}", key),
+            Input::Mouse(MouseButton::Left) => write!(f, "MouseLeft"),
+            Input::Mouse(MouseButton::Right) => write!(f, "MouseRight"),
+            Input::Mouse(MouseButton::Middle) => write!(f, "MouseMiddle"),
+            Input::Mouse(MouseButton::Other(button)) => write!(f, "Mouse{}", button),
+        }
+    }
+}
+
+impl TryFrom<String> for Binding {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, String> {
+        let mut parts: Vec<&str> = text.split('+').map(str::trim).collect();
+        let name = parts.pop().filter(|name| !name.is_empty()).ok_or_else(|| format!("empty binding '{}'", text))?;
+
+        let mut modifiers = Modifiers::default();
+        for part in parts {
+            match part.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => modifiers.ctrl = true,
+                "shift" => modifiers.shift = true,
+                "alt" => modifiers.alt = true,
+                _ => return Err(format!("unknown modifier '{}' in '{}', expected Ctrl, Shift or Alt", part, text)),
+            }
+        }
+
+        let input = match name {
+            "MouseLeft" => Input::Mouse(MouseButton::Left),
+            "MouseRight" => Input::Mouse(MouseButton::Right),
+            "MouseMiddle" => Input::Mouse(MouseButton::Middle),
+            _ => match name.strip_prefix("Mouse").and_then(|button| button.parse().ok()) {
+                Some(button) => Input::Mouse(MouseButton::Other(button)),
+                None => Input::Key(parse_key(name).ok_or_else(|| format!("unknown key '{}' in '{}'", name, text))?),
+            },
+        };
+        Ok(Self { input, modifiers })
+    }
+}
+
+impl From<Binding> for String {
+    fn from(binding: Binding) -> Self {
+        binding.to_string()
+    }
+}
+
+macro_rules! key_names {
+    ($($key:ident),* $(,)?) => {
+        const KEY_NAMES: &[(&str, VirtualKeyCode)] = &[$((stringify!($key), VirtualKeyCode::$key)),*];
+    };
+}
+
+// The names are the VirtualKeyCode variants, so they match the Debug output used for writing.
+key_names![
+    Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Key0,
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    Escape, F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    Snapshot, Scroll, Pause, Insert, Home, Delete, End, PageDown, PageUp,
+    Left, Up, Right, Down, Back, Return, Space, Tab,
+    Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+    NumpadAdd, NumpadSubtract, NumpadMultiply, NumpadDivide, NumpadDecimal, NumpadEnter,
+    Apostrophe, Backslash, Comma, Equals, Grave, LBracket, Minus, Period, RBracket, Semicolon, Slash,
+    LAlt, LControl, LShift, RAlt, RControl, RShift,
+];
+
+fn parse_key(name: &str) -> Option<VirtualKeyCode> {
+    KEY_NAMES
+        .iter()
+        .find(|(key_name, _)| key_name.eq_ignore_ascii_case(name))
+        .map(|&(_, key)| key)
+}
+
+pub type Bindings = BTreeMap<Action, Vec<Binding>>;
+
+pub fn default_bindings() -> Bindings {
+    use VirtualKeyCode as Key;
+    let mut bindings = BTreeMap::from([
+        (Action::MoveForward, vec![Binding::key(Key::W)]),
+        (Action::MoveBackward, vec![Binding::key(Key::S)]),
+        (Action::MoveLeft, vec![Binding::key(Key::A)]),
+        (Action::MoveRight, vec![Binding::key(Key::D)]),
+        (Action::MoveUp, vec![Binding::key(Key::Space), Binding::key(Key::E)]),
+        (Action::MoveDown, vec![Binding::key(Key::LControl), Binding::key(Key::Q)]),
+        (Action::Sprint, vec![Binding::key(Key::LShift)]),
+        (Action::OrbitRotate, vec![Binding::mouse(MouseButton::Left)]),
+        (Action::OrbitPan, vec![Binding::mouse(MouseButton::Middle)]),
+        (Action::ToggleCameraMode, vec![Binding::key(Key::C)]),
+        (Action::ToggleProjection, vec![Binding::key(Key::Numpad5)]),
+        (Action::FrameScene, vec![Binding::key(Key::F)]),
+        (Action::ViewTop, vec![Binding::key(Key::Numpad7)]),
+        (Action::ViewFront, vec![Binding::key(Key::Numpad1)]),
+        (Action::ViewSide, vec![Binding::key(Key::Numpad3)]),
+        (Action::ViewIsometric, vec![Binding::key(Key::Numpad0)]),
+        (Action::CaptureCursor, vec![Binding::mouse(MouseButton::Right)]),
+        (Action::ReleaseCursor, vec![Binding::key(Key::Escape)]),
+        (Action::ToggleFullscreen, vec![Binding::key(Key::F11)]),
+        (Action::ToggleGui, vec![Binding::key(Key::F1)]),
+        (Action::ToggleWireframe, vec![Binding::key(Key::F2)]),
+        (Action::ToggleOverlay, vec![Binding::key(Key::F3)]),
+        (Action::ToggleGpuCulling, vec![Binding::key(Key::G)]),
+        (Action::Screenshot, vec![Binding::key(Key::F12)]),
+    ]);
+    // 1-9 recall, Shift+1-9 save; Ctrl would also fly down
+    let digits = [
+        Key::Key1, Key::Key2, Key::Key3, Key::Key4, Key::Key5, Key::Key6, Key::Key7, Key::Key8, Key::Key9,
+    ];
+    for ((recall, save), key) in RECALL_BOOKMARK.into_iter().zip(SAVE_BOOKMARK).zip(digits) {
+        bindings.insert(recall, vec![Binding::key(key)]);
+        bindings.insert(save, vec![Binding::shift_key(key)]);
+    }
+    bindings
+}
+
+// Actions missing from `configured` keep their default bindings; an empty list unbinds one.
+pub fn merge_with_defaults(configured: &Bindings) -> Bindings {
+    let mut bindings = default_bindings();
+    bindings.extend(configured.iter().map(|(&action, list)| (action, list.clone())));
+    bindings
+}
+
+// One message per binding that more than one action uses.
+pub fn find_conflicts(bindings: &Bindings) -> Vec<String> {
+    let mut users: Vec<(Binding, Vec<Action>)> = Vec::new();
+    for (&action, list) in bindings {
+        for &binding in list {
+            match users.iter_mut().find(|(used, _)| *used == binding) {
+                Some((_, actions)) if !actions.contains(&action) => actions.push(action),
+                Some(_) => {}
+                None => users.push((binding, vec![action])),
+            }
+        }
+    }
+    users
+        .into_iter()
+        .filter(|(_, actions)| actions.len() > 1)
+        .map(|(binding, actions)| {
+            let names: Vec<String> = actions.iter().map(|action| action_name(*action)).collect();
+            format!("input: {} is bound to {}", binding, names.join(" and "))
+        })
+        .collect()
+}
+
+fn action_name(action: Action) -> String {
+    // the same snake_case name as in the file
+    toml::Value::try_from(action)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_else(|| format!("{:?}", action))
+}
+
+// Turns window events into action state. `pressed` and `released` report edges since the last
+// `end_frame`, so taps shorter than a frame are not lost; `held` is the current state. Mouse
+// motion and scrolling add up over the same span.
+pub struct ActionState {
+    bindings: Bindings,
+    down: HashSet<Input>,
+    modifiers: Modifiers,
+    pressed: HashSet<Action>,
+    released: HashSet<Action>,
+    mouse_delta: (f32, f32),
+    scroll: f32,
+}
+
+impl ActionState {
+    pub fn new(bindings: Bindings) -> Self {
+        Self {
+            bindings,
+            down: HashSet::new(),
+            modifiers: Modifiers::default(),
+            pressed: HashSet::new(),
+            released: HashSet::new(),
+            mouse_delta: (0.0, 0.0),
+            scroll: 0.0,
+        }
+    }
+
+    // Modifier changes alone can start or end actions, e.g. releasing Ctrl of Ctrl+W.
+    pub fn set_modifiers(&mut self, state: ModifiersState) {
+        let before = self.held_actions();
+        self.modifiers = Modifiers::from_state(state);
+        self.record_edges(&before);
+    }
+
+    pub fn handle_key(&mut self, key: VirtualKeyCode, state: ElementState) {
+        self.handle_input(Input::Key(key), state);
+    }
+
+    pub fn handle_mouse_button(&mut self, button: MouseButton, state: ElementState) {
+        self.handle_input(Input::Mouse(button), state);
+    }
+
+    // Raw pointer motion in pixels, right and down positive.
+    pub fn handle_mouse_motion(&mut self, dx: f64, dy: f64) {
+        self.mouse_delta.0 += dx as f32;
+        self.mouse_delta.1 += dy as f32;
+    }
+
+    pub fn handle_scroll(&mut self, delta: MouseScrollDelta) {
+        self.scroll += match delta {
+            MouseScrollDelta::LineDelta(_, lines) => lines,
+            MouseScrollDelta::PixelDelta(position) => position.y as f32 / PIXELS_PER_SCROLL_LINE,
+        };
+    }
+
+    fn handle_input(&mut self, input: Input, state: ElementState) {
+        let before = self.held_actions();
+        match state {
+            ElementState::Pressed => {
+                // key repeat doesn't press again
+                if !self.down.insert(input) {
+                    return;
+                }
+            }
+            ElementState::Released => {
+                self.down.remove(&input);
+            }
+        }
+        self.record_edges(&before);
+    }
+
+    fn record_edges(&mut self, before: &HashSet<Action>) {
+        let after = self.held_actions();
+        self.pressed.extend(after.difference(before));
+        self.released.extend(before.difference(&after));
+    }
+
+    // Releases everything, e.g. when the window loses focus and the releases would go elsewhere.
+    pub fn release_all(&mut self) {
+        let before = self.held_actions();
+        self.down.clear();
+        self.released.extend(before);
+    }
+
+    pub fn held(&self, action: Action) -> bool {
+        self.bindings
+            .get(&action)
+            .is_some_and(|list| list.iter().any(|binding| self.is_active(binding)))
+    }
+
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn released(&self, action: Action) -> bool {
+        self.released.contains(&action)
+    }
+
+    pub fn mouse_delta(&self) -> (f32, f32) {
+        self.mouse_delta
+    }
+
+    // Lines scrolled, positive away from the user.
+    pub fn scroll(&self) -> f32 {
+        self.scroll
+    }
+
+    pub fn axis(&self, axis: Axis) -> f32 {
+        let (positive, negative) = axis.actions();
+        self.held(positive) as i32 as f32 - self.held(negative) as i32 as f32
+    }
+
+    // Whether any movement action is held.
+    pub fn moving(&self) -> bool {
+        Axis::ALL.iter().any(|&axis| {
+            let (positive, negative) = axis.actions();
+            self.held(positive) || self.held(negative)
+        })
+    }
+
+    pub fn end_frame(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+        self.mouse_delta = (0.0, 0.0);
+        self.scroll = 0.0;
+    }
+
+    // A binding is active while its input is down with its modifiers, unless another binding
+    // on the same input asks for more modifiers that are also held, so Ctrl+W shadows W.
+    fn is_active(&self, binding: &Binding) -> bool {
+        if !self.down.contains(&binding.input) || !binding.modifiers.satisfied_by(self.modifiers) {
+            return false;
+        }
+        !self.bindings.values().flatten().any(|other| {
+            other.input == binding.input
+                && other.modifiers.count() > binding.modifiers.count()
+                && other.modifiers.satisfied_by(self.modifiers)
+        })
+    }
+
+    fn held_actions(&self) -> HashSet<Action> {
+        self.bindings
+            .keys()
+            .copied()
+            .filter(|&action| self.held(action))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Binding {
+        Binding::try_from(text.to_string()).unwrap()
+    }
+
+    #[test]
+    fn binding_round_trip() {
+        for text in ["W", "Ctrl+S", "Ctrl+Shift+Alt+F5", "MouseLeft", "MouseMiddle", "Shift+Mouse4", "Numpad5"] {
+            assert_eq!(parse(text).to_string(), text);
+        }
+        // modifiers and key names are case insensitive and may be spaced out
+        assert_eq!(parse("control + shift + key1"), parse("Ctrl+Shift+Key1"));
+        assert!(Binding::try_from("Hyper+W".to_string()).is_err());
+        assert!(Binding::try_from("Ctrl+".to_string()).is_err());
+        assert!(Binding::try_from("NotAKey".to_string()).is_err());
+    }
+
+    #[test]
+    fn conflicts() {
+        assert!(find_conflicts(&default_bindings()).is_empty());
+
+        let mut bindings = default_bindings();
+        bindings.insert(Action::Screenshot, vec![parse("W"), parse("Ctrl+W")]);
+        assert_eq!(
+            find_conflicts(&bindings),
+            vec!["input: W is bound to move_forward and screenshot".to_string()]
+        );
+    }
+
+    #[test]
+    fn merge_keeps_unconfigured_defaults() {
+        let configured = Bindings::from([
+            (Action::MoveForward, vec![parse("Up")]),
+            (Action::Screenshot, Vec::new()),
+        ]);
+        let merged = merge_with_defaults(&configured);
+        let defaults = default_bindings();
+
+        assert_eq!(merged.len(), defaults.len());
+        assert_eq!(merged[&Action::MoveForward], vec![parse("Up")]);
+        assert!(merged[&Action::Screenshot].is_empty());
+        assert_eq!(merged[&Action::MoveBackward], defaults[&Action::MoveBackward]);
+    }
+
+    #[test]
+    fn tap_within_one_frame() {
+        let mut input = ActionState::new(default_bindings());
+        input.handle_key(VirtualKeyCode::F2, ElementState::Pressed);
+        input.handle_key(VirtualKeyCode::F2, ElementState::Released);
+
+        assert!(input.pressed(Action::ToggleWireframe));
+        assert!(input.released(Action::ToggleWireframe));
+        assert!(!input.held(Action::ToggleWireframe));
+
+        input.end_frame();
+        assert!(!input.pressed(Action::ToggleWireframe));
+        assert!(!input.released(Action::ToggleWireframe));
+    }
+
+    #[test]
+    fn held_across_frames() {
+        let mut input = ActionState::new(default_bindings());
+        input.handle_key(VirtualKeyCode::W, ElementState::Pressed);
+        assert!(input.pressed(Action::MoveForward));
+        input.end_frame();
+
+        assert!(input.held(Action::MoveForward));
+        assert!(!input.pressed(Action::MoveForward));
+
+        input.release_all();
+        assert!(input.released(Action::MoveForward));
+        assert!(!input.held(Action::MoveForward));
+    }
+
+    #[test]
+    fn modifier_bindings() {
+        let mut input = ActionState::new(default_bindings());
+        input.handle_key(VirtualKeyCode::Key3, ElementState::Pressed);
+        assert!(input.pressed(RECALL_BOOKMARK[2]));
+        assert!(!input.pressed(SAVE_BOOKMARK[2]));
+        input.handle_key(VirtualKeyCode::Key3, ElementState::Released);
+        input.end_frame();
+
+        input.set_modifiers(ModifiersState::SHIFT);
+        input.handle_key(VirtualKeyCode::Key3, ElementState::Pressed);
+        assert!(input.pressed(SAVE_BOOKMARK[2]));
+    }
+}
@@ -1,78 +1,147 @@
 mod vulkan_app;
 mod camera;
+mod scene;
+mod gui;
+mod frame_pacing;
+mod settings;
+mod cli;
+mod input;
+mod fullscreen;
+mod bookmarks;
 
-use vulkan_app::{VulkanApp, HEIGHT, WIDTH};
-use camera::{Camera, CameraMovement};
-use winit::event::{Event, WindowEvent, DeviceEvent, ElementState, KeyboardInput, VirtualKeyCode};
+use vulkan_app::{ImportedCamera, VulkanApp};
+use camera::{Camera, CameraMode, Projection, ViewPreset, MAX_PITCH};
+use gui::Gui;
+use frame_pacing::FramePacing;
+use settings::{EmitterConfig, Settings};
+use cli::Cli;
+use input::{Action, ActionState};
+use bookmarks::Bookmarks;
+use clap::Parser;
+use vulkan_app::RenderMode;
+use winit::event::{Event, WindowEvent, DeviceEvent, ElementState, KeyboardInput};
 use winit::event_loop::{ControlFlow, EventLoop};
-use winit::window::WindowBuilder;
+use winit::window::{CursorGrabMode, Window, WindowBuilder};
 
 fn main() {
+    let cli = Cli::parse();
+    let mut settings = Settings::load(&cli.settings).unwrap_or_else(|error| {
+        eprintln!("Failed to load settings, using defaults: {}", error);
+        Settings::default()
+    });
+    cli.apply(&mut settings);
+    // headless runs keep the window hidden and exit after the last frame
+    let frame_limit = cli.frame_limit();
+    let mut screenshot_path = cli.screenshot.clone();
+    let bookmarks_path = std::path::Path::new(bookmarks::BOOKMARKS_PATH);
+    let mut bookmarks = Bookmarks::load(bookmarks_path).unwrap_or_else(|error| {
+        eprintln!("Failed to load bookmarks, starting with none: {}", error);
+        Bookmarks::new(bookmarks_path)
+    });
+
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new()
         .with_title("Vulkan Triangle")
-        .with_inner_size(winit::dpi::LogicalSize::new(WIDTH, HEIGHT))
+        .with_inner_size(winit::dpi::LogicalSize::new(settings.window.width, settings.window.height))
+        .with_visible(frame_limit.is_none())
         .build(&event_loop)
         .unwrap();
+    if settings.window.fullscreen {
+        window.set_fullscreen(Some(fullscreen::fullscreen(&window, &settings.window)));
+    }
 
-    let mut app = VulkanApp::new(&window);
+    let mut app = VulkanApp::new(&window, &settings.renderer);
     let mut camera = Camera::new(cgmath::Vector3::new(2.0, 2.0, 2.0), -135.0, -35.0);
+    camera.speed = settings.camera.speed as f32;
+    camera.acceleration = settings.camera.acceleration as f32;
+    camera.damping = settings.camera.damping as f32;
+    camera.sensitivity = settings.camera.sensitivity as f32;
+    camera.invert_y = settings.camera.invert_y;
+    camera.mouse_smoothing = settings.camera.mouse_smoothing as f32;
+    camera.fov = settings.camera.fov as f32;
+    camera.near = settings.camera.near as f32;
+    camera.far = settings.camera.far as f32;
+    camera.set_projection(settings.camera.projection);
+    if let Some(model_path) = &cli.model {
+        match app.load_model(model_path) {
+            Ok(Some((transform, imported))) => adopt_camera(&mut camera, transform, imported),
+            Ok(None) => {}
+            Err(error) => eprintln!("Failed to load model: {}", error),
+        }
+    }
+    // after loading, so emitters can attach to the model's nodes
+    add_emitters(&mut app, &settings.emitters);
+    if let Some(environment_path) = &cli.environment {
+        if let Err(error) = app.load_environment(environment_path) {
+            eprintln!("Failed to load environment: {}", error);
+        }
+    }
+    if let Some(position) = cli.camera_position {
+        camera.position = position.into();
+    }
+    if let Some(yaw) = cli.camera_yaw {
+        camera.yaw = yaw;
+    }
+    if let Some(pitch) = cli.camera_pitch {
+        camera.pitch = pitch.clamp(-MAX_PITCH, MAX_PITCH);
+    }
 
-    let mut input_state = InputState::default();
-    let mut last_frame = std::time::Instant::now();
-    let mut camera_focused = false;
-    let mut wants_to_grab_cursor = false;
+    let mut gui = Gui::new(&event_loop, &window);
+    let mut actions = ActionState::new(input::merge_with_defaults(&settings.input));
+    let mut pacing = FramePacing::new();
+    // how the cursor is held while it steers the camera, None while it is free
+    let mut cursor_grab: Option<CursorGrabMode> = None;
 
     event_loop.run(move |event, _, control_flow| {
-        *control_flow = ControlFlow::Poll;
         match event {
-            Event::WindowEvent { event, .. } => match event {
-                WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
-                WindowEvent::Resized(new_size) => {
-                    if new_size.width > 0 && new_size.height > 0 {
-                        app.framebuffer_resized = true;
+            Event::WindowEvent { event, .. } => {
+                pacing.request_redraw();
+                // the GUI only sees input while the cursor is free
+                let gui_consumed = cursor_grab.is_none() && gui.handle_event(&event);
+                match event {
+                    WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+                    WindowEvent::Resized(new_size) => {
+                        if new_size.width > 0 && new_size.height > 0 {
+                            app.framebuffer_resized = true;
+                        }
                     }
-                }
-                WindowEvent::MouseInput { state: ElementState::Pressed, .. } => {
-                    if !camera_focused {
-                        wants_to_grab_cursor = true;
-                        window.set_fullscreen(Some(winit::window::Fullscreen::Borderless(None)));
+                    WindowEvent::Focused(focused) => {
+                        if !focused {
+                            release_cursor(&window, &mut cursor_grab);
+                            actions.release_all();
+                        }
                     }
-                }
-                WindowEvent::Focused(focused) => {
-                    if !focused {
-                        camera_focused = false;
-                        window.set_fullscreen(None);
-                        window.set_cursor_visible(true);
+                    WindowEvent::ModifiersChanged(state) => actions.set_modifiers(state),
+                    // releases always get through, so inputs held when the GUI took focus don't stick
+                    WindowEvent::MouseInput { state, button, .. } => {
+                        if !(state == ElementState::Pressed && gui_consumed) {
+                            actions.handle_mouse_button(button, state);
+                        }
                     }
-                }
-                WindowEvent::KeyboardInput { input, .. } => {
-                    if let Some(key) = input.virtual_keycode {
-                        let pressed = input.state == ElementState::Pressed;
-                        match key {
-                            VirtualKeyCode::W => input_state.forward = pressed,
-                            VirtualKeyCode::S => input_state.backward = pressed,
-                            VirtualKeyCode::A => input_state.left = pressed,
-                            VirtualKeyCode::D => input_state.right = pressed,
-                            VirtualKeyCode::Space => input_state.up = pressed,
-                            VirtualKeyCode::LShift => input_state.down = pressed,
-                            VirtualKeyCode::Escape => {
-                                if pressed {
-                                    camera_focused = false;
-                                    window.set_fullscreen(None);
-                                    window.set_cursor_visible(true);
-                                }
+                    WindowEvent::KeyboardInput { input, .. } => {
+                        if !(input.state == ElementState::Pressed && gui_consumed) {
+                            if let Some(key) = input.virtual_keycode {
+                                actions.handle_key(key, input.state);
                             }
-                            _ => {}
                         }
                     }
+                    WindowEvent::MouseWheel { delta, .. } => {
+                        if !gui_consumed {
+                            actions.handle_scroll(delta);
+                        }
+                    }
+                    _ => {}
                 }
-                _ => {}
-            },
+            }
             Event::DeviceEvent { event, .. } => {
-                if camera_focused {
-                    if let DeviceEvent::MouseMotion { delta } = event {
-                        camera.process_mouse(-delta.0 as f32, -delta.1 as f32);
+                // only collected while it moves the camera, so a drag doesn't start with a jump
+                if let DeviceEvent::MouseMotion { delta } = event {
+                    if camera.uses_mouse(&actions, cursor_grab.is_some()) {
+                        pacing.request_redraw();
+                        actions.handle_mouse_motion(delta.0, delta.1);
+                    }
+                    // a confined cursor still stops at the window edge
+                    if cursor_grab == Some(CursorGrabMode::Confined) {
                         let window_size = window.inner_size();
                         let center_x = window_size.width / 2;
                         let center_y = window_size.height / 2;
@@ -81,36 +150,171 @@ fn main() {
                 }
             }
             Event::MainEventsCleared => {
-                if wants_to_grab_cursor {
-                    camera_focused = true;
-                    window.set_cursor_visible(false);
-                    wants_to_grab_cursor = false;
-                }
-
-                let now = std::time::Instant::now();
-                let dt = now.duration_since(last_frame).as_secs_f32();
-                last_frame = now;
+                let animating = app.is_animating() || gui.needs_repaint() || actions.moving() || camera.is_moving();
+                let Some(dt) = pacing.begin_frame(animating, control_flow) else { return };
 
-                if input_state.forward { camera.process_keyboard(CameraMovement::Forward, dt); }
-                if input_state.backward { camera.process_keyboard(CameraMovement::Backward, dt); }
-                if input_state.left { camera.process_keyboard(CameraMovement::Left, dt); }
-                if input_state.right { camera.process_keyboard(CameraMovement::Right, dt); }
-                if input_state.up { camera.process_keyboard(CameraMovement::Up, dt); }
-                if input_state.down { camera.process_keyboard(CameraMovement::Down, dt); }
+                // presses since the last drawn frame, so none are lost to skipped frames
+                if actions.pressed(Action::CaptureCursor) && cursor_grab.is_none() && camera.mode == CameraMode::Fly {
+                    cursor_grab = capture_cursor(&window);
+                }
+                if actions.pressed(Action::ReleaseCursor) {
+                    release_cursor(&window, &mut cursor_grab);
+                }
+                if actions.pressed(Action::ToggleFullscreen) {
+                    window.set_fullscreen(match window.fullscreen() {
+                        Some(_) => None,
+                        None => Some(fullscreen::fullscreen(&window, &settings.window)),
+                    });
+                }
+                if actions.pressed(Action::ToggleCameraMode) {
+                    let mode = match camera.mode {
+                        CameraMode::Fly => CameraMode::Orbit,
+                        CameraMode::Orbit => CameraMode::Fly,
+                    };
+                    camera.set_mode(mode);
+                    // the cursor is needed for dragging
+                    if mode == CameraMode::Orbit {
+                        release_cursor(&window, &mut cursor_grab);
+                    }
+                }
+                if actions.pressed(Action::ToggleProjection) {
+                    camera.set_projection(match camera.projection {
+                        Projection::Perspective => Projection::Orthographic,
+                        Projection::Orthographic => Projection::Perspective,
+                    });
+                }
+                if actions.pressed(Action::FrameScene) {
+                    if let Some((min, max)) = app.scene_bounds() {
+                        camera.frame_box(min, max);
+                    }
+                }
+                for (action, preset) in [
+                    (Action::ViewTop, ViewPreset::Top),
+                    (Action::ViewFront, ViewPreset::Front),
+                    (Action::ViewSide, ViewPreset::Side),
+                    (Action::ViewIsometric, ViewPreset::Isometric),
+                ] {
+                    if actions.pressed(action) {
+                        camera.set_view(preset);
+                    }
+                }
+                if actions.pressed(Action::ToggleGui) {
+                    gui.visible = !gui.visible;
+                }
+                if actions.pressed(Action::ToggleOverlay) {
+                    app.toggle_overlay();
+                }
+                if actions.pressed(Action::ToggleGpuCulling) {
+                    app.set_gpu_culling(!app.gpu_culling_enabled());
+                }
+                if actions.pressed(Action::ToggleWireframe) {
+                    app.set_render_mode(match app.render_mode() {
+                        RenderMode::Shaded => RenderMode::Wireframe,
+                        RenderMode::Wireframe => RenderMode::Shaded,
+                    });
+                }
+                if actions.pressed(Action::Screenshot) {
+                    let timestamp = std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .map_or(0, |elapsed| elapsed.as_secs());
+                    app.request_screenshot(format!("screenshot-{}.png", timestamp).into());
+                }
+                for (index, (recall, save)) in input::RECALL_BOOKMARK.into_iter().zip(input::SAVE_BOOKMARK).enumerate() {
+                    let slot = index + 1;
+                    if actions.pressed(save) {
+                        match bookmarks.save(slot, camera.pose()) {
+                            Ok(()) => println!("Saved bookmark {}", slot),
+                            Err(error) => eprintln!("Failed to save bookmark: {}", error),
+                        }
+                    }
+                    if actions.pressed(recall) {
+                        match bookmarks.get(slot) {
+                            Some(pose) => camera.animate_to(pose, settings.camera.transition_duration as f32),
+                            None => println!("Bookmark {} is empty", slot),
+                        }
+                    }
+                }
+                camera.update(&actions, cursor_grab.is_some(), dt);
+                actions.end_frame();
 
+                if gui.visible {
+                    let gui_frame = gui.frame(&window, &mut camera, &mut app, &mut pacing);
+                    app.submit_gui(gui_frame);
+                }
+                // requested ahead of the last frame and kept until a frame actually gets drawn
+                if frame_limit.is_some_and(|limit| app.frames_drawn() + 1 >= limit as u64) {
+                    if let Some(path) = screenshot_path.take() {
+                        app.request_screenshot(path);
+                    }
+                }
                 app.draw_frame(&window, &camera);
+                if let Some(result) = app.finish_screenshot() {
+                    match result {
+                        Ok(path) => println!("Saved screenshot to {}", path.display()),
+                        Err(error) => {
+                            eprintln!("Failed to save screenshot: {}", error);
+                            *control_flow = ControlFlow::ExitWithCode(1);
+                            return;
+                        }
+                    }
+                }
+                if frame_limit.is_some_and(|limit| app.frames_drawn() >= limit as u64) && !app.screenshot_pending() {
+                    *control_flow = ControlFlow::Exit;
+                }
             }
             _ => {}
         }
     });
 }
 
-#[derive(Default)]
-struct InputState {
-    forward: bool,
-    backward: bool,
-    left: bool,
-    right: bool,
-    up: bool,
-    down: bool,
+// Takes over the pose and projection of a camera from a loaded file.
+fn adopt_camera(camera: &mut Camera, transform: cgmath::Matrix4<f32>, imported: ImportedCamera) {
+    camera.set_from_matrix(transform);
+    if let Some(fov) = imported.fov {
+        camera.fov = fov;
+    }
+    camera.near = imported.near;
+    if let Some(far) = imported.far {
+        camera.far = far;
+    }
+    // assigned rather than switched, the pose is the file's exactly
+    camera.projection = imported.projection;
+    if let Some(ortho_height) = imported.ortho_height {
+        camera.ortho_height = ortho_height;
+    }
+}
+
+fn add_emitters(app: &mut VulkanApp, emitters: &[EmitterConfig]) {
+    for config in emitters {
+        let node = config.node.as_deref().and_then(|name| {
+            let node = app.find_node(name);
+            if node.is_none() {
+                eprintln!("No node called '{}' for a particle emitter, placing it in world space", name);
+            }
+            node
+        });
+        if app.add_emitter(config.to_emitter(node)).is_none() {
+            eprintln!("The particle buffer is full, skipping the remaining emitters");
+            break;
+        }
+    }
+}
+
+// Locks the cursor in place, or keeps it inside the window where locking isn't supported.
+fn capture_cursor(window: &Window) -> Option<CursorGrabMode> {
+    let mode = [CursorGrabMode::Locked, CursorGrabMode::Confined]
+        .into_iter()
+        .find(|&mode| window.set_cursor_grab(mode).is_ok());
+    match mode {
+        Some(_) => window.set_cursor_visible(false),
+        None => eprintln!("Failed to capture the cursor"),
+    }
+    mode
+}
+
+fn release_cursor(window: &Window, cursor_grab: &mut Option<CursorGrabMode>) {
+    if cursor_grab.take().is_some() {
+        let _ = window.set_cursor_grab(CursorGrabMode::None);
+        window.set_cursor_visible(true);
+    }
 }
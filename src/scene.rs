@@ -0,0 +1,293 @@
+use cgmath::{Matrix4, One, Quaternion, SquareMatrix, Vector3};
+
+pub type NodeId = usize;
+
+pub struct Node {
+    pub name: String,
+    pub mesh: Option<usize>,
+    pub material: Option<usize>,
+    pub color: [f32; 4],
+    pub visible: bool,
+    translation: Vector3<f32>,
+    rotation: Quaternion<f32>,
+    scale: Vector3<f32>,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    world: Matrix4<f32>,
+    dirty: bool,
+}
+
+impl Node {
+    fn new(name: &str, parent: Option<NodeId>) -> Self {
+        Self {
+            name: name.to_string(),
+            mesh: None,
+            material: None,
+            color: [1.0, 1.0, 1.0, 1.0],
+            visible: true,
+            translation: Vector3::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+            parent,
+            children: Vec::new(),
+            world: Matrix4::identity(),
+            dirty: true,
+        }
+    }
+
+    pub fn translation(&self) -> Vector3<f32> {
+        self.translation
+    }
+
+    pub fn rotation(&self) -> Quaternion<f32> {
+        self.rotation
+    }
+
+    pub fn scale(&self) -> Vector3<f32> {
+        self.scale
+    }
+
+    pub fn set_translation(&mut self, translation: Vector3<f32>) {
+        self.translation = translation;
+        self.dirty = true;
+    }
+
+    pub fn set_rotation(&mut self, rotation: Quaternion<f32>) {
+        self.rotation = rotation;
+        self.dirty = true;
+    }
+
+    pub fn set_scale(&mut self, scale: Vector3<f32>) {
+        self.scale = scale;
+        self.dirty = true;
+    }
+
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
+    }
+
+    // Only up to date after Scene::update_world_matrices.
+    pub fn world_matrix(&self) -> Matrix4<f32> {
+        self.world
+    }
+
+    pub fn local_matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+#[derive(Default)]
+pub struct Scene {
+    nodes: Vec<Node>,
+    roots: Vec<NodeId>,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_node(&mut self, name: &str, parent: Option<NodeId>) -> NodeId {
+        let id = self.nodes.len();
+        self.nodes.push(Node::new(name, parent));
+        match parent {
+            Some(parent) => self.nodes[parent].children.push(id),
+            None => self.roots.push(id),
+        }
+        id
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id]
+    }
+
+    pub fn node_mut(&mut self, id: NodeId) -> &mut Node {
+        &mut self.nodes[id]
+    }
+
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    // The first node called `name`.
+    pub fn find(&self, name: &str) -> Option<NodeId> {
+        self.nodes.iter().position(|node| node.name == name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>) {
+        let mut ancestor = parent;
+        while let Some(current) = ancestor {
+            assert!(current != id, "Cannot parent a node to one of its descendants");
+            ancestor = self.nodes[current].parent;
+        }
+
+        match self.nodes[id].parent {
+            Some(old_parent) => self.nodes[old_parent].children.retain(|&child| child != id),
+            None => self.roots.retain(|&root| root != id),
+        }
+        match parent {
+            Some(new_parent) => self.nodes[new_parent].children.push(id),
+            None => self.roots.push(id),
+        }
+        self.nodes[id].parent = parent;
+        self.nodes[id].dirty = true;
+    }
+
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.roots.clear();
+    }
+
+    // Recomputes world matrices for dirty nodes and everything below them.
+    pub fn update_world_matrices(&mut self) {
+        let mut stack: Vec<(NodeId, Matrix4<f32>, bool)> = self
+            .roots
+            .iter()
+            .rev()
+            .map(|&root| (root, Matrix4::identity(), false))
+            .collect();
+
+        while let Some((id, parent_world, parent_changed)) = stack.pop() {
+            let node = &mut self.nodes[id];
+            let changed = node.dirty || parent_changed;
+            if changed {
+                node.world = parent_world * node.local_matrix();
+                node.dirty = false;
+            }
+            let world = node.world;
+            for &child in node.children.iter().rev() {
+                stack.push((child, world, changed));
+            }
+        }
+    }
+
+    // Nodes with a mesh whose whole ancestor chain is visible, in depth-first order.
+    pub fn visible_nodes(&self) -> Vec<NodeId> {
+        let mut visible = Vec::new();
+        let mut stack: Vec<NodeId> = self.roots.iter().rev().copied().collect();
+        while let Some(id) = stack.pop() {
+            let node = &self.nodes[id];
+            if !node.visible {
+                continue;
+            }
+            if node.mesh.is_some() {
+                visible.push(id);
+            }
+            stack.extend(node.children.iter().rev());
+        }
+        visible
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Deg, InnerSpace, Rotation3, Vector4};
+
+    use super::*;
+
+    fn assert_close(a: Matrix4<f32>, b: Matrix4<f32>) {
+        let columns = [(a.x, b.x), (a.y, b.y), (a.z, b.z), (a.w, b.w)];
+        let difference: f32 = columns.iter().map(|(a, b)| (a - b).magnitude()).sum();
+        assert!(difference < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    // root -> child -> grandchild, each with a different transform.
+    fn chain() -> (Scene, [NodeId; 3]) {
+        let mut scene = Scene::new();
+        let root = scene.add_node("root", None);
+        let child = scene.add_node("child", Some(root));
+        let grandchild = scene.add_node("grandchild", Some(child));
+        scene.node_mut(root).set_translation(Vector3::new(1.0, 2.0, 3.0));
+        scene.node_mut(root).set_rotation(Quaternion::from_angle_z(Deg(90.0)));
+        scene.node_mut(child).set_translation(Vector3::new(0.0, 5.0, 0.0));
+        scene.node_mut(child).set_scale(Vector3::new(2.0, 2.0, 2.0));
+        scene.node_mut(grandchild).set_translation(Vector3::new(1.0, 0.0, 0.0));
+        scene.update_world_matrices();
+        (scene, [root, child, grandchild])
+    }
+
+    #[test]
+    fn world_is_parent_times_local() {
+        let (scene, [root, child, grandchild]) = chain();
+        assert_close(scene.node(root).world_matrix(), scene.node(root).local_matrix());
+        for (parent, id) in [(root, child), (child, grandchild)] {
+            assert_close(
+                scene.node(id).world_matrix(),
+                scene.node(parent).world_matrix() * scene.node(id).local_matrix(),
+            );
+        }
+
+        // the grandchild's origin: 1 along X, scaled by 2, 5 along Y, turned a quarter about Z, moved
+        let origin = scene.node(grandchild).world_matrix() * Vector4::new(0.0, 0.0, 0.0, 1.0);
+        assert!((origin.truncate() - Vector3::new(-4.0, 4.0, 3.0)).magnitude() < 1e-4, "{:?}", origin);
+    }
+
+    #[test]
+    fn parent_change_updates_subtree() {
+        let (mut scene, [root, child, grandchild]) = chain();
+        let before = scene.node(grandchild).world_matrix();
+
+        scene.node_mut(root).set_translation(Vector3::new(0.0, 0.0, 10.0));
+        // nothing moves until the next update
+        assert_close(scene.node(grandchild).world_matrix(), before);
+
+        scene.update_world_matrices();
+        let moved = Matrix4::from_translation(Vector3::new(-1.0, -2.0, 7.0)) * before;
+        assert_close(scene.node(grandchild).world_matrix(), moved);
+        assert_close(
+            scene.node(child).world_matrix(),
+            scene.node(root).world_matrix() * scene.node(child).local_matrix(),
+        );
+    }
+
+    #[test]
+    fn reparenting_moves_the_subtree() {
+        let (mut scene, [root, child, grandchild]) = chain();
+        scene.set_parent(child, None);
+        scene.update_world_matrices();
+
+        assert_eq!(scene.roots(), &[root, child]);
+        assert_eq!(scene.node(child).parent(), None);
+        assert!(scene.node(root).children().is_empty());
+        assert_close(scene.node(child).world_matrix(), scene.node(child).local_matrix());
+        assert_close(
+            scene.node(grandchild).world_matrix(),
+            scene.node(child).local_matrix() * scene.node(grandchild).local_matrix(),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "descendants")]
+    fn reparenting_under_a_descendant_panics() {
+        let (mut scene, [root, _, grandchild]) = chain();
+        scene.set_parent(root, Some(grandchild));
+    }
+
+    #[test]
+    fn hidden_subtrees_are_skipped() {
+        let (mut scene, [root, child, grandchild]) = chain();
+        let other = scene.add_node("other", None);
+        for id in [root, child, grandchild, other] {
+            scene.node_mut(id).mesh = Some(0);
+        }
+        assert_eq!(scene.visible_nodes(), vec![root, child, grandchild, other]);
+
+        scene.node_mut(child).visible = false;
+        assert_eq!(scene.visible_nodes(), vec![root, other]);
+
+        // nodes without a mesh are not drawn, but their children still are
+        scene.node_mut(child).visible = true;
+        scene.node_mut(child).mesh = None;
+        assert_eq!(scene.visible_nodes(), vec![root, grandchild, other]);
+    }
+}
@@ -0,0 +1,336 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::camera::{self, Projection};
+use crate::input::{self, Bindings};
+use crate::scene::NodeId;
+use crate::vulkan_app::{EmitterSettings, PresentMode, SpawnShape};
+
+pub const SETTINGS_PATH: &str = "settings.toml";
+
+const MSAA_SAMPLE_COUNTS: [u32; 7] = [1, 2, 4, 8, 16, 32, 64];
+
+// Startup options read from settings.toml. Missing keys keep their defaults, unknown ones are errors.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub window: WindowSettings,
+    pub camera: CameraSettings,
+    pub renderer: RendererSettings,
+    // Particle emitters, written as [[emitters]] tables.
+    pub emitters: Vec<EmitterConfig>,
+    // Action name to list of bindings. Actions left out keep their default bindings.
+    pub input: Bindings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            window: WindowSettings::default(),
+            camera: CameraSettings::default(),
+            renderer: RendererSettings::default(),
+            emitters: Vec::new(),
+            input: input::default_bindings(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowSettings {
+    // Logical size, i.e. before the display's scale factor.
+    pub width: u32,
+    pub height: u32,
+    pub fullscreen: bool,
+    pub fullscreen_mode: FullscreenMode,
+    // Exclusive fullscreen resolution, the monitor's largest if unset.
+    pub video_mode: Option<VideoModeSettings>,
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        Self {
+            width: 800,
+            height: 600,
+            fullscreen: false,
+            fullscreen_mode: FullscreenMode::Borderless,
+            video_mode: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FullscreenMode {
+    // A window covering the monitor at its current resolution.
+    Borderless,
+    // Takes over the monitor and switches it to `video_mode`.
+    Exclusive,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VideoModeSettings {
+    pub width: u32,
+    pub height: u32,
+    // In Hz, the highest available if unset.
+    pub refresh_rate: Option<u32>,
+}
+
+// f64 so the defaults are written as typed, e.g. 0.1 rather than 0.10000000149011612.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CameraSettings {
+    // Units per second, changed with the scroll wheel in fly mode.
+    pub speed: f64,
+    // How quickly flying reaches full speed and comes to rest, in 1/s.
+    pub acceleration: f64,
+    pub damping: f64,
+    // Degrees per pixel of mouse motion.
+    pub sensitivity: f64,
+    pub invert_y: bool,
+    // Seconds over which mouse motion is spread out, 0 for none.
+    pub mouse_smoothing: f64,
+    pub projection: Projection,
+    // Vertical field of view in degrees, also sets the initial orthographic zoom.
+    pub fov: f64,
+    pub near: f64,
+    pub far: f64,
+    // Seconds taken to fly to a recalled bookmark, 0 to jump.
+    pub transition_duration: f64,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        Self {
+            speed: 2.5,
+            acceleration: 10.0,
+            damping: 8.0,
+            sensitivity: 0.1,
+            invert_y: false,
+            mouse_smoothing: 0.0,
+            projection: Projection::Perspective,
+            fov: 45.0,
+            near: 0.1,
+            far: 100.0,
+            transition_duration: 0.5,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RendererSettings {
+    // Index or part of the name of the GPU to use, the first suitable one if unset.
+    pub gpu: Option<String>,
+    // Enables VK_LAYER_KHRONOS_validation when it is installed.
+    pub validation: bool,
+    pub present_mode: PresentMode,
+    // Lowered to what the GPU supports.
+    pub msaa_samples: u32,
+    pub grid_divisions: u32,
+    // Linear RGB.
+    pub clear_color: [f32; 3],
+    pub grid_color: [f32; 3],
+}
+
+impl Default for RendererSettings {
+    fn default() -> Self {
+        Self {
+            gpu: None,
+            validation: false,
+            present_mode: PresentMode::Mailbox,
+            msaa_samples: 1,
+            grid_divisions: 24,
+            clear_color: [0.0, 0.0, 0.0],
+            grid_color: [0.0, 0.0, 0.0],
+        }
+    }
+}
+
+// Added once the scene is loaded, e.g.
+//   [[emitters]]
+//   node = "cube"
+//   rate = 400.0
+//   shape = { type = "sphere", radius = 0.1 }
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EmitterConfig {
+    // Name of the scene node to follow; world space when unset.
+    pub node: Option<String>,
+    // Relative to the node.
+    pub position: [f32; 3],
+    pub direction: [f32; 3],
+    // Half angle of the launch cone in degrees.
+    pub cone_angle: f32,
+    pub shape: SpawnShapeConfig,
+    // Particles per second.
+    pub rate: f32,
+    pub speed: f32,
+    // Seconds.
+    pub lifetime: f32,
+    pub gravity: [f32; 3],
+    // Linear RGBA, faded between over each particle's life.
+    pub start_color: [f32; 4],
+    pub end_color: [f32; 4],
+    pub size: f32,
+}
+
+impl Default for EmitterConfig {
+    fn default() -> Self {
+        let defaults = EmitterSettings::default();
+        Self {
+            node: None,
+            position: defaults.position.into(),
+            direction: defaults.direction.into(),
+            cone_angle: defaults.cone_angle.0,
+            shape: SpawnShapeConfig::Point,
+            rate: defaults.rate,
+            speed: defaults.speed,
+            lifetime: defaults.lifetime,
+            gravity: defaults.gravity.into(),
+            start_color: defaults.start_color,
+            end_color: defaults.end_color,
+            size: defaults.size,
+        }
+    }
+}
+
+impl EmitterConfig {
+    pub fn to_emitter(&self, node: Option<NodeId>) -> EmitterSettings {
+        EmitterSettings {
+            position: self.position.into(),
+            direction: self.direction.into(),
+            cone_angle: cgmath::Deg(self.cone_angle),
+            shape: match self.shape {
+                SpawnShapeConfig::Point => SpawnShape::Point,
+                SpawnShapeConfig::Sphere { radius } => SpawnShape::Sphere { radius },
+                SpawnShapeConfig::Box { half_extents } => SpawnShape::Box {
+                    half_extents: half_extents.into(),
+                },
+            },
+            rate: self.rate,
+            speed: self.speed,
+            lifetime: self.lifetime,
+            gravity: self.gravity.into(),
+            start_color: self.start_color,
+            end_color: self.end_color,
+            size: self.size,
+            node,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum SpawnShapeConfig {
+    Point,
+    Sphere { radius: f32 },
+    Box { half_extents: [f32; 3] },
+}
+
+#[derive(Debug)]
+pub struct SettingsError {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+impl Settings {
+    // Reads the file, or writes the defaults to it if there is none yet.
+    pub fn load(path: &Path) -> Result<Self, SettingsError> {
+        let error = |message: String| SettingsError {
+            path: path.to_path_buf(),
+            message,
+        };
+
+        if !path.exists() {
+            let settings = Self::default();
+            let text = toml::to_string(&settings).map_err(|e| error(e.to_string()))?;
+            std::fs::write(path, text).map_err(|e| error(format!("failed to write defaults: {}", e)))?;
+            println!("Wrote default settings to {}", path.display());
+            return Ok(settings);
+        }
+
+        let text = std::fs::read_to_string(path).map_err(|e| error(e.to_string()))?;
+        let settings: Self = toml::from_str(&text).map_err(|e| error(e.to_string()))?;
+        let problems = settings.validate();
+        if !problems.is_empty() {
+            return Err(error(format!("invalid settings:\n  {}", problems.join("\n  "))));
+        }
+        Ok(settings)
+    }
+
+    // Every value that parsed but is out of range, as "section.key ..." messages.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut check = |ok: bool, message: &str| {
+            if !ok {
+                problems.push(message.to_string());
+            }
+        };
+
+        let window = &self.window;
+        check(window.width > 0, "window.width must be greater than 0");
+        check(window.height > 0, "window.height must be greater than 0");
+        if let Some(video_mode) = &window.video_mode {
+            check(
+                video_mode.width > 0 && video_mode.height > 0,
+                "window.video_mode size must be greater than 0",
+            );
+            check(video_mode.refresh_rate != Some(0), "window.video_mode.refresh_rate must be greater than 0");
+        }
+
+        let camera = &self.camera;
+        check(
+            (camera::MIN_SPEED as f64..=camera::MAX_SPEED as f64).contains(&camera.speed),
+            &format!("camera.speed must be between {} and {}", camera::MIN_SPEED, camera::MAX_SPEED),
+        );
+        check(camera.acceleration > 0.0, "camera.acceleration must be greater than 0");
+        check(camera.damping > 0.0, "camera.damping must be greater than 0");
+        check(camera.sensitivity > 0.0, "camera.sensitivity must be greater than 0");
+        check(camera.mouse_smoothing >= 0.0, "camera.mouse_smoothing must not be negative");
+        check(camera.fov > 0.0 && camera.fov < 180.0, "camera.fov must be between 0 and 180 degrees");
+        check(camera.near > 0.0, "camera.near must be greater than 0");
+        check(camera.far > camera.near, "camera.far must be greater than camera.near");
+        check(
+            camera.transition_duration >= 0.0,
+            "camera.transition_duration must not be negative",
+        );
+
+        let renderer = &self.renderer;
+        check(
+            MSAA_SAMPLE_COUNTS.contains(&renderer.msaa_samples),
+            "renderer.msaa_samples must be one of 1, 2, 4, 8, 16, 32 or 64",
+        );
+        check(renderer.grid_divisions > 0, "renderer.grid_divisions must be at least 1");
+        for (name, color) in [("clear_color", renderer.clear_color), ("grid_color", renderer.grid_color)] {
+            check(
+                color.iter().all(|c| (0.0..=1.0).contains(c)),
+                &format!("renderer.{} components must be between 0 and 1", name),
+            );
+        }
+
+        for (i, emitter) in self.emitters.iter().enumerate() {
+            check(emitter.rate > 0.0, &format!("emitters[{}].rate must be greater than 0", i));
+            check(emitter.lifetime > 0.0, &format!("emitters[{}].lifetime must be greater than 0", i));
+            check(emitter.size > 0.0, &format!("emitters[{}].size must be greater than 0", i));
+            check(
+                (0.0..=180.0).contains(&emitter.cone_angle),
+                &format!("emitters[{}].cone_angle must be between 0 and 180 degrees", i),
+            );
+        }
+
+        problems.extend(input::find_conflicts(&input::merge_with_defaults(&self.input)));
+        problems
+    }
+}
@@ -0,0 +1,41 @@
+#version 450
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4 view;
+    mat4 proj;
+} ubo;
+
+struct Instance {
+    mat4 model;
+    vec4 color;
+    vec4 boundingSphere;
+    uint indexCount;
+    uint batch;
+    uint batchOffset;
+    uint objectId;
+};
+
+layout(std430, set = 2, binding = 0) readonly buffer Instances {
+    Instance instances[];
+};
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inColor;
+layout(location = 2) in vec3 inNormal;
+layout(location = 3) in vec2 inTexCoord;
+
+layout(location = 0) out vec3 fragColor;
+layout(location = 1) out vec2 fragTexCoord;
+layout(location = 2) out vec3 fragWorldPosition;
+layout(location = 3) out vec3 fragNormal;
+
+void main() {
+    // firstInstance of each indirect command is the instance index written by cull.comp
+    Instance instance = instances[gl_InstanceIndex];
+    gl_Position = ubo.proj * ubo.view * instance.model * vec4(inPosition, 1.0);
+    fragColor = inColor * instance.color.rgb;
+    fragTexCoord = inTexCoord;
+    fragWorldPosition = (instance.model * vec4(inPosition, 1.0)).xyz;
+    // meshes without normals keep a zero normal, which the fragment shader leaves unlit
+    fragNormal = transpose(inverse(mat3(instance.model))) * inNormal;
+}
@@ -0,0 +1,48 @@
+// Output encoding shared by every shader that writes to the swapchain. Included through the
+// callback in build.rs, so it has no #version of its own.
+
+const mat3 REC709_TO_REC2020 = mat3(
+    0.6274, 0.0691, 0.0164,
+    0.3293, 0.9195, 0.0880,
+    0.0433, 0.0114, 0.8956
+);
+
+vec3 linearToSrgb(vec3 color) {
+    vec3 lower = color * 12.92;
+    vec3 higher = 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055;
+    return mix(higher, lower, lessThan(color, vec3(0.0031308)));
+}
+
+// SMPTE ST 2084 from absolute luminance
+vec3 pqEncode(vec3 nits) {
+    vec3 y = pow(clamp(nits / 10000.0, 0.0, 1.0), vec3(0.1593017578125));
+    return pow((0.8359375 + 18.8515625 * y) / (1.0 + 18.6875 * y), vec3(78.84375));
+}
+
+// linear up to 80% of the peak, then rolls off towards it instead of clipping
+vec3 tonemap(vec3 color, float peak) {
+    float knee = 0.8 * peak;
+    vec3 excess = max(color - knee, 0.0);
+    float range = peak - knee;
+    return min(color, vec3(knee)) + range * excess / (excess + range);
+}
+
+// 1.0 is paper white; HDR outputs leave room above it up to the display's peak and roll off
+// towards it, SDR outputs clip at 1.0 as they always have.
+// params x: 0 sRGB format, 1 UNORM format needing the sRGB curve, 2 HDR10, 3 scRGB;
+// y: paper white nits, z: peak nits. UI passes tonemapped = false to stay at paper white.
+vec3 encodeOutput(vec3 color, vec3 params, bool tonemapped) {
+    int mode = int(params.x);
+    float paperWhite = params.y;
+    color = max(color, 0.0);
+    if (mode == 2 || mode == 3) {
+        if (tonemapped) {
+            color = tonemap(color, params.z / paperWhite);
+        }
+        color *= paperWhite;
+        // scRGB puts 1.0 at 80 nits
+        return mode == 2 ? pqEncode(REC709_TO_REC2020 * color) : color / 80.0;
+    }
+    color = min(color, 1.0);
+    return mode == 1 ? linearToSrgb(color) : color;
+}
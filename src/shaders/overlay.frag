@@ -0,0 +1,22 @@
+#version 450
+
+layout(push_constant) uniform PushConstants {
+    vec2 screenSize;
+    // x: output encoding as in output.glsl, y: paper white nits
+    vec2 outputParams;
+} pc;
+
+layout(set = 0, binding = 0) uniform sampler2D fontAtlas;
+
+layout(location = 0) in vec2 fragUV;
+layout(location = 1) in vec4 fragColor;
+
+layout(location = 0) out vec4 outColor;
+
+#include "output.glsl"
+
+void main() {
+    // uvs are in texels, fetching keeps the glyphs sharp at any scale
+    float coverage = texelFetch(fontAtlas, ivec2(fragUV), 0).a;
+    outColor = vec4(encodeOutput(fragColor.rgb, vec3(pc.outputParams, 0.0), false), fragColor.a * coverage);
+}
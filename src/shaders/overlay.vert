@@ -0,0 +1,21 @@
+#version 450
+
+layout(push_constant) uniform PushConstants {
+    vec2 screenSize;
+    // x: output encoding as in shader.frag, y: paper white nits
+    vec2 outputParams;
+} pc;
+
+layout(location = 0) in vec2 inPosition;
+layout(location = 1) in vec2 inUV;
+layout(location = 2) in vec4 inColor;
+
+layout(location = 0) out vec2 fragUV;
+layout(location = 1) out vec4 fragColor;
+
+void main() {
+    fragUV = inUV;
+    fragColor = inColor;
+    // pixels from the top left corner, which is (-1, -1) in Vulkan clip space
+    gl_Position = vec4(inPosition / pc.screenSize * 2.0 - 1.0, 0.0, 1.0);
+}
@@ -0,0 +1,25 @@
+#version 450
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4 view;
+    mat4 proj;
+    vec4 cameraPosition;
+    // x: image-based lighting enabled, y: intensity, z: roughness, w: metallic
+    vec4 lighting;
+    // x: output encoding as in output.glsl, y: paper white nits, z: peak nits
+    vec4 outputParams;
+} ubo;
+
+layout(location = 0) in vec4 fragColor;
+layout(location = 1) in vec2 fragOffset;
+
+layout(location = 0) out vec4 outColor;
+
+#include "output.glsl"
+
+void main() {
+    float falloff = 1.0 - smoothstep(0.5, 1.0, length(fragOffset));
+    // blended additively, so alpha only scales the contribution; outputs other than an sRGB
+    // format add in their encoded space, which brightens overlapping particles somewhat
+    outColor = vec4(encodeOutput(fragColor.rgb * fragColor.a * falloff, ubo.outputParams.xyz, true), 1.0);
+}
@@ -0,0 +1,47 @@
+#version 450
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4 view;
+    mat4 proj;
+} ubo;
+
+struct Particle {
+    vec4 positionAge;
+    vec4 velocityLifetime;
+    vec4 color;
+};
+
+layout(std430, set = 1, binding = 0) readonly buffer Particles {
+    Particle particles[];
+};
+
+layout(push_constant) uniform ParticleDraw {
+    float size;
+} draw;
+
+layout(location = 0) out vec4 fragColor;
+layout(location = 1) out vec2 fragOffset;
+
+const vec2 corners[6] = vec2[](
+    vec2(-1.0, -1.0), vec2(1.0, -1.0), vec2(1.0, 1.0),
+    vec2(-1.0, -1.0), vec2(1.0, 1.0), vec2(-1.0, 1.0)
+);
+
+void main() {
+    // six vertices per particle, firstVertex selects the emitter's range
+    Particle particle = particles[gl_VertexIndex / 6];
+    vec2 corner = corners[gl_VertexIndex % 6];
+    fragColor = particle.color;
+    fragOffset = corner;
+
+    if (particle.positionAge.w >= particle.velocityLifetime.w) {
+        // dead particles collapse outside the clip volume
+        gl_Position = vec4(0.0, 0.0, 2.0, 1.0);
+        return;
+    }
+
+    vec3 right = vec3(ubo.view[0][0], ubo.view[1][0], ubo.view[2][0]);
+    vec3 up = vec3(ubo.view[0][1], ubo.view[1][1], ubo.view[2][1]);
+    vec3 position = particle.positionAge.xyz + (right * corner.x + up * corner.y) * draw.size;
+    gl_Position = ubo.proj * ubo.view * vec4(position, 1.0);
+}
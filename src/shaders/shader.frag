@@ -1,9 +1,61 @@
 #version 450
 
+layout(binding = 0) uniform UniformBufferObject {
+    mat4 view;
+    mat4 proj;
+    vec4 cameraPosition;
+    // x: image-based lighting enabled, y: intensity, z: roughness, w: metallic
+    vec4 lighting;
+    // x: output encoding as in output.glsl, y: paper white nits, z: peak nits
+    vec4 outputParams;
+} ubo;
+
+layout(set = 1, binding = 0) uniform sampler2D texSampler;
+
+layout(set = 3, binding = 0) uniform samplerCube irradianceMap;
+layout(set = 3, binding = 1) uniform samplerCube prefilteredMap;
+layout(set = 3, binding = 2) uniform sampler2D brdfLut;
+
 layout(location = 0) in vec3 fragColor;
+layout(location = 1) in vec2 fragTexCoord;
+layout(location = 2) in vec3 fragWorldPosition;
+layout(location = 3) in vec3 fragNormal;
 
 layout(location = 0) out vec4 outColor;
 
+// the world is Z-up while cube maps are authored Y-up
+vec3 toCubeSpace(vec3 direction) {
+    return vec3(direction.x, direction.z, -direction.y);
+}
+
+#include "output.glsl"
+
 void main() {
-    outColor = vec4(fragColor, 1.0);
+    vec3 albedo = fragColor * texture(texSampler, fragTexCoord).rgb;
+    float normalLength = length(fragNormal);
+    if (ubo.lighting.x == 0.0 || normalLength < 1e-4) {
+        outColor = vec4(encodeOutput(albedo, ubo.outputParams.xyz, true), 1.0);
+        return;
+    }
+
+    float roughness = ubo.lighting.z;
+    float metallic = ubo.lighting.w;
+    vec3 normal = fragNormal / normalLength;
+    vec3 view = normalize(ubo.cameraPosition.xyz - fragWorldPosition);
+    float nDotV = max(dot(normal, view), 1e-4);
+    vec3 reflected = reflect(-view, normal);
+
+    // Fresnel with a roughness term, since ambient light arrives from every direction
+    vec3 f0 = mix(vec3(0.04), albedo, metallic);
+    vec3 fresnel = f0 + (max(vec3(1.0 - roughness), f0) - f0) * pow(1.0 - nDotV, 5.0);
+    vec3 diffuseWeight = (1.0 - fresnel) * (1.0 - metallic);
+
+    vec3 diffuse = texture(irradianceMap, toCubeSpace(normal)).rgb * albedo;
+    float maxLod = float(textureQueryLevels(prefilteredMap) - 1);
+    vec3 prefiltered = textureLod(prefilteredMap, toCubeSpace(reflected), roughness * maxLod).rgb;
+    vec2 brdf = texture(brdfLut, vec2(nDotV, roughness)).rg;
+    vec3 specular = prefiltered * (fresnel * brdf.x + brdf.y);
+
+    vec3 color = (diffuseWeight * diffuse + specular) * ubo.lighting.y;
+    outColor = vec4(encodeOutput(color, ubo.outputParams.xyz, true), 1.0);
 }
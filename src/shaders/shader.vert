@@ -1,17 +1,31 @@
 #version 450
 
 layout(binding = 0) uniform UniformBufferObject {
-    mat4 model;
     mat4 view;
     mat4 proj;
 } ubo;
 
+layout(push_constant) uniform ObjectPushConstants {
+    mat4 model;
+    vec4 color;
+    uint objectId;
+} object;
+
 layout(location = 0) in vec3 inPosition;
 layout(location = 1) in vec3 inColor;
+layout(location = 2) in vec3 inNormal;
+layout(location = 3) in vec2 inTexCoord;
 
 layout(location = 0) out vec3 fragColor;
+layout(location = 1) out vec2 fragTexCoord;
+layout(location = 2) out vec3 fragWorldPosition;
+layout(location = 3) out vec3 fragNormal;
 
 void main() {
-    gl_Position = ubo.proj * ubo.view * ubo.model * vec4(inPosition, 1.0);
-    fragColor = inColor;
+    gl_Position = ubo.proj * ubo.view * object.model * vec4(inPosition, 1.0);
+    fragColor = inColor * object.color.rgb;
+    fragTexCoord = inTexCoord;
+    fragWorldPosition = (object.model * vec4(inPosition, 1.0)).xyz;
+    // meshes without normals keep a zero normal, which the fragment shader leaves unlit
+    fragNormal = transpose(inverse(mat3(object.model))) * inNormal;
 }
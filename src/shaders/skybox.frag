@@ -0,0 +1,26 @@
+#version 450
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4 view;
+    mat4 proj;
+    vec4 cameraPosition;
+    // x: image-based lighting enabled, y: intensity, z: roughness, w: metallic
+    vec4 lighting;
+    // x: output encoding as in output.glsl, y: paper white nits, z: peak nits
+    vec4 outputParams;
+} ubo;
+
+layout(set = 1, binding = 0) uniform samplerCube skybox;
+
+layout(location = 0) in vec3 fragDirection;
+
+layout(location = 0) out vec4 outColor;
+
+#include "output.glsl"
+
+void main() {
+    vec3 direction = normalize(fragDirection);
+    // the world is Z-up while cube maps are authored Y-up
+    vec3 color = texture(skybox, vec3(direction.x, direction.z, -direction.y)).rgb;
+    outColor = vec4(encodeOutput(color, ubo.outputParams.xyz, true), 1.0);
+}
@@ -0,0 +1,29 @@
+#version 450
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4 view;
+    mat4 proj;
+} ubo;
+
+layout(location = 0) out vec3 fragDirection;
+
+// two triangles per cube face, indexing corners whose bits 0..2 select -1 or +1 on x, y, z
+const int indices[36] = int[](
+    0, 2, 3, 0, 3, 1,
+    4, 5, 7, 4, 7, 6,
+    0, 1, 5, 0, 5, 4,
+    2, 6, 7, 2, 7, 3,
+    0, 4, 6, 0, 6, 2,
+    1, 3, 7, 1, 7, 5
+);
+
+void main() {
+    int corner = indices[gl_VertexIndex];
+    vec3 position = vec3(corner & 1, (corner >> 1) & 1, (corner >> 2) & 1) * 2.0 - 1.0;
+    fragDirection = position;
+
+    // only the rotation of the view, so the sky stays centred on the camera
+    vec4 clipPosition = ubo.proj * mat4(mat3(ubo.view)) * vec4(position, 1.0);
+    // z = w lands every fragment exactly on the far plane
+    gl_Position = clipPosition.xyww;
+}
@@ -1,9 +1,43 @@
 use ash::{vk, Entry};
+use cgmath::{Deg, Matrix4, Quaternion, Rotation3, Vector3};
 use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
 
-use super::utils::{QueueFamilyIndices, UniformBufferObject};
-use super::vertex::{Vertex, INDICES, VERTICES, generate_wireframe_vertices};
-use super::{buffers, commands, descriptors, images, instance, pipeline, swapchain};
+use super::utils::{DeviceFeatures, QueueFamilyIndices, UniformBufferObject};
+use super::mesh::{Aabb, Mesh, MeshData};
+use super::descriptors::MaterialDescriptors;
+use super::texture::Texture;
+use super::culling::{self, CullStats, Frustum};
+use super::gpu_culling::{GpuCulling, MAX_GPU_INSTANCES};
+use super::compute::{ComputeDispatch, ComputePass};
+use super::particles::ParticleSystem;
+use super::skybox::Skybox;
+use super::swapchain::{HdrSettings, OutputEncoding, OutputMode, PresentMode};
+use super::ibl::{IblMaps, LightingSettings};
+use super::overlay::Overlay;
+use super::gui::{GuiFrame, GuiRenderer};
+use super::profiler::GpuProfiler;
+use super::queries::{self, OcclusionQueries, StatisticsQuery};
+use super::screenshot::{Screenshot, ScreenshotError};
+use super::{buffers, commands, descriptors, gltf_loader, images, instance, obj_loader, pipeline, swapchain};
+
+use crate::scene::{NodeId, Scene};
+use crate::settings::RendererSettings;
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RenderMode {
+    Shaded,
+    // Triangle meshes drawn as their edges, needs fillModeNonSolid.
+    Wireframe,
+}
+
+pub struct DrawCommand {
+    pub mesh: usize,
+    pub material: usize,
+    pub transform: Matrix4<f32>,
+    pub color: [f32; 4],
+    pub object_id: u32,
+}
 
 pub struct VulkanApp {
     pub(super) entry: Entry,
@@ -16,31 +50,87 @@ pub struct VulkanApp {
     pub(super) device: ash::Device,
     pub(super) graphics_queue: vk::Queue,
     pub(super) present_queue: vk::Queue,
+    pub(super) compute_queue: vk::Queue,
     pub(super) swapchain_loader: ash::extensions::khr::Swapchain,
     pub(super) swapchain: vk::SwapchainKHR,
     pub(super) swapchain_images: Vec<vk::Image>,
     pub(super) swapchain_format: vk::Format,
     pub(super) swapchain_extent: vk::Extent2D,
+    // Requested mode; the swapchain falls back to FIFO if the surface stops offering it.
+    pub(super) present_mode: PresentMode,
+    pub(super) supported_present_modes: Vec<PresentMode>,
+    pub(super) output_mode: OutputMode,
+    pub(super) supported_output_modes: Vec<OutputMode>,
+    // What the current surface format needs from the shaders.
+    pub(super) output_encoding: OutputEncoding,
+    pub(super) hdr_settings: HdrSettings,
+    // Sample count of the color and depth attachments; 1 renders straight into the swapchain.
+    pub(super) msaa_samples: vk::SampleCountFlags,
     pub(super) swapchain_image_views: Vec<vk::ImageView>,
     pub(super) render_pass: vk::RenderPass,
     pub(super) pipeline_layout: vk::PipelineLayout,
     pub(super) graphics_pipeline: vk::Pipeline,
     pub(super) wireframe_pipeline: vk::Pipeline,
+    pub(super) indirect_pipeline: vk::Pipeline,
+    // Null when fillModeNonSolid is unavailable.
+    pub(super) triangle_wireframe_pipeline: vk::Pipeline,
+    pub(super) indirect_wireframe_pipeline: vk::Pipeline,
+    pub(super) render_mode: RenderMode,
+    pub(super) particle_pipeline: vk::Pipeline,
+    pub(super) particle_pipeline_layout: vk::PipelineLayout,
+    pub(super) skybox_pipeline: vk::Pipeline,
+    pub(super) skybox_pipeline_layout: vk::PipelineLayout,
+    pub(super) skybox: Option<Skybox>,
+    // Background color used when there is no skybox.
+    pub(super) clear_color: [f32; 4],
+    pub(super) ibl: IblMaps,
+    pub(super) ibl_descriptor_set_layout: vk::DescriptorSetLayout,
+    pub(super) lighting: LightingSettings,
+    pub(super) overlay: Overlay,
+    pub(super) overlay_pipeline: vk::Pipeline,
+    pub(super) overlay_pipeline_layout: vk::PipelineLayout,
+    pub(super) gui: GuiRenderer,
+    pub(super) pending_gui: Option<GuiFrame>,
+    pub(super) gui_pipeline: vk::Pipeline,
+    pub(super) gui_pipeline_layout: vk::PipelineLayout,
+    pub(super) profiler: GpuProfiler,
+    pub(super) statistics_query: StatisticsQuery,
+    pub(super) occlusion_queries: OcclusionQueries,
+    pub(super) occlusion_culling_enabled: bool,
+    pub(super) occlusion_pipeline: vk::Pipeline,
+    pub(super) occlusion_pipeline_layout: vk::PipelineLayout,
     pub(super) framebuffers: Vec<vk::Framebuffer>,
     pub(super) command_pool: vk::CommandPool,
+    pub(super) compute_command_pool: vk::CommandPool,
+    pub(super) compute_passes: Vec<ComputePass>,
+    pub(super) pending_dispatches: Vec<ComputeDispatch>,
+    pub(super) particles: ParticleSystem,
     pub(super) command_buffers: Vec<vk::CommandBuffer>,
     pub(super) image_available_semaphore: vk::Semaphore,
     pub(super) render_finished_semaphore: vk::Semaphore,
     pub(super) in_flight_fence: vk::Fence,
+    pub(super) frames_drawn: u64,
+    pub(super) screenshot_request: Option<std::path::PathBuf>,
+    // Copy recorded into the frame in flight, or why it could not be.
+    pub(super) screenshot: Option<Screenshot>,
+    pub(super) screenshot_error: Option<ScreenshotError>,
     pub framebuffer_resized: bool,
     pub(super) queue_family_indices: QueueFamilyIndices,
-    pub(super) vertex_buffer: vk::Buffer,
-    pub(super) vertex_buffer_memory: vk::DeviceMemory,
-    pub(super) wireframe_vertex_buffer: vk::Buffer,
-    pub(super) wireframe_vertex_buffer_memory: vk::DeviceMemory,
-    pub(super) wireframe_vertex_count: u32,
-    pub(super) index_buffer: vk::Buffer,
-    pub(super) index_buffer_memory: vk::DeviceMemory,
+    pub(super) meshes: Vec<Mesh>,
+    // The default scene's grid, rebuilt by set_grid_divisions.
+    pub(super) grid_mesh: Option<usize>,
+    pub(super) grid_divisions: u32,
+    pub(super) grid_color: [f32; 3],
+    pub scene: Scene,
+    pub(super) cull_stats: CullStats,
+    pub(super) device_features: DeviceFeatures,
+    pub(super) draw_indirect_count_loader: Option<ash::extensions::khr::DrawIndirectCount>,
+    pub(super) gpu_culling: GpuCulling,
+    pub(super) gpu_culling_enabled: bool,
+    pub(super) textures: Vec<Texture>,
+    pub(super) material_descriptor_set_layout: vk::DescriptorSetLayout,
+    pub(super) material_descriptors: MaterialDescriptors,
+    pub(super) material_descriptor_sets: Vec<vk::DescriptorSet>,
     pub(super) uniform_buffers: Vec<vk::Buffer>,
     pub(super) uniform_buffers_memory: Vec<vk::DeviceMemory>,
     pub(super) descriptor_set_layout: vk::DescriptorSetLayout,
@@ -49,12 +139,16 @@ pub struct VulkanApp {
     pub(super) depth_image: vk::Image,
     pub(super) depth_image_memory: vk::DeviceMemory,
     pub(super) depth_image_view: vk::ImageView,
+    // Multisampled render target, null handles when msaa_samples is 1.
+    pub(super) color_image: vk::Image,
+    pub(super) color_image_memory: vk::DeviceMemory,
+    pub(super) color_image_view: vk::ImageView,
 }
 
 impl VulkanApp {
-    pub fn new(window: &winit::window::Window) -> Self {
+    pub fn new(window: &winit::window::Window, settings: &RendererSettings) -> Self {
         let entry = unsafe { Entry::load().unwrap() };
-        let instance = instance::create_instance(&entry, window);
+        let instance = instance::create_instance(&entry, window, settings.validation);
         let (debug_utils_loader, debug_messenger) = instance::setup_debug_messenger(&entry, &instance);
         let surface = unsafe {
             ash_window::create_surface(
@@ -68,37 +162,42 @@ impl VulkanApp {
         };
         let surface_loader = ash::extensions::khr::Surface::new(&entry, &instance);
         let (physical_device, queue_family_indices) =
-            instance::pick_physical_device(&instance, &surface_loader, surface);
-        let (device, graphics_queue, present_queue) =
+            instance::pick_physical_device(&instance, &surface_loader, surface, settings.gpu.as_deref());
+        let (device, graphics_queue, present_queue, compute_queue, device_features) =
             instance::create_logical_device(&instance, physical_device, &queue_family_indices);
+        let draw_indirect_count_loader = device_features
+            .draw_indirect_count
+            .then(|| ash::extensions::khr::DrawIndirectCount::new(&instance, &device));
+        let gpu_culling = GpuCulling::new(
+            &instance,
+            &device,
+            physical_device,
+            device_features.draw_indirect_count && device_features.multi_draw_indirect,
+        );
 
-        let (vertex_buffer, vertex_buffer_memory) = buffers::create_vertex_buffer(
-            &instance,
-            &device,
-            physical_device,
-            &queue_family_indices,
-            &VERTICES,
-        );
-        let wire_vertices = generate_wireframe_vertices(24);
-        let wireframe_vertex_count = wire_vertices.len() as u32;
-        let (wireframe_vertex_buffer, wireframe_vertex_buffer_memory) =
-            buffers::create_vertex_buffer(
-                &instance,
-                &device,
-                physical_device,
-                &queue_family_indices,
-                &wire_vertices,
-            );
-        let (index_buffer, index_buffer_memory) = buffers::create_index_buffer(
-            &instance,
-            &device,
-            physical_device,
-            &queue_family_indices,
-            &INDICES,
-        );
+        let meshes = vec![
+            Mesh::cube(&instance, &device, physical_device, &queue_family_indices),
+            Mesh::wireframe_grid(
+                &instance,
+                &device,
+                physical_device,
+                &queue_family_indices,
+                settings.grid_divisions,
+                settings.grid_color,
+            ),
+        ];
 
         let swapchain_loader = ash::extensions::khr::Swapchain::new(&instance, &device);
-        let (swapchain, swapchain_format, swapchain_extent) = swapchain::create_swapchain(
+        let swapchain_support = swapchain::query_swapchain_support(&surface_loader, physical_device, surface);
+        let supported_present_modes = swapchain::supported_present_modes(&swapchain_support.present_modes);
+        let supported_output_modes = swapchain::supported_output_modes(&swapchain_support.formats);
+        let present_mode = if supported_present_modes.contains(&settings.present_mode) {
+            settings.present_mode
+        } else {
+            eprintln!("Present mode {} is not supported, using FIFO", settings.present_mode.name());
+            PresentMode::Fifo
+        };
+        let (swapchain, swapchain_format, swapchain_extent, output_encoding) = swapchain::create_swapchain(
             &instance,
             &device,
             physical_device,
@@ -107,43 +206,169 @@ impl VulkanApp {
             &queue_family_indices,
             &swapchain_loader,
             window,
+            present_mode,
+            OutputMode::Sdr,
         );
         let swapchain_images = unsafe { swapchain_loader.get_swapchain_images(swapchain).unwrap() };
         let swapchain_image_views =
             swapchain::create_image_views(&device, &swapchain_images, swapchain_format);
         let depth_format = images::find_depth_format(&instance, physical_device);
         let descriptor_set_layout = descriptors::create_descriptor_set_layout(&device);
-        let render_pass = pipeline::create_render_pass(&device, swapchain_format, depth_format);
+        let material_descriptor_set_layout = descriptors::create_material_descriptor_set_layout(&device);
+        // irradiance, prefiltered specular and BRDF lookup table
+        let ibl_descriptor_set_layout = descriptors::create_sampler_descriptor_set_layout(&device, 3);
+        let msaa_samples = images::choose_sample_count(&instance, physical_device, settings.msaa_samples);
+        if msaa_samples.as_raw() != settings.msaa_samples {
+            eprintln!(
+                "{}x MSAA is not supported, using {}x",
+                settings.msaa_samples,
+                msaa_samples.as_raw()
+            );
+        }
+        let render_pass = pipeline::create_render_pass(&device, swapchain_format, depth_format, msaa_samples);
         let (graphics_pipeline, pipeline_layout) = pipeline::create_graphics_pipeline(
             &device,
             render_pass,
             swapchain_extent,
-            descriptor_set_layout,
+            msaa_samples,
+            &[
+                descriptor_set_layout,
+                material_descriptor_set_layout,
+                gpu_culling.descriptor_set_layout,
+                ibl_descriptor_set_layout,
+            ],
         );
         let wireframe_pipeline = pipeline::create_wireframe_pipeline(
+            &device,
+            render_pass,
+            swapchain_extent,
+            msaa_samples,
+            pipeline_layout,
+        );
+        let indirect_pipeline = pipeline::create_indirect_pipeline(
             &device,
             render_pass,
             swapchain_extent,
+            msaa_samples,
             pipeline_layout,
+        );
+        let (triangle_wireframe_pipeline, indirect_wireframe_pipeline) = if device_features.fill_mode_non_solid {
+            (
+                pipeline::create_triangle_wireframe_pipeline(&device, render_pass, swapchain_extent, msaa_samples, pipeline_layout),
+                pipeline::create_indirect_wireframe_pipeline(&device, render_pass, swapchain_extent, msaa_samples, pipeline_layout),
+            )
+        } else {
+            (vk::Pipeline::null(), vk::Pipeline::null())
+        };
+        let (skybox_pipeline, skybox_pipeline_layout) = pipeline::create_skybox_pipeline(
+            &device,
+            render_pass,
+            swapchain_extent,
+            msaa_samples,
+            &[descriptor_set_layout, material_descriptor_set_layout],
         );
+        let (occlusion_pipeline, occlusion_pipeline_layout) = pipeline::create_occlusion_pipeline(
+            &device,
+            render_pass,
+            swapchain_extent,
+            msaa_samples,
+            &[descriptor_set_layout],
+        );
+        let (overlay_pipeline, overlay_pipeline_layout) = pipeline::create_overlay_pipeline(
+            &device,
+            render_pass,
+            swapchain_extent,
+            msaa_samples,
+            &[material_descriptor_set_layout],
+        );
+        let (gui_pipeline, gui_pipeline_layout) = pipeline::create_gui_pipeline(
+            &device,
+            render_pass,
+            swapchain_extent,
+            msaa_samples,
+            &[material_descriptor_set_layout],
+        );
         let (depth_image, depth_image_memory, depth_image_view) = images::create_depth_resources(
             &instance,
             &device,
             physical_device,
             swapchain_extent,
+            msaa_samples,
+        );
+        let (color_image, color_image_memory, color_image_view) = images::create_color_resources(
+            &instance,
+            &device,
+            physical_device,
+            swapchain_extent,
+            swapchain_format,
+            msaa_samples,
         );
         let framebuffers = pipeline::create_framebuffers(
             &device,
             &swapchain_image_views,
             depth_image_view,
+            color_image_view,
             render_pass,
             swapchain_extent,
         );
         let command_pool = commands::create_command_pool(&device, &queue_family_indices);
+        let compute_command_pool = commands::create_compute_command_pool(&device, &queue_family_indices);
         let command_buffers =
             commands::create_command_buffers(&device, command_pool, framebuffers.len());
         let (image_available_semaphore, render_finished_semaphore, in_flight_fence) =
             commands::create_sync_objects(&device);
+        let profiler = GpuProfiler::new(
+            &instance,
+            &device,
+            physical_device,
+            queue_family_indices.graphics_family.unwrap(),
+        );
+        let statistics_query = StatisticsQuery::new(&device, device_features.pipeline_statistics_query);
+        let occlusion_queries = OcclusionQueries::new(&device);
+
+        let mut compute_passes = Vec::new();
+        let particles = ParticleSystem::new(
+            &instance,
+            &device,
+            physical_device,
+            command_pool,
+            graphics_queue,
+            &mut compute_passes,
+        );
+        let (particle_pipeline, particle_pipeline_layout) = pipeline::create_particle_pipeline(
+            &device,
+            render_pass,
+            swapchain_extent,
+            msaa_samples,
+            &[descriptor_set_layout, particles.descriptor_set_layout],
+        );
+
+        let mut material_descriptors = MaterialDescriptors::new(&device, material_descriptor_set_layout);
+        let default_texture = Texture::white(
+            &instance,
+            &device,
+            physical_device,
+            command_pool,
+            graphics_queue,
+        );
+        let material_descriptor_sets = vec![material_descriptors.allocate(&device, &default_texture)];
+        let textures = vec![default_texture];
+        let ibl = IblMaps::placeholder(
+            &instance,
+            &device,
+            physical_device,
+            command_pool,
+            graphics_queue,
+            ibl_descriptor_set_layout,
+        );
+        let overlay = Overlay::new(
+            &instance,
+            &device,
+            physical_device,
+            command_pool,
+            graphics_queue,
+            &mut material_descriptors,
+        );
 
         let (uniform_buffers, uniform_buffers_memory) = buffers::create_uniform_buffers(
             &instance,
@@ -151,6 +376,12 @@ impl VulkanApp {
             physical_device,
             swapchain_images.len(),
         );
+
+        let mut scene = Scene::new();
+        let cube = scene.add_node("cube", None);
+        scene.node_mut(cube).mesh = Some(0);
+        let grid = scene.add_node("grid", Some(cube));
+        scene.node_mut(grid).mesh = Some(1);
         let (descriptor_pool, descriptor_sets) = descriptors::create_descriptor_pool(
             &device,
             swapchain_images.len(),
@@ -163,6 +394,7 @@ impl VulkanApp {
             &uniform_buffers,
             swapchain_images.len(),
         );
+        let [clear_r, clear_g, clear_b] = settings.clear_color;
 
         Self {
             entry,
@@ -175,31 +407,80 @@ impl VulkanApp {
             device,
             graphics_queue,
             present_queue,
+            compute_queue,
             swapchain_loader,
             swapchain,
             swapchain_images,
             swapchain_format,
             swapchain_extent,
+            present_mode,
+            supported_present_modes,
+            output_mode: OutputMode::Sdr,
+            supported_output_modes,
+            output_encoding,
+            hdr_settings: HdrSettings::default(),
+            msaa_samples,
             swapchain_image_views,
             render_pass,
             pipeline_layout,
             graphics_pipeline,
             wireframe_pipeline,
+            indirect_pipeline,
+            triangle_wireframe_pipeline,
+            indirect_wireframe_pipeline,
+            render_mode: RenderMode::Shaded,
+            particle_pipeline,
+            particle_pipeline_layout,
+            skybox_pipeline,
+            skybox_pipeline_layout,
+            skybox: None,
+            clear_color: [clear_r, clear_g, clear_b, 1.0],
+            ibl,
+            ibl_descriptor_set_layout,
+            lighting: LightingSettings::default(),
+            overlay,
+            overlay_pipeline,
+            overlay_pipeline_layout,
+            gui: GuiRenderer::new(),
+            pending_gui: None,
+            gui_pipeline,
+            gui_pipeline_layout,
+            profiler,
+            statistics_query,
+            occlusion_queries,
+            occlusion_culling_enabled: false,
+            occlusion_pipeline,
+            occlusion_pipeline_layout,
             framebuffers,
             command_pool,
+            compute_command_pool,
+            compute_passes,
+            pending_dispatches: Vec::new(),
+            particles,
             command_buffers,
             image_available_semaphore,
             render_finished_semaphore,
             in_flight_fence,
+            frames_drawn: 0,
+            screenshot_request: None,
+            screenshot: None,
+            screenshot_error: None,
             framebuffer_resized: false,
             queue_family_indices,
-            vertex_buffer,
-            vertex_buffer_memory,
-            wireframe_vertex_buffer,
-            wireframe_vertex_buffer_memory,
-            wireframe_vertex_count,
-            index_buffer,
-            index_buffer_memory,
+            meshes,
+            grid_mesh: Some(1),
+            grid_divisions: settings.grid_divisions,
+            grid_color: settings.grid_color,
+            scene,
+            cull_stats: CullStats::default(),
+            device_features,
+            draw_indirect_count_loader,
+            gpu_culling,
+            gpu_culling_enabled: false,
+            textures,
+            material_descriptor_set_layout,
+            material_descriptors,
+            material_descriptor_sets,
             uniform_buffers,
             uniform_buffers_memory,
             descriptor_set_layout,
@@ -208,7 +489,379 @@ impl VulkanApp {
             depth_image,
             depth_image_memory,
             depth_image_view,
+            color_image,
+            color_image_memory,
+            color_image_view,
+        }
+    }
+
+    pub fn add_mesh(&mut self, data: &MeshData) -> usize {
+        let mesh = Mesh::new(
+            &self.instance,
+            &self.device,
+            self.physical_device,
+            &self.queue_family_indices,
+            &data.vertices,
+            &data.indices,
+            data.topology,
+        );
+        self.meshes.push(mesh);
+        self.meshes.len() - 1
+    }
+
+    pub fn add_texture(&mut self, width: u32, height: u32, pixels: &[u8], format: vk::Format) -> usize {
+        let texture = Texture::from_rgba8(
+            &self.instance,
+            &self.device,
+            self.physical_device,
+            self.command_pool,
+            self.graphics_queue,
+            width,
+            height,
+            pixels,
+            format,
+        );
+        let descriptor_set = self.material_descriptors.allocate(&self.device, &texture);
+        self.textures.push(texture);
+        self.material_descriptor_sets.push(descriptor_set);
+        self.textures.len() - 1
+    }
+
+    // Drops every node, mesh and texture, keeping only the default white texture.
+    pub fn clear_scene(&mut self) {
+        unsafe {
+            self.device.device_wait_idle().unwrap();
+        }
+        self.scene.clear();
+        self.particles.clear();
+        self.occlusion_queries.clear();
+        self.grid_mesh = None;
+        for mesh in self.meshes.drain(..) {
+            mesh.destroy(&self.device);
         }
+        for texture in self.textures.drain(1..) {
+            texture.destroy(&self.device);
+        }
+        let descriptor_sets: Vec<vk::DescriptorSet> = self.material_descriptor_sets.drain(1..).collect();
+        self.material_descriptors.free(&self.device, &descriptor_sets);
+    }
+
+    pub fn load_obj(&mut self, path: &std::path::Path) -> Result<usize, obj_loader::ObjError> {
+        let meshes = obj_loader::load_obj(path)?;
+        self.clear_scene();
+        let name = path.file_stem().map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+        let root = self.scene.add_node(&name, None);
+        for data in meshes.iter() {
+            let mesh = self.add_mesh(data);
+            let node = self.scene.add_node(&data.name, Some(root));
+            self.scene.node_mut(node).mesh = Some(mesh);
+        }
+        Ok(meshes.len())
+    }
+
+    // Returns the world transform and projection of the file's first camera so the caller can
+    // adopt it.
+    pub fn load_gltf(
+        &mut self,
+        path: &std::path::Path,
+    ) -> Result<Option<(Matrix4<f32>, gltf_loader::ImportedCamera)>, gltf_loader::GltfError> {
+        let scene = gltf_loader::load_gltf(path)?;
+        for warning in scene.warnings.iter() {
+            eprintln!("{}: warning: {}", path.display(), warning);
+        }
+
+        self.clear_scene();
+        let mut texture_indices = Vec::with_capacity(scene.textures.len());
+        for texture in scene.textures.iter() {
+            texture_indices.push(texture.as_ref().map(|texture| {
+                self.add_texture(
+                    texture.width,
+                    texture.height,
+                    &texture.pixels,
+                    vk::Format::R8G8B8A8_SRGB,
+                )
+            }));
+        }
+
+        let mesh_indices: Vec<usize> = scene.meshes.iter().map(|data| self.add_mesh(data)).collect();
+        let materials: Vec<usize> = scene
+            .meshes
+            .iter()
+            .map(|data| {
+                data.material
+                    .and_then(|material| scene.materials[material].base_color_texture)
+                    .and_then(|image| texture_indices[image])
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        // glTF is Y-up, so everything hangs below a root that rotates it into our Z-up space
+        let name = path.file_stem().map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+        let root = self.scene.add_node(&name, None);
+        self.scene
+            .node_mut(root)
+            .set_rotation(Quaternion::from_angle_x(Deg(90.0)));
+
+        // importer nodes are stored parents-first, so parents are always mapped already
+        let mut node_ids: Vec<NodeId> = Vec::with_capacity(scene.nodes.len());
+        for imported in scene.nodes.iter() {
+            let parent = imported.parent.map_or(root, |parent| node_ids[parent]);
+            let id = self.scene.add_node(&imported.name, Some(parent));
+            let node = self.scene.node_mut(id);
+            node.set_translation(imported.translation);
+            node.set_rotation(imported.rotation);
+            node.set_scale(imported.scale);
+
+            if let [mesh] = imported.meshes[..] {
+                node.mesh = Some(mesh_indices[mesh]);
+                node.material = Some(materials[mesh]);
+            } else {
+                for &mesh in imported.meshes.iter() {
+                    let primitive = self.scene.add_node(&scene.meshes[mesh].name, Some(id));
+                    let node = self.scene.node_mut(primitive);
+                    node.mesh = Some(mesh_indices[mesh]);
+                    node.material = Some(materials[mesh]);
+                }
+            }
+            node_ids.push(id);
+        }
+
+        Ok(scene.camera())
+    }
+
+    pub fn load_model(
+        &mut self,
+        path: &std::path::Path,
+    ) -> Result<Option<(Matrix4<f32>, gltf_loader::ImportedCamera)>, Box<dyn std::error::Error>> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("gltf") | Some("glb") => Ok(self.load_gltf(path)?),
+            _ => {
+                self.load_obj(path)?;
+                Ok(None)
+            }
+        }
+    }
+
+    pub fn gpu_culling_enabled(&self) -> bool {
+        self.gpu_culling_enabled
+    }
+
+    // GPU culling writes firstInstance from the shader, which needs drawIndirectFirstInstance.
+    pub fn set_gpu_culling(&mut self, enabled: bool) {
+        if enabled && !self.device_features.draw_indirect_first_instance {
+            eprintln!("GPU culling is not supported: drawIndirectFirstInstance is unavailable");
+            return;
+        }
+        self.gpu_culling_enabled = enabled;
+    }
+
+    pub fn render_mode(&self) -> RenderMode {
+        self.render_mode
+    }
+
+    pub fn set_render_mode(&mut self, mode: RenderMode) {
+        if mode == RenderMode::Wireframe && !self.device_features.fill_mode_non_solid {
+            eprintln!("Wireframe rendering is not supported: fillModeNonSolid is unavailable");
+            return;
+        }
+        self.render_mode = mode;
+    }
+
+    pub fn present_mode(&self) -> PresentMode {
+        self.present_mode
+    }
+
+    pub fn supported_present_modes(&self) -> &[PresentMode] {
+        &self.supported_present_modes
+    }
+
+    // Takes effect when the swapchain is recreated at the end of the next frame.
+    pub fn set_present_mode(&mut self, mode: PresentMode) {
+        if !self.supported_present_modes.contains(&mode) {
+            eprintln!("Present mode {:?} is not supported by the surface", mode);
+            return;
+        }
+        if mode != self.present_mode {
+            self.present_mode = mode;
+            self.framebuffer_resized = true;
+        }
+    }
+
+    pub fn output_mode(&self) -> OutputMode {
+        self.output_mode
+    }
+
+    pub fn supported_output_modes(&self) -> &[OutputMode] {
+        &self.supported_output_modes
+    }
+
+    // The encoding actually in use, SDR when the surface lost the requested HDR format.
+    pub fn output_encoding(&self) -> OutputEncoding {
+        self.output_encoding
+    }
+
+    // Switches the surface format when the swapchain is recreated at the end of the next frame.
+    pub fn set_output_mode(&mut self, mode: OutputMode) {
+        if !self.supported_output_modes.contains(&mode) {
+            eprintln!("{} output is not supported by the surface", mode.name());
+            return;
+        }
+        if mode != self.output_mode {
+            self.output_mode = mode;
+            self.framebuffer_resized = true;
+        }
+    }
+
+    pub fn hdr_settings_mut(&mut self) -> &mut HdrSettings {
+        &mut self.hdr_settings
+    }
+
+    // Frames submitted so far; frames skipped for an out of date swapchain don't count.
+    pub fn frames_drawn(&self) -> u64 {
+        self.frames_drawn
+    }
+
+    // Whether frames keep changing without input, e.g. while particles simulate.
+    pub fn is_animating(&self) -> bool {
+        self.particles.emitter_count() > 0 || !self.pending_dispatches.is_empty()
+    }
+
+    pub fn grid_divisions(&self) -> u32 {
+        self.grid_divisions
+    }
+
+    // Rebuilds the default scene's grid; only remembered once a loaded scene has replaced it.
+    pub fn set_grid_divisions(&mut self, divisions: u32) {
+        self.grid_divisions = divisions.max(1);
+        let Some(index) = self.grid_mesh else { return };
+        let mesh = Mesh::wireframe_grid(
+            &self.instance,
+            &self.device,
+            self.physical_device,
+            &self.queue_family_indices,
+            self.grid_divisions,
+            self.grid_color,
+        );
+        unsafe {
+            self.device.device_wait_idle().unwrap();
+        }
+        std::mem::replace(&mut self.meshes[index], mesh).destroy(&self.device);
+    }
+
+    // World-space box around every visible mesh, None for an empty scene.
+    pub fn scene_bounds(&mut self) -> Option<(Vector3<f32>, Vector3<f32>)> {
+        self.scene.update_world_matrices();
+        self.scene
+            .visible_nodes()
+            .into_iter()
+            .filter_map(|id| {
+                let node = self.scene.node(id);
+                let mesh = self.meshes.get(node.mesh?)?;
+                Some(culling::transform_aabb(&mesh.bounds, node.world_matrix()))
+            })
+            .reduce(|a, b| Aabb {
+                min: Vector3::new(a.min.x.min(b.min.x), a.min.y.min(b.min.y), a.min.z.min(b.min.z)),
+                max: Vector3::new(a.max.x.max(b.max.x), a.max.y.max(b.max.y), a.max.z.max(b.max.z)),
+            })
+            .map(|bounds| (bounds.min, bounds.max))
+    }
+
+    // Culls on the CPU and returns the draws that survived. With GPU culling enabled, triangle
+    // meshes are uploaded to the culling pass instead and left for record_command_buffer.
+    fn collect_draws(&mut self, camera: &crate::camera::Camera) -> Vec<DrawCommand> {
+        self.scene.update_world_matrices();
+        let frustum = Frustum::from_matrix(camera.projection_matrix(self.aspect_ratio()) * camera.view_matrix());
+
+        // GPU results belong to the previous frame, whose fence draw_frame has already waited on
+        let gpu_tested = self.gpu_culling.instance_count();
+        let gpu_counts = self.gpu_culling.visible_counts(&self.device);
+        let gpu_drawn = gpu_counts.iter().sum();
+        let gpu_triangles = self
+            .gpu_culling
+            .batches
+            .iter()
+            .zip(gpu_counts)
+            .map(|(batch, count)| count as u64 * self.meshes.get(batch.mesh).map_or(0, Mesh::triangle_count))
+            .sum();
+        let mut stats = CullStats {
+            tested: gpu_tested,
+            culled: gpu_tested.saturating_sub(gpu_drawn),
+            drawn: gpu_drawn,
+            occluded: 0,
+            triangles: gpu_triangles,
+        };
+        // occlusion results also come from the previous frame
+        self.occlusion_queries.collect(&self.device);
+        let mut draws = Vec::new();
+        let mut gpu_draws = Vec::new();
+        let mut gpu_overflow = false;
+        for id in self.scene.visible_nodes() {
+            let node = self.scene.node(id);
+            let Some(mesh_index) = node.mesh else { continue };
+            let Some(mesh) = self.meshes.get(mesh_index) else { continue };
+            let transform = node.world_matrix();
+
+            if self.gpu_culling_enabled && mesh.topology == vk::PrimitiveTopology::TRIANGLE_LIST {
+                // the instance buffers are fixed size, anything past them is culled here instead
+                if gpu_draws.len() == MAX_GPU_INSTANCES {
+                    gpu_overflow = true;
+                } else {
+                    gpu_draws.push(DrawCommand {
+                        mesh: mesh_index,
+                        material: node.material.unwrap_or(0),
+                        transform,
+                        color: node.color,
+                        object_id: id as u32,
+                    });
+                    continue;
+                }
+            }
+
+            stats.tested += 1;
+            // the sphere test is cheaper and rejects most objects, the box test tightens the rest
+            let bounds = culling::transform_aabb(&mesh.bounds, transform);
+            let visible = frustum.intersects_sphere(&culling::transform_sphere(&mesh.bounding_sphere, transform))
+                && frustum.intersects_aabb(&bounds);
+            if !visible {
+                stats.culled += 1;
+                continue;
+            }
+            if self.occlusion_culling_enabled
+                && self.occlusion_queries.is_occluded(id as u32)
+                && !queries::contains_point(&bounds, camera.position)
+            {
+                stats.occluded += 1;
+                self.occlusion_queries
+                    .proxies
+                    .push((id as u32, queries::proxy_transform(&bounds)));
+                continue;
+            }
+
+            stats.drawn += 1;
+            stats.triangles += mesh.triangle_count();
+            draws.push(DrawCommand {
+                mesh: mesh_index,
+                material: node.material.unwrap_or(0),
+                transform,
+                color: node.color,
+                object_id: id as u32,
+            });
+        }
+
+        if gpu_overflow && !self.gpu_culling.overflow_warned {
+            eprintln!(
+                "More than {} objects for GPU culling, culling the rest on the CPU",
+                MAX_GPU_INSTANCES
+            );
+            self.gpu_culling.overflow_warned = true;
+        }
+        self.gpu_culling.upload(&self.device, &gpu_draws, &self.meshes, &frustum);
+        self.cull_stats = stats;
+        draws
     }
 
     pub fn draw_frame(&mut self, window: &winit::window::Window, camera: &crate::camera::Camera) {
@@ -238,6 +891,14 @@ impl VulkanApp {
                 Err(error) => panic!("Error acquiring swapchain image: {}", error),
             };
 
+            let draws = self.collect_draws(camera);
+            self.overlay.record_frame();
+            if self.overlay.enabled {
+                self.update_overlay(camera, &draws);
+            }
+            self.update_gui();
+            self.update_particles();
+            let dispatches = std::mem::take(&mut self.pending_dispatches);
             self.update_uniform_buffer(image_index as usize, camera);
 
             self.device
@@ -253,6 +914,8 @@ impl VulkanApp {
             self.record_command_buffer(
                 self.command_buffers[image_index as usize],
                 image_index as usize,
+                &draws,
+                &dispatches,
             );
 
             let wait_semaphores = [self.image_available_semaphore];
@@ -273,6 +936,7 @@ impl VulkanApp {
                     self.in_flight_fence,
                 )
                 .unwrap();
+            self.frames_drawn += 1;
 
             let swapchains = [self.swapchain];
             let present_info = vk::PresentInfoKHR::builder()
@@ -310,18 +974,39 @@ impl Drop for VulkanApp {
         unsafe {
             self.device.device_wait_idle().unwrap();
             self.cleanup_swapchain();
-            self.device.destroy_buffer(self.index_buffer, None);
-            self.device.free_memory(self.index_buffer_memory, None);
-            self.device.destroy_buffer(self.wireframe_vertex_buffer, None);
-            self.device.free_memory(self.wireframe_vertex_buffer_memory, None);
-            self.device.destroy_buffer(self.vertex_buffer, None);
-            self.device.free_memory(self.vertex_buffer_memory, None);
+            for mesh in self.meshes.iter() {
+                mesh.destroy(&self.device);
+            }
+            for texture in self.textures.iter() {
+                texture.destroy(&self.device);
+            }
+            if let Some(skybox) = &self.skybox {
+                skybox.texture.destroy(&self.device);
+            }
+            self.ibl.destroy(&self.device);
+            self.overlay.destroy(&self.device);
+            self.gui.destroy(&self.device);
+            self.profiler.destroy(&self.device);
+            self.statistics_query.destroy(&self.device);
+            self.occlusion_queries.destroy(&self.device);
+            self.device
+                .destroy_descriptor_set_layout(self.ibl_descriptor_set_layout, None);
+            self.gpu_culling.destroy(&self.device);
+            for pass in self.compute_passes.iter() {
+                pass.destroy(&self.device);
+            }
+            self.particles.destroy(&self.device);
+            self.material_descriptors.destroy(&self.device);
+            self.device
+                .destroy_descriptor_set_layout(self.material_descriptor_set_layout, None);
             self.device
                 .destroy_semaphore(self.image_available_semaphore, None);
             self.device
                 .destroy_semaphore(self.render_finished_semaphore, None);
             self.device.destroy_fence(self.in_flight_fence, None);
             self.device.destroy_command_pool(self.command_pool, None);
+            self.device
+                .destroy_command_pool(self.compute_command_pool, None);
             self.device.destroy_image_view(self.depth_image_view, None);
             self.device.destroy_image(self.depth_image, None);
             self.device.free_memory(self.depth_image_memory, None);
@@ -1,18 +1,17 @@
 use ash::vk;
 
-use cgmath::{Matrix4, SquareMatrix};
 
 
-use super::{utils::{QueueFamilyIndices, UniformBufferObject}, vertex::{Vertex, INDICES}, VulkanApp};
+use super::{utils::{QueueFamilyIndices, UniformBufferObject}, vertex::Vertex, VulkanApp};
 
-pub fn create_index_buffer(
+pub fn create_index_buffer<T: Copy>(
     instance: &ash::Instance,
     device: &ash::Device,
     pdevice: vk::PhysicalDevice,
     _indices: &QueueFamilyIndices,
-    data: &[u16],
+    data: &[T],
 ) -> (vk::Buffer, vk::DeviceMemory) {
-    let buffer_size = (std::mem::size_of::<u16>() * INDICES.len()) as vk::DeviceSize;
+    let buffer_size = (std::mem::size_of::<T>() * data.len()) as vk::DeviceSize;
     let (buffer, buffer_memory) = create_buffer(
         instance,
         device,
@@ -26,7 +25,7 @@ pub fn create_index_buffer(
         let data_ptr = device
             .map_memory(buffer_memory, 0, buffer_size, vk::MemoryMapFlags::empty())
             .unwrap();
-        let mut align = ash::util::Align::new(data_ptr, std::mem::align_of::<u16>() as _, buffer_size);
+        let mut align = ash::util::Align::new(data_ptr, std::mem::align_of::<T>() as _, buffer_size);
         align.copy_from_slice(data);
         device.unmap_memory(buffer_memory);
     }
@@ -141,18 +140,32 @@ pub fn create_uniform_buffers(
 }
 
 impl VulkanApp {
+    pub(super) fn aspect_ratio(&self) -> f32 {
+        self.swapchain_extent.width as f32 / self.swapchain_extent.height as f32
+    }
+
     pub fn update_uniform_buffer(&self, current_image: usize, camera: &crate::camera::Camera) {
-        let model = Matrix4::identity();
         let view = camera.view_matrix();
-        let mut proj = cgmath::perspective(
-            cgmath::Deg(45.0),
-            self.swapchain_extent.width as f32 / self.swapchain_extent.height as f32,
-            0.1,
-            100.0,
-        );
-        proj[1][1] *= -1.0;
-
-        let ubo = UniformBufferObject { model, view, proj };
+        let proj = camera.projection_matrix(self.aspect_ratio());
+
+        let lighting = &self.lighting;
+        let ubo = UniformBufferObject {
+            view,
+            proj,
+            camera_position: camera.position.extend(1.0).into(),
+            lighting: [
+                self.ibl.loaded as u32 as f32,
+                lighting.intensity,
+                lighting.roughness,
+                lighting.metallic,
+            ],
+            output: [
+                self.output_encoding.shader_value(),
+                self.hdr_settings.paper_white_nits,
+                self.hdr_settings.peak_nits,
+                0.0,
+            ],
+        };
 
         unsafe {
             let data_ptr = self
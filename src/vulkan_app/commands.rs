@@ -1,6 +1,6 @@
 use ash::{vk};
 
-use super::{utils::QueueFamilyIndices, vertex::{INDICES}, VulkanApp};
+use super::{app::{DrawCommand, RenderMode}, compute::ComputeDispatch, utils::{ObjectPushConstants, QueueFamilyIndices}, VulkanApp};
 
 pub fn create_command_pool(device: &ash::Device, indices: &QueueFamilyIndices) -> vk::CommandPool {
     let pool_info = vk::CommandPoolCreateInfo::builder()
@@ -9,6 +9,14 @@ pub fn create_command_pool(device: &ash::Device, indices: &QueueFamilyIndices) -
     unsafe { device.create_command_pool(&pool_info, None).unwrap() }
 }
 
+// Pool for the dedicated compute queue, or the graphics family if there is none.
+pub fn create_compute_command_pool(device: &ash::Device, indices: &QueueFamilyIndices) -> vk::CommandPool {
+    let pool_info = vk::CommandPoolCreateInfo::builder()
+        .queue_family_index(indices.compute_family.or(indices.graphics_family).unwrap())
+        .flags(vk::CommandPoolCreateFlags::empty());
+    unsafe { device.create_command_pool(&pool_info, None).unwrap() }
+}
+
 pub fn create_command_buffers(
     device: &ash::Device,
     command_pool: vk::CommandPool,
@@ -21,17 +29,74 @@ pub fn create_command_buffers(
     unsafe { device.allocate_command_buffers(&alloc_info).unwrap() }
 }
 
+pub fn begin_single_time_commands(
+    device: &ash::Device,
+    command_pool: vk::CommandPool,
+) -> vk::CommandBuffer {
+    let alloc_info = vk::CommandBufferAllocateInfo::builder()
+        .command_pool(command_pool)
+        .level(vk::CommandBufferLevel::PRIMARY)
+        .command_buffer_count(1);
+    let command_buffer = unsafe { device.allocate_command_buffers(&alloc_info).unwrap()[0] };
+
+    let begin_info = vk::CommandBufferBeginInfo::builder()
+        .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+    unsafe {
+        device
+            .begin_command_buffer(command_buffer, &begin_info)
+            .unwrap();
+    }
+
+    command_buffer
+}
+
+pub fn end_single_time_commands(
+    device: &ash::Device,
+    command_pool: vk::CommandPool,
+    queue: vk::Queue,
+    command_buffer: vk::CommandBuffer,
+) {
+    unsafe {
+        device.end_command_buffer(command_buffer).unwrap();
+        let submit_info = vk::SubmitInfo::builder()
+            .command_buffers(std::slice::from_ref(&command_buffer));
+        device
+            .queue_submit(queue, std::slice::from_ref(&submit_info), vk::Fence::null())
+            .unwrap();
+        device.queue_wait_idle(queue).unwrap();
+        device.free_command_buffers(command_pool, std::slice::from_ref(&command_buffer));
+    }
+}
+
 impl VulkanApp {
-    pub fn record_command_buffer(&self, command_buffer: vk::CommandBuffer, image_index: usize) {
+    pub fn record_command_buffer(
+        &mut self,
+        command_buffer: vk::CommandBuffer,
+        image_index: usize,
+        draws: &[DrawCommand],
+        dispatches: &[ComputeDispatch],
+    ) {
         let begin_info = vk::CommandBufferBeginInfo::builder();
         unsafe {
             self.device
                 .begin_command_buffer(command_buffer, &begin_info)
                 .unwrap();
         }
+        self.profiler.begin_frame(&self.device, command_buffer);
+        self.statistics_query.begin_frame(&self.device, command_buffer);
+        if self.occlusion_culling_enabled {
+            self.occlusion_queries.reset(&self.device, command_buffer);
+        }
+        self.profiler.begin_scope(&self.device, command_buffer, "frame");
+        self.profiler.begin_scope(&self.device, command_buffer, "compute");
+        self.record_dispatches(command_buffer, dispatches);
+        self.profiler.end_scope(&self.device, command_buffer);
+        self.profiler.begin_scope(&self.device, command_buffer, "culling");
+        self.gpu_culling.record_cull(&self.device, command_buffer);
+        self.profiler.end_scope(&self.device, command_buffer);
 
         let clear_color = vk::ClearValue {
-            color: vk::ClearColorValue { float32: [0.0, 0.0, 0.0, 1.0] },
+            color: vk::ClearColorValue { float32: self.clear_color },
         };
         let depth_clear = vk::ClearValue {
             depth_stencil: vk::ClearDepthStencilValue { depth: 1.0, stencil: 0 },
@@ -46,49 +111,187 @@ impl VulkanApp {
             })
             .clear_values(&clear_values);
 
+        // the statistics cover every draw of the main pass
+        self.statistics_query.begin(&self.device, command_buffer);
         unsafe {
             self.device.cmd_begin_render_pass(
                 command_buffer,
                 &render_pass_info,
                 vk::SubpassContents::INLINE,
             );
-            self.device.cmd_bind_pipeline(
-                command_buffer,
-                vk::PipelineBindPoint::GRAPHICS,
-                self.graphics_pipeline,
-            );
-            let vertex_buffers = [self.vertex_buffer];
-            let offsets = [0];
-            self.device
-                .cmd_bind_vertex_buffers(command_buffer, 0, &vertex_buffers, &offsets);
-            self.device.cmd_bind_index_buffer(
+            self.profiler.begin_scope(&self.device, command_buffer, "opaque");
+            self.device.cmd_bind_descriptor_sets(
                 command_buffer,
-                self.index_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
                 0,
-                vk::IndexType::UINT16,
+                &[self.descriptor_sets[image_index]],
+                &[],
             );
             self.device.cmd_bind_descriptor_sets(
                 command_buffer,
                 vk::PipelineBindPoint::GRAPHICS,
                 self.pipeline_layout,
-                0,
-                &[self.descriptor_sets[image_index]],
+                3,
+                &[self.ibl.descriptor_set],
                 &[],
             );
-            self.device
-                .cmd_draw_indexed(command_buffer, INDICES.len() as u32, 1, 0, 0, 0);
-            self.device.cmd_bind_pipeline(
-                command_buffer,
-                vk::PipelineBindPoint::GRAPHICS,
-                self.wireframe_pipeline,
-            );
-            let wire_buffers = [self.wireframe_vertex_buffer];
-            let offsets = [0];
-            self.device
-                .cmd_bind_vertex_buffers(command_buffer, 0, &wire_buffers, &offsets);
-            self.device.cmd_draw(command_buffer, self.wireframe_vertex_count, 1, 0, 0);
+            for draw in draws.iter() {
+                let mesh = &self.meshes[draw.mesh];
+                let pipeline = if mesh.topology == vk::PrimitiveTopology::LINE_LIST {
+                    self.wireframe_pipeline
+                } else if self.render_mode == RenderMode::Wireframe {
+                    self.triangle_wireframe_pipeline
+                } else {
+                    self.graphics_pipeline
+                };
+                self.device.cmd_bind_pipeline(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    pipeline,
+                );
+                self.device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.pipeline_layout,
+                    1,
+                    &[self.material_descriptor_sets[draw.material]],
+                    &[],
+                );
+                let push_constants = ObjectPushConstants {
+                    model: draw.transform,
+                    color: draw.color,
+                    object_id: draw.object_id,
+                };
+                self.device.cmd_push_constants(
+                    command_buffer,
+                    self.pipeline_layout,
+                    vk::ShaderStageFlags::VERTEX,
+                    0,
+                    std::slice::from_raw_parts(
+                        &push_constants as *const ObjectPushConstants as *const u8,
+                        std::mem::size_of::<ObjectPushConstants>(),
+                    ),
+                );
+                let vertex_buffers = [mesh.vertex_buffer];
+                let offsets = [0];
+                self.device
+                    .cmd_bind_vertex_buffers(command_buffer, 0, &vertex_buffers, &offsets);
+                self.device.cmd_bind_index_buffer(
+                    command_buffer,
+                    mesh.index_buffer,
+                    0,
+                    mesh.index_type,
+                );
+                let queried = self.occlusion_culling_enabled
+                    && self.occlusion_queries.begin(&self.device, command_buffer, draw.object_id);
+                self.device
+                    .cmd_draw_indexed(command_buffer, mesh.index_count, 1, 0, 0, 0);
+                if queried {
+                    self.occlusion_queries.end(&self.device, command_buffer);
+                }
+            }
+            self.record_indirect_draws(command_buffer);
+            // after every opaque draw, so the proxies are tested against the finished depth buffer
+            self.record_occlusion_proxies(command_buffer, image_index);
+            self.profiler.end_scope(&self.device, command_buffer);
+            self.profiler.begin_scope(&self.device, command_buffer, "skybox");
+            self.record_skybox(command_buffer, image_index);
+            self.profiler.end_scope(&self.device, command_buffer);
+            self.profiler.begin_scope(&self.device, command_buffer, "particles");
+            self.record_particles(command_buffer, image_index);
+            self.profiler.end_scope(&self.device, command_buffer);
+            self.profiler.begin_scope(&self.device, command_buffer, "ui");
+            self.record_overlay(command_buffer);
+            self.record_gui(command_buffer);
+            self.profiler.end_scope(&self.device, command_buffer);
             self.device.cmd_end_render_pass(command_buffer);
+            self.statistics_query.end(&self.device, command_buffer);
+            self.profiler.end_scope(&self.device, command_buffer);
+            self.record_screenshot(command_buffer, image_index);
             self.device.end_command_buffer(command_buffer).unwrap();
+        }
+    }
+}
+
+impl VulkanApp {
+    // Draws the batches prepared by the GPU culling pass. Set 0 must already be bound.
+    fn record_indirect_draws(&self, command_buffer: vk::CommandBuffer) {
+        let culling = &self.gpu_culling;
+        if culling.batches.is_empty() {
+            return;
+        }
+
+        let stride = std::mem::size_of::<vk::DrawIndexedIndirectCommand>() as u32;
+        let pipeline = match self.render_mode {
+            RenderMode::Shaded => self.indirect_pipeline,
+            RenderMode::Wireframe => self.indirect_wireframe_pipeline,
+        };
+        unsafe {
+            self.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipeline,
+            );
+            self.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                2,
+                &[culling.descriptor_set],
+                &[],
+            );
+            for (i, batch) in culling.batches.iter().enumerate() {
+                let mesh = &self.meshes[batch.mesh];
+                self.device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.pipeline_layout,
+                    1,
+                    &[self.material_descriptor_sets[batch.material]],
+                    &[],
+                );
+                self.device
+                    .cmd_bind_vertex_buffers(command_buffer, 0, &[mesh.vertex_buffer], &[0]);
+                self.device.cmd_bind_index_buffer(
+                    command_buffer,
+                    mesh.index_buffer,
+                    0,
+                    mesh.index_type,
+                );
+
+                let offset = (batch.first * stride) as vk::DeviceSize;
+                match &self.draw_indirect_count_loader {
+                    Some(loader) if culling.compact => loader.cmd_draw_indexed_indirect_count(
+                        command_buffer,
+                        culling.draw_command_buffer,
+                        offset,
+                        culling.count_buffer,
+                        (i * std::mem::size_of::<u32>()) as vk::DeviceSize,
+                        batch.count,
+                        stride,
+                    ),
+                    _ if self.device_features.multi_draw_indirect => self.device.cmd_draw_indexed_indirect(
+                        command_buffer,
+                        culling.draw_command_buffer,
+                        offset,
+                        batch.count,
+                        stride,
+                    ),
+                    // culled commands have an instance count of zero, so drawing them is harmless
+                    _ => {
+                        for draw in 0..batch.count {
+                            self.device.cmd_draw_indexed_indirect(
+                                command_buffer,
+                                culling.draw_command_buffer,
+                                offset + (draw * stride) as vk::DeviceSize,
+                                1,
+                                stride,
+                            );
+                        }
+                    }
+                }
+            }
         }
     }
 }
@@ -0,0 +1,326 @@
+use ash::vk;
+
+use super::{commands, descriptors, pipeline, VulkanApp};
+
+#[derive(Clone, Copy)]
+pub enum ComputeBinding {
+    StorageBuffer(vk::Buffer),
+    // The image must be in GENERAL layout whenever the pass runs.
+    StorageImage { image: vk::Image, view: vk::ImageView },
+    // Read through a sampler; the image must be in SHADER_READ_ONLY_OPTIMAL layout.
+    SampledImage { image: vk::Image, view: vk::ImageView, sampler: vk::Sampler },
+}
+
+// A compute shader together with the resources it is bound to, one binding per entry in set 0.
+pub struct ComputePass {
+    pub pipeline: vk::Pipeline,
+    pub pipeline_layout: vk::PipelineLayout,
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    pub descriptor_pool: vk::DescriptorPool,
+    pub descriptor_set: vk::DescriptorSet,
+    pub bindings: Vec<ComputeBinding>,
+    pub push_constant_size: u32,
+}
+
+pub struct ComputeDispatch {
+    pub pass: usize,
+    pub push_constants: Vec<u8>,
+    pub group_count: [u32; 3],
+}
+
+impl ComputePass {
+    pub fn new(
+        device: &ash::Device,
+        shader_code: &[u8],
+        bindings: &[ComputeBinding],
+        push_constant_size: u32,
+    ) -> Self {
+        let descriptor_set_layout = descriptors::create_compute_descriptor_set_layout(device, bindings);
+        let (descriptor_pool, descriptor_set) =
+            descriptors::create_compute_descriptor_set(device, descriptor_set_layout, bindings);
+        let (pipeline, pipeline_layout) = pipeline::create_compute_pipeline(
+            device,
+            shader_code,
+            std::slice::from_ref(&descriptor_set_layout),
+            push_constant_size,
+        );
+
+        Self {
+            pipeline,
+            pipeline_layout,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_set,
+            bindings: bindings.to_vec(),
+            push_constant_size,
+        }
+    }
+
+    pub fn record(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        push_constants: &[u8],
+        group_count: [u32; 3],
+    ) {
+        assert_eq!(
+            push_constants.len() as u32,
+            self.push_constant_size,
+            "Push constant data does not match the compute pass"
+        );
+
+        unsafe {
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, self.pipeline);
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline_layout,
+                0,
+                &[self.descriptor_set],
+                &[],
+            );
+            if !push_constants.is_empty() {
+                device.cmd_push_constants(
+                    command_buffer,
+                    self.pipeline_layout,
+                    vk::ShaderStageFlags::COMPUTE,
+                    0,
+                    push_constants,
+                );
+            }
+            device.cmd_dispatch(command_buffer, group_count[0], group_count[1], group_count[2]);
+        }
+    }
+
+    // Queue family ownership transfer barriers for every bound resource.
+    fn ownership_barriers(
+        &self,
+        src_family: u32,
+        dst_family: u32,
+        src_access_mask: vk::AccessFlags,
+        dst_access_mask: vk::AccessFlags,
+    ) -> (Vec<vk::BufferMemoryBarrier>, Vec<vk::ImageMemoryBarrier>) {
+        let mut buffer_barriers = Vec::new();
+        let mut image_barriers = Vec::new();
+        for binding in self.bindings.iter() {
+            let (image, layout) = match *binding {
+                ComputeBinding::StorageBuffer(buffer) => {
+                    buffer_barriers.push(
+                        vk::BufferMemoryBarrier::builder()
+                            .src_access_mask(src_access_mask)
+                            .dst_access_mask(dst_access_mask)
+                            .src_queue_family_index(src_family)
+                            .dst_queue_family_index(dst_family)
+                            .buffer(buffer)
+                            .offset(0)
+                            .size(vk::WHOLE_SIZE)
+                            .build(),
+                    );
+                    continue;
+                }
+                ComputeBinding::StorageImage { image, .. } => (image, vk::ImageLayout::GENERAL),
+                ComputeBinding::SampledImage { image, .. } => (image, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+            };
+            image_barriers.push(
+                vk::ImageMemoryBarrier::builder()
+                    .src_access_mask(src_access_mask)
+                    .dst_access_mask(dst_access_mask)
+                    .old_layout(layout)
+                    .new_layout(layout)
+                    .src_queue_family_index(src_family)
+                    .dst_queue_family_index(dst_family)
+                    .image(image)
+                    .subresource_range(vk::ImageSubresourceRange {
+                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        base_mip_level: 0,
+                        level_count: vk::REMAINING_MIP_LEVELS,
+                        base_array_layer: 0,
+                        layer_count: vk::REMAINING_ARRAY_LAYERS,
+                    })
+                    .build(),
+            );
+        }
+        (buffer_barriers, image_barriers)
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+            device.destroy_descriptor_pool(self.descriptor_pool, None);
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+    }
+}
+
+impl VulkanApp {
+    pub fn create_compute_pass(
+        &mut self,
+        shader_code: &[u8],
+        bindings: &[ComputeBinding],
+        push_constant_size: u32,
+    ) -> usize {
+        let pass = ComputePass::new(&self.device, shader_code, bindings, push_constant_size);
+        self.compute_passes.push(pass);
+        self.compute_passes.len() - 1
+    }
+
+    // Recorded into the next frame's command buffer ahead of the render pass.
+    pub fn queue_dispatch(&mut self, pass: usize, push_constants: &[u8], group_count: [u32; 3]) {
+        self.pending_dispatches.push(ComputeDispatch {
+            pass,
+            push_constants: push_constants.to_vec(),
+            group_count,
+        });
+    }
+
+    // One-off dispatch that blocks until it has finished, e.g. for precomputing lookup tables.
+    // Uses the dedicated compute queue when there is one, moving the pass's resources over to
+    // its queue family and back.
+    pub fn run_compute(&self, pass: usize, push_constants: &[u8], group_count: [u32; 3]) {
+        let pass = &self.compute_passes[pass];
+        let graphics_family = self.queue_family_indices.graphics_family.unwrap();
+        let compute_family = match self.queue_family_indices.compute_family {
+            Some(family) if family != graphics_family => family,
+            _ => {
+                let command_buffer = commands::begin_single_time_commands(&self.device, self.command_pool);
+                pass.record(&self.device, command_buffer, push_constants, group_count);
+                commands::end_single_time_commands(&self.device, self.command_pool, self.graphics_queue, command_buffer);
+                return;
+            }
+        };
+
+        unsafe {
+            let (buffer_barriers, image_barriers) = pass.ownership_barriers(
+                graphics_family,
+                compute_family,
+                vk::AccessFlags::MEMORY_WRITE,
+                vk::AccessFlags::empty(),
+            );
+            let command_buffer = commands::begin_single_time_commands(&self.device, self.command_pool);
+            self.device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::DependencyFlags::empty(),
+                &[],
+                &buffer_barriers,
+                &image_barriers,
+            );
+            commands::end_single_time_commands(&self.device, self.command_pool, self.graphics_queue, command_buffer);
+
+            let (acquire_buffers, acquire_images) = pass.ownership_barriers(
+                graphics_family,
+                compute_family,
+                vk::AccessFlags::empty(),
+                vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+            );
+            let (release_buffers, release_images) = pass.ownership_barriers(
+                compute_family,
+                graphics_family,
+                vk::AccessFlags::SHADER_WRITE,
+                vk::AccessFlags::empty(),
+            );
+            let command_buffer = commands::begin_single_time_commands(&self.device, self.compute_command_pool);
+            self.device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &acquire_buffers,
+                &acquire_images,
+            );
+            pass.record(&self.device, command_buffer, push_constants, group_count);
+            self.device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::DependencyFlags::empty(),
+                &[],
+                &release_buffers,
+                &release_images,
+            );
+            commands::end_single_time_commands(
+                &self.device,
+                self.compute_command_pool,
+                self.compute_queue,
+                command_buffer,
+            );
+
+            let (buffer_barriers, image_barriers) = pass.ownership_barriers(
+                compute_family,
+                graphics_family,
+                vk::AccessFlags::empty(),
+                vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE,
+            );
+            let command_buffer = commands::begin_single_time_commands(&self.device, self.command_pool);
+            self.device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::DependencyFlags::empty(),
+                &[],
+                &buffer_barriers,
+                &image_barriers,
+            );
+            commands::end_single_time_commands(&self.device, self.command_pool, self.graphics_queue, command_buffer);
+        }
+    }
+
+    // Records the queued dispatches in order. Each one sees the writes of the previous ones and
+    // everything written is visible to vertex input, indirect draws and shaders afterwards.
+    pub(super) fn record_dispatches(&self, command_buffer: vk::CommandBuffer, dispatches: &[ComputeDispatch]) {
+        if dispatches.is_empty() {
+            return;
+        }
+
+        let between_barrier = vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+        for (i, dispatch) in dispatches.iter().enumerate() {
+            if i > 0 {
+                unsafe {
+                    self.device.cmd_pipeline_barrier(
+                        command_buffer,
+                        vk::PipelineStageFlags::COMPUTE_SHADER,
+                        vk::PipelineStageFlags::COMPUTE_SHADER,
+                        vk::DependencyFlags::empty(),
+                        std::slice::from_ref(&between_barrier),
+                        &[],
+                        &[],
+                    );
+                }
+            }
+            self.compute_passes[dispatch.pass].record(
+                &self.device,
+                command_buffer,
+                &dispatch.push_constants,
+                dispatch.group_count,
+            );
+        }
+
+        let graphics_barrier = vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(
+                vk::AccessFlags::INDIRECT_COMMAND_READ
+                    | vk::AccessFlags::VERTEX_ATTRIBUTE_READ
+                    | vk::AccessFlags::INDEX_READ
+                    | vk::AccessFlags::SHADER_READ,
+            );
+        unsafe {
+            self.device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::DRAW_INDIRECT
+                    | vk::PipelineStageFlags::VERTEX_INPUT
+                    | vk::PipelineStageFlags::VERTEX_SHADER
+                    | vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::DependencyFlags::empty(),
+                std::slice::from_ref(&graphics_barrier),
+                &[],
+                &[],
+            );
+        }
+    }
+}
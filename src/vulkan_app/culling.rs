@@ -0,0 +1,227 @@
+use cgmath::{InnerSpace, Matrix, Matrix4, Vector3, Vector4};
+
+use super::mesh::{Aabb, BoundingSphere};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CullStats {
+    pub tested: u32,
+    pub culled: u32,
+    pub drawn: u32,
+    // Frustum-visible objects skipped because last frame's occlusion query passed no samples.
+    pub occluded: u32,
+    // Triangles of the drawn objects; line meshes add none.
+    pub triangles: u64,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Plane {
+    pub normal: Vector3<f32>,
+    pub distance: f32,
+}
+
+impl Plane {
+    fn from_coefficients(coefficients: Vector4<f32>) -> Self {
+        let normal = coefficients.truncate();
+        let length = normal.magnitude();
+        Self {
+            normal: normal / length,
+            distance: coefficients.w / length,
+        }
+    }
+
+    // Positive on the inside of the frustum.
+    pub fn signed_distance(&self, point: Vector3<f32>) -> f32 {
+        self.normal.dot(point) + self.distance
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Frustum {
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    // Gribb/Hartmann extraction. Expects an OpenGL style -w..w depth range, which is what
+    // cgmath::perspective produces; the Y flip only swaps the top and bottom planes.
+    pub fn from_matrix(view_proj: Matrix4<f32>) -> Self {
+        let row0 = view_proj.row(0);
+        let row1 = view_proj.row(1);
+        let row2 = view_proj.row(2);
+        let row3 = view_proj.row(3);
+
+        Self {
+            planes: [
+                Plane::from_coefficients(row3 + row0),
+                Plane::from_coefficients(row3 - row0),
+                Plane::from_coefficients(row3 + row1),
+                Plane::from_coefficients(row3 - row1),
+                Plane::from_coefficients(row3 + row2),
+                Plane::from_coefficients(row3 - row2),
+            ],
+        }
+    }
+
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.signed_distance(sphere.center) >= -sphere.radius)
+    }
+
+    // Conservative: boxes near a frustum corner can pass even when fully outside.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        let center = aabb.center();
+        let extents = aabb.extents();
+        self.planes.iter().all(|plane| {
+            let radius = extents.x * plane.normal.x.abs()
+                + extents.y * plane.normal.y.abs()
+                + extents.z * plane.normal.z.abs();
+            plane.signed_distance(center) >= -radius
+        })
+    }
+}
+
+// World-space box enclosing a transformed local box (Arvo's method).
+pub fn transform_aabb(aabb: &Aabb, transform: Matrix4<f32>) -> Aabb {
+    let center = (transform * aabb.center().extend(1.0)).truncate();
+    let extents = aabb.extents();
+    let world_extents = Vector3::new(
+        transform.x.x.abs() * extents.x + transform.y.x.abs() * extents.y + transform.z.x.abs() * extents.z,
+        transform.x.y.abs() * extents.x + transform.y.y.abs() * extents.y + transform.z.y.abs() * extents.z,
+        transform.x.z.abs() * extents.x + transform.y.z.abs() * extents.y + transform.z.z.abs() * extents.z,
+    );
+    Aabb {
+        min: center - world_extents,
+        max: center + world_extents,
+    }
+}
+
+pub fn transform_sphere(sphere: &BoundingSphere, transform: Matrix4<f32>) -> BoundingSphere {
+    let center = (transform * sphere.center.extend(1.0)).truncate();
+    let scale = transform
+        .x
+        .truncate()
+        .magnitude()
+        .max(transform.y.truncate().magnitude())
+        .max(transform.z.truncate().magnitude());
+    BoundingSphere {
+        center,
+        radius: sphere.radius * scale,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Deg, Matrix4, Point3, Vector3};
+
+    use super::*;
+
+    // Eye five units back along -Y looking at the origin, Z up: 90 degrees square, near 1, far 100.
+    fn frustum() -> Frustum {
+        let projection = cgmath::perspective(Deg(90.0), 1.0, 1.0, 100.0);
+        let view = Matrix4::look_at_rh(
+            Point3::new(0.0, -5.0, 0.0),
+            Point3::new(0.0, 0.0, 0.0),
+            Vector3::new(0.0, 0.0, 1.0),
+        );
+        Frustum::from_matrix(projection * view)
+    }
+
+    fn sphere(x: f32, y: f32, z: f32, radius: f32) -> BoundingSphere {
+        BoundingSphere {
+            center: Vector3::new(x, y, z),
+            radius,
+        }
+    }
+
+    fn cube(x: f32, y: f32, z: f32, half: f32) -> Aabb {
+        Aabb {
+            min: Vector3::new(x - half, y - half, z - half),
+            max: Vector3::new(x + half, y + half, z + half),
+        }
+    }
+
+    fn assert_close(a: Vector3<f32>, b: Vector3<f32>) {
+        assert!((a - b).magnitude() < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn planes_from_perspective_look_at() {
+        let diagonal = std::f32::consts::FRAC_1_SQRT_2;
+        // Left, right, bottom, top, near, far.
+        let expected = [
+            (Vector3::new(diagonal, diagonal, 0.0), 5.0 * diagonal),
+            (Vector3::new(-diagonal, diagonal, 0.0), 5.0 * diagonal),
+            (Vector3::new(0.0, diagonal, diagonal), 5.0 * diagonal),
+            (Vector3::new(0.0, diagonal, -diagonal), 5.0 * diagonal),
+            (Vector3::new(0.0, 1.0, 0.0), 4.0),
+            (Vector3::new(0.0, -1.0, 0.0), 95.0),
+        ];
+        for (plane, (normal, distance)) in frustum().planes.iter().zip(expected) {
+            assert_close(plane.normal, normal);
+            assert!((plane.distance - distance).abs() < 1e-3, "{} != {}", plane.distance, distance);
+        }
+    }
+
+    #[test]
+    fn volumes_inside() {
+        let frustum = frustum();
+        assert!(frustum.intersects_sphere(&sphere(0.0, 0.0, 0.0, 1.0)));
+        assert!(frustum.intersects_aabb(&cube(0.0, 0.0, 0.0, 1.0)));
+    }
+
+    #[test]
+    fn volumes_outside() {
+        let frustum = frustum();
+        // Between the eye and the near plane, past each side plane, and beyond the far plane.
+        let centers = [
+            (0.0, -4.5, 0.0),
+            (-10.0, 0.0, 0.0),
+            (10.0, 0.0, 0.0),
+            (0.0, 0.0, -10.0),
+            (0.0, 0.0, 10.0),
+            (0.0, 200.0, 0.0),
+        ];
+        for (x, y, z) in centers {
+            assert!(!frustum.intersects_sphere(&sphere(x, y, z, 0.25)), "sphere at {:?}", (x, y, z));
+            assert!(!frustum.intersects_aabb(&cube(x, y, z, 0.25)), "box at {:?}", (x, y, z));
+        }
+    }
+
+    #[test]
+    fn volumes_straddling() {
+        let frustum = frustum();
+        // Centered on the right plane, and across the near plane.
+        for (x, y, z) in [(5.0, 0.0, 0.0), (0.0, -4.0, 0.0)] {
+            assert!(frustum.intersects_sphere(&sphere(x, y, z, 0.5)), "sphere at {:?}", (x, y, z));
+            assert!(frustum.intersects_aabb(&cube(x, y, z, 0.5)), "box at {:?}", (x, y, z));
+        }
+    }
+
+    // Scale x3 along X, a quarter turn about Z, then move to (10, 0, 0).
+    fn transform() -> Matrix4<f32> {
+        Matrix4::from_translation(Vector3::new(10.0, 0.0, 0.0))
+            * Matrix4::from_angle_z(Deg(90.0))
+            * Matrix4::from_nonuniform_scale(3.0, 1.0, 1.0)
+    }
+
+    #[test]
+    fn transformed_aabb() {
+        let aabb = Aabb {
+            min: Vector3::new(0.0, -2.0, -3.0),
+            max: Vector3::new(2.0, 2.0, 3.0),
+        };
+        // Center (1, 0, 0) scales to (3, 0, 0) and turns to (0, 3, 0); extents (1, 2, 3) become (3, 2, 3)
+        // then swap X and Y.
+        let world = transform_aabb(&aabb, transform());
+        assert_close(world.min, Vector3::new(8.0, 0.0, -3.0));
+        assert_close(world.max, Vector3::new(12.0, 6.0, 3.0));
+    }
+
+    #[test]
+    fn transformed_sphere() {
+        let world = transform_sphere(&sphere(1.0, 0.0, 0.0, 2.0), transform());
+        assert_close(world.center, Vector3::new(10.0, 3.0, 0.0));
+        // The largest axis scale wins.
+        assert!((world.radius - 6.0).abs() < 1e-4, "{}", world.radius);
+    }
+}
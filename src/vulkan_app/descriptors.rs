@@ -1,13 +1,15 @@
+use std::collections::HashMap;
+
 use ash::{vk};
 
-use super::{utils::UniformBufferObject, VulkanApp};
+use super::{compute::ComputeBinding, texture::Texture, utils::UniformBufferObject, VulkanApp};
 
 pub fn create_descriptor_set_layout(device: &ash::Device) -> vk::DescriptorSetLayout {
     let ubo_layout_binding = vk::DescriptorSetLayoutBinding::builder()
         .binding(0)
         .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
         .descriptor_count(1)
-        .stage_flags(vk::ShaderStageFlags::VERTEX)
+        .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
         .build();
 
     let layout_info = vk::DescriptorSetLayoutCreateInfo::builder()
@@ -76,3 +78,370 @@ pub fn create_descriptor_sets(
 
     descriptor_sets
 }
+
+pub fn create_material_descriptor_set_layout(device: &ash::Device) -> vk::DescriptorSetLayout {
+    let sampler_layout_binding = vk::DescriptorSetLayoutBinding::builder()
+        .binding(0)
+        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+        .descriptor_count(1)
+        .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+        .build();
+
+    let layout_info = vk::DescriptorSetLayoutCreateInfo::builder()
+        .bindings(std::slice::from_ref(&sampler_layout_binding));
+
+    unsafe { device.create_descriptor_set_layout(&layout_info, None).unwrap() }
+}
+
+// Sets per material descriptor pool; another pool is added whenever the existing ones are full.
+const MATERIAL_SETS_PER_POOL: u32 = 256;
+
+fn create_material_descriptor_pool(device: &ash::Device, max_sets: u32) -> vk::DescriptorPool {
+    let pool_size = vk::DescriptorPoolSize::builder()
+        .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+        .descriptor_count(max_sets)
+        .build();
+
+    let pool_info = vk::DescriptorPoolCreateInfo::builder()
+        .flags(vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET)
+        .pool_sizes(std::slice::from_ref(&pool_size))
+        .max_sets(max_sets);
+
+    unsafe { device.create_descriptor_pool(&pool_info, None).unwrap() }
+}
+
+// Single-texture descriptor sets for model textures, the skybox, the overlay font and GUI
+// textures, spread over as many pools as they need.
+pub struct MaterialDescriptors {
+    layout: vk::DescriptorSetLayout,
+    pools: Vec<vk::DescriptorPool>,
+    // pool each live set came from, needed to free it
+    owners: HashMap<vk::DescriptorSet, vk::DescriptorPool>,
+}
+
+impl MaterialDescriptors {
+    pub fn new(device: &ash::Device, layout: vk::DescriptorSetLayout) -> Self {
+        Self {
+            layout,
+            pools: vec![create_material_descriptor_pool(device, MATERIAL_SETS_PER_POOL)],
+            owners: HashMap::new(),
+        }
+    }
+
+    pub fn allocate(&mut self, device: &ash::Device, texture: &Texture) -> vk::DescriptorSet {
+        let allocate = |pool: vk::DescriptorPool| {
+            let alloc_info = vk::DescriptorSetAllocateInfo::builder()
+                .descriptor_pool(pool)
+                .set_layouts(std::slice::from_ref(&self.layout));
+            unsafe { device.allocate_descriptor_sets(&alloc_info) }.map(|sets| (pool, sets[0]))
+        };
+        // newest pool first, it is the one most likely to have room
+        let allocated = self.pools.iter().rev().find_map(|&pool| match allocate(pool) {
+            Ok(allocated) => Some(allocated),
+            Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY) | Err(vk::Result::ERROR_FRAGMENTED_POOL) => None,
+            Err(error) => panic!("Failed to allocate a material descriptor set: {:?}", error),
+        });
+        let (pool, descriptor_set) = allocated.unwrap_or_else(|| {
+            let pool = create_material_descriptor_pool(device, MATERIAL_SETS_PER_POOL);
+            self.pools.push(pool);
+            allocate(pool).unwrap()
+        });
+        self.owners.insert(descriptor_set, pool);
+        write_material_descriptor_set(device, descriptor_set, texture);
+        descriptor_set
+    }
+
+    pub fn free(&mut self, device: &ash::Device, descriptor_sets: &[vk::DescriptorSet]) {
+        for descriptor_set in descriptor_sets {
+            let pool = self.owners.remove(descriptor_set).unwrap();
+            unsafe {
+                device
+                    .free_descriptor_sets(pool, std::slice::from_ref(descriptor_set))
+                    .unwrap();
+            }
+        }
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        for &pool in self.pools.iter() {
+            unsafe { device.destroy_descriptor_pool(pool, None) };
+        }
+    }
+}
+
+fn write_material_descriptor_set(device: &ash::Device, descriptor_set: vk::DescriptorSet, texture: &Texture) {
+    let image_info = vk::DescriptorImageInfo::builder()
+        .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+        .image_view(texture.view)
+        .sampler(texture.sampler)
+        .build();
+
+    let descriptor_write = vk::WriteDescriptorSet::builder()
+        .dst_set(descriptor_set)
+        .dst_binding(0)
+        .dst_array_element(0)
+        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+        .image_info(std::slice::from_ref(&image_info))
+        .build();
+
+    unsafe { device.update_descriptor_sets(std::slice::from_ref(&descriptor_write), &[]) };
+}
+
+// One STORAGE_BUFFER binding per entry, visible to the given stages.
+pub fn create_storage_buffer_descriptor_set_layout(
+    device: &ash::Device,
+    binding_stages: &[vk::ShaderStageFlags],
+) -> vk::DescriptorSetLayout {
+    let bindings: Vec<vk::DescriptorSetLayoutBinding> = binding_stages
+        .iter()
+        .enumerate()
+        .map(|(binding, &stages)| {
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(binding as u32)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(stages)
+                .build()
+        })
+        .collect();
+
+    let layout_info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+
+    unsafe { device.create_descriptor_set_layout(&layout_info, None).unwrap() }
+}
+
+pub fn create_storage_buffer_descriptor_set(
+    device: &ash::Device,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    buffers: &[vk::Buffer],
+) -> (vk::DescriptorPool, vk::DescriptorSet) {
+    let pool_size = vk::DescriptorPoolSize::builder()
+        .ty(vk::DescriptorType::STORAGE_BUFFER)
+        .descriptor_count(buffers.len() as u32)
+        .build();
+
+    let pool_info = vk::DescriptorPoolCreateInfo::builder()
+        .pool_sizes(std::slice::from_ref(&pool_size))
+        .max_sets(1);
+
+    let descriptor_pool = unsafe { device.create_descriptor_pool(&pool_info, None).unwrap() };
+
+    let alloc_info = vk::DescriptorSetAllocateInfo::builder()
+        .descriptor_pool(descriptor_pool)
+        .set_layouts(std::slice::from_ref(&descriptor_set_layout));
+
+    let descriptor_set = unsafe { device.allocate_descriptor_sets(&alloc_info).unwrap()[0] };
+
+    let buffer_infos: Vec<vk::DescriptorBufferInfo> = buffers
+        .iter()
+        .map(|&buffer| {
+            vk::DescriptorBufferInfo::builder()
+                .buffer(buffer)
+                .offset(0)
+                .range(vk::WHOLE_SIZE)
+                .build()
+        })
+        .collect();
+    let descriptor_writes: Vec<vk::WriteDescriptorSet> = buffer_infos
+        .iter()
+        .enumerate()
+        .map(|(binding, buffer_info)| {
+            vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(binding as u32)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(std::slice::from_ref(buffer_info))
+                .build()
+        })
+        .collect();
+
+    unsafe { device.update_descriptor_sets(&descriptor_writes, &[]) };
+
+    (descriptor_pool, descriptor_set)
+}
+
+// One COMBINED_IMAGE_SAMPLER binding per texture, read by the fragment shader.
+pub fn create_sampler_descriptor_set_layout(device: &ash::Device, count: u32) -> vk::DescriptorSetLayout {
+    let bindings: Vec<vk::DescriptorSetLayoutBinding> = (0..count)
+        .map(|binding| {
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(binding)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                .build()
+        })
+        .collect();
+
+    let layout_info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+
+    unsafe { device.create_descriptor_set_layout(&layout_info, None).unwrap() }
+}
+
+pub fn create_sampler_descriptor_set(
+    device: &ash::Device,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    textures: &[&Texture],
+) -> (vk::DescriptorPool, vk::DescriptorSet) {
+    let pool_size = vk::DescriptorPoolSize::builder()
+        .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+        .descriptor_count(textures.len() as u32)
+        .build();
+
+    let pool_info = vk::DescriptorPoolCreateInfo::builder()
+        .pool_sizes(std::slice::from_ref(&pool_size))
+        .max_sets(1);
+
+    let descriptor_pool = unsafe { device.create_descriptor_pool(&pool_info, None).unwrap() };
+
+    let alloc_info = vk::DescriptorSetAllocateInfo::builder()
+        .descriptor_pool(descriptor_pool)
+        .set_layouts(std::slice::from_ref(&descriptor_set_layout));
+
+    let descriptor_set = unsafe { device.allocate_descriptor_sets(&alloc_info).unwrap()[0] };
+
+    let image_infos: Vec<vk::DescriptorImageInfo> = textures
+        .iter()
+        .map(|texture| {
+            vk::DescriptorImageInfo::builder()
+                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .image_view(texture.view)
+                .sampler(texture.sampler)
+                .build()
+        })
+        .collect();
+    let descriptor_writes: Vec<vk::WriteDescriptorSet> = image_infos
+        .iter()
+        .enumerate()
+        .map(|(binding, image_info)| {
+            vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(binding as u32)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(std::slice::from_ref(image_info))
+                .build()
+        })
+        .collect();
+
+    unsafe { device.update_descriptor_sets(&descriptor_writes, &[]) };
+
+    (descriptor_pool, descriptor_set)
+}
+
+fn compute_descriptor_type(binding: &ComputeBinding) -> vk::DescriptorType {
+    match binding {
+        ComputeBinding::StorageBuffer(_) => vk::DescriptorType::STORAGE_BUFFER,
+        ComputeBinding::StorageImage { .. } => vk::DescriptorType::STORAGE_IMAGE,
+        ComputeBinding::SampledImage { .. } => vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+    }
+}
+
+pub fn create_compute_descriptor_set_layout(
+    device: &ash::Device,
+    bindings: &[ComputeBinding],
+) -> vk::DescriptorSetLayout {
+    let layout_bindings: Vec<vk::DescriptorSetLayoutBinding> = bindings
+        .iter()
+        .enumerate()
+        .map(|(binding, resource)| {
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(binding as u32)
+                .descriptor_type(compute_descriptor_type(resource))
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .build()
+        })
+        .collect();
+
+    let layout_info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&layout_bindings);
+
+    unsafe { device.create_descriptor_set_layout(&layout_info, None).unwrap() }
+}
+
+pub fn create_compute_descriptor_set(
+    device: &ash::Device,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    bindings: &[ComputeBinding],
+) -> (vk::DescriptorPool, vk::DescriptorSet) {
+    let pool_sizes: Vec<vk::DescriptorPoolSize> = [
+        vk::DescriptorType::STORAGE_BUFFER,
+        vk::DescriptorType::STORAGE_IMAGE,
+        vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+    ]
+    .into_iter()
+    .map(|ty| {
+        let count = bindings
+            .iter()
+            .filter(|binding| compute_descriptor_type(binding) == ty)
+            .count() as u32;
+        vk::DescriptorPoolSize::builder().ty(ty).descriptor_count(count).build()
+    })
+    .filter(|pool_size| pool_size.descriptor_count > 0)
+    .collect();
+
+    let pool_info = vk::DescriptorPoolCreateInfo::builder()
+        .pool_sizes(&pool_sizes)
+        .max_sets(1);
+
+    let descriptor_pool = unsafe { device.create_descriptor_pool(&pool_info, None).unwrap() };
+
+    let alloc_info = vk::DescriptorSetAllocateInfo::builder()
+        .descriptor_pool(descriptor_pool)
+        .set_layouts(std::slice::from_ref(&descriptor_set_layout));
+
+    let descriptor_set = unsafe { device.allocate_descriptor_sets(&alloc_info).unwrap()[0] };
+
+    // build every info first so the writes can point into stable storage
+    let buffer_infos: Vec<vk::DescriptorBufferInfo> = bindings
+        .iter()
+        .map(|binding| match *binding {
+            ComputeBinding::StorageBuffer(buffer) => vk::DescriptorBufferInfo::builder()
+                .buffer(buffer)
+                .offset(0)
+                .range(vk::WHOLE_SIZE)
+                .build(),
+            ComputeBinding::StorageImage { .. } | ComputeBinding::SampledImage { .. } => {
+                vk::DescriptorBufferInfo::default()
+            }
+        })
+        .collect();
+    let image_infos: Vec<vk::DescriptorImageInfo> = bindings
+        .iter()
+        .map(|binding| match *binding {
+            ComputeBinding::StorageImage { view, .. } => vk::DescriptorImageInfo::builder()
+                .image_view(view)
+                .image_layout(vk::ImageLayout::GENERAL)
+                .build(),
+            ComputeBinding::SampledImage { view, sampler, .. } => vk::DescriptorImageInfo::builder()
+                .image_view(view)
+                .sampler(sampler)
+                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .build(),
+            ComputeBinding::StorageBuffer(_) => vk::DescriptorImageInfo::default(),
+        })
+        .collect();
+    let descriptor_writes: Vec<vk::WriteDescriptorSet> = bindings
+        .iter()
+        .enumerate()
+        .map(|(i, binding)| {
+            let write = vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(i as u32)
+                .dst_array_element(0)
+                .descriptor_type(compute_descriptor_type(binding));
+            match binding {
+                ComputeBinding::StorageBuffer(_) => write.buffer_info(std::slice::from_ref(&buffer_infos[i])),
+                ComputeBinding::StorageImage { .. } | ComputeBinding::SampledImage { .. } => {
+                    write.image_info(std::slice::from_ref(&image_infos[i]))
+                }
+            }
+            .build()
+        })
+        .collect();
+
+    unsafe { device.update_descriptor_sets(&descriptor_writes, &[]) };
+
+    (descriptor_pool, descriptor_set)
+}
@@ -0,0 +1,461 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use ash::vk;
+use cgmath::{InnerSpace, Matrix4, Quaternion, SquareMatrix, Vector3, Vector4};
+
+use super::mesh::{MeshData, MeshIndices};
+use crate::camera::Projection;
+use super::vertex::Vertex;
+
+#[derive(Debug)]
+pub struct GltfError {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl fmt::Display for GltfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
+
+impl std::error::Error for GltfError {}
+
+// Extensions that change how the data reads: compressed or quantized geometry, other image
+// formats, remapped texture coordinates and instancing. Others only add to it and are skipped
+// silently.
+const MEANINGFUL_EXTENSIONS: [&str; 8] = [
+    "KHR_draco_mesh_compression",
+    "EXT_meshopt_compression",
+    "KHR_mesh_quantization",
+    "KHR_texture_basisu",
+    "EXT_texture_webp",
+    "EXT_texture_avif",
+    "KHR_texture_transform",
+    "EXT_mesh_gpu_instancing",
+];
+
+pub struct TextureData {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+// The base color factor is baked into the vertex colors, only the texture is kept here.
+pub struct ImportedMaterial {
+    pub base_color_texture: Option<usize>,
+}
+
+// A camera's projection in the renderer's terms. The window decides the aspect ratio, so the
+// file's is not kept.
+#[derive(Clone, Copy, Debug)]
+pub struct ImportedCamera {
+    pub projection: Projection,
+    // Vertical field of view in degrees, set for perspective cameras.
+    pub fov: Option<f32>,
+    // World units visible vertically, set for orthographic ones.
+    pub ortho_height: Option<f32>,
+    pub near: f32,
+    // None for an infinite perspective projection.
+    pub far: Option<f32>,
+}
+
+pub struct ImportedNode {
+    pub name: String,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+    pub meshes: Vec<usize>,
+    pub camera: Option<usize>,
+}
+
+impl ImportedNode {
+    pub fn local_matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+pub struct ImportedScene {
+    pub nodes: Vec<ImportedNode>,
+    pub roots: Vec<usize>,
+    pub meshes: Vec<MeshData>,
+    pub materials: Vec<ImportedMaterial>,
+    // indexed like the glTF images; None when an image could not be decoded
+    pub textures: Vec<Option<TextureData>>,
+    pub cameras: Vec<ImportedCamera>,
+    pub warnings: Vec<String>,
+}
+
+impl ImportedScene {
+    // World matrix of every node, converted from glTF's Y-up into the renderer's Z-up space.
+    pub fn world_matrices(&self) -> Vec<Matrix4<f32>> {
+        let mut world = vec![Matrix4::identity(); self.nodes.len()];
+        let mut stack: Vec<(usize, Matrix4<f32>)> =
+            self.roots.iter().map(|&root| (root, y_up_to_z_up_matrix())).collect();
+        while let Some((index, parent)) = stack.pop() {
+            world[index] = parent * self.nodes[index].local_matrix();
+            for &child in self.nodes[index].children.iter() {
+                stack.push((child, world[index]));
+            }
+        }
+        world
+    }
+
+    // World transform and projection of the first node carrying a camera, if the file has one.
+    pub fn camera(&self) -> Option<(Matrix4<f32>, ImportedCamera)> {
+        let world = self.world_matrices();
+        self.nodes
+            .iter()
+            .enumerate()
+            .find_map(|(index, node)| Some((world[index], self.cameras[node.camera?])))
+    }
+}
+
+// Rotates +90 degrees about X so that glTF's +Y maps to the renderer's +Z.
+pub fn y_up_to_z_up_matrix() -> Matrix4<f32> {
+    Matrix4::new(
+        1.0, 0.0, 0.0, 0.0,
+        0.0, 0.0, 1.0, 0.0,
+        0.0, -1.0, 0.0, 0.0,
+        0.0, 0.0, 0.0, 1.0,
+    )
+}
+
+pub fn load_gltf(path: &Path) -> Result<ImportedScene, GltfError> {
+    let error = |message: String| GltfError {
+        path: path.to_path_buf(),
+        message,
+    };
+
+    let bytes = std::fs::read(path).map_err(|e| error(e.to_string()))?;
+    let gltf::Gltf { document, blob } =
+        gltf::Gltf::from_slice_without_validation(&bytes).map_err(|e| error(e.to_string()))?;
+
+    // Required extensions would make validation fail outright; we report them and try anyway.
+    let mut warnings = Vec::new();
+    let mut root = document.into_json();
+    for extension in root.extensions_used.iter() {
+        let required = root.extensions_required.contains(extension);
+        if !required && !MEANINGFUL_EXTENSIONS.contains(&extension.as_str()) {
+            continue;
+        }
+        warnings.push(format!(
+            "extension {} is not supported and will be ignored{}",
+            extension,
+            if required { " (marked as required)" } else { "" }
+        ));
+    }
+    root.extensions_required.clear();
+    let document = gltf::Document::from_json(root).map_err(|e| error(e.to_string()))?;
+
+    let base = path.parent().unwrap_or_else(|| Path::new("./"));
+    let buffers = gltf::import_buffers(&document, Some(base), blob).map_err(|e| error(e.to_string()))?;
+
+    let mut textures = Vec::new();
+    for image in document.images() {
+        match gltf::image::Data::from_source(image.source(), Some(base), &buffers) {
+            Ok(data) => textures.push(Some(convert_image(data))),
+            Err(e) => {
+                warnings.push(format!("image {} could not be loaded: {}", image.index(), e));
+                textures.push(None);
+            }
+        }
+    }
+
+    let materials = document
+        .materials()
+        .map(|material| ImportedMaterial {
+            base_color_texture: material
+                .pbr_metallic_roughness()
+                .base_color_texture()
+                .map(|info| info.texture().source().index())
+                .filter(|&image| textures[image].is_some()),
+        })
+        .collect();
+
+    let cameras = document
+        .cameras()
+        .map(|camera| match camera.projection() {
+            gltf::camera::Projection::Perspective(p) => ImportedCamera {
+                projection: Projection::Perspective,
+                fov: Some(p.yfov().to_degrees()),
+                ortho_height: None,
+                near: p.znear(),
+                far: p.zfar(),
+            },
+            // ymag is half the view height
+            gltf::camera::Projection::Orthographic(o) => ImportedCamera {
+                projection: Projection::Orthographic,
+                fov: None,
+                ortho_height: Some(2.0 * o.ymag()),
+                near: o.znear(),
+                far: Some(o.zfar()),
+            },
+        })
+        .collect();
+
+    let scene = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+        .ok_or_else(|| error("file contains no scenes".to_string()))?;
+
+    let mut importer = NodeImporter {
+        buffers: &buffers,
+        nodes: Vec::new(),
+        meshes: Vec::new(),
+        mesh_primitives: HashMap::new(),
+        warnings,
+    };
+    let roots = scene
+        .nodes()
+        .map(|node| importer.import_node(&node, None))
+        .collect();
+
+    Ok(ImportedScene {
+        nodes: importer.nodes,
+        roots,
+        meshes: importer.meshes,
+        materials,
+        textures,
+        cameras,
+        warnings: importer.warnings,
+    })
+}
+
+struct NodeImporter<'a> {
+    buffers: &'a [gltf::buffer::Data],
+    nodes: Vec<ImportedNode>,
+    meshes: Vec<MeshData>,
+    // glTF mesh index -> indices of the MeshData created for its primitives
+    mesh_primitives: HashMap<usize, Vec<usize>>,
+    warnings: Vec<String>,
+}
+
+impl NodeImporter<'_> {
+    fn import_node(&mut self, node: &gltf::Node, parent: Option<usize>) -> usize {
+        let (translation, rotation, scale) = node.transform().decomposed();
+        let meshes = match node.mesh() {
+            Some(mesh) => self.import_mesh(&mesh),
+            None => Vec::new(),
+        };
+
+        let index = self.nodes.len();
+        self.nodes.push(ImportedNode {
+            name: node.name().unwrap_or("").to_string(),
+            parent,
+            children: Vec::new(),
+            translation: Vector3::from(translation),
+            rotation: Quaternion::new(rotation[3], rotation[0], rotation[1], rotation[2]),
+            scale: Vector3::from(scale),
+            meshes,
+            camera: node.camera().map(|camera| camera.index()),
+        });
+
+        for child in node.children() {
+            let child_index = self.import_node(&child, Some(index));
+            self.nodes[index].children.push(child_index);
+        }
+
+        index
+    }
+
+    fn import_mesh(&mut self, mesh: &gltf::Mesh) -> Vec<usize> {
+        if let Some(indices) = self.mesh_primitives.get(&mesh.index()) {
+            return indices.clone();
+        }
+
+        let mut indices = Vec::new();
+        for primitive in mesh.primitives() {
+            match self.import_primitive(mesh, &primitive) {
+                Ok(data) => {
+                    indices.push(self.meshes.len());
+                    self.meshes.push(data);
+                }
+                Err(message) => self.warnings.push(format!(
+                    "mesh '{}' primitive {} skipped: {}",
+                    mesh.name().unwrap_or(""),
+                    primitive.index(),
+                    message
+                )),
+            }
+        }
+
+        self.mesh_primitives.insert(mesh.index(), indices.clone());
+        indices
+    }
+
+    fn import_primitive(&self, mesh: &gltf::Mesh, primitive: &gltf::Primitive) -> Result<MeshData, String> {
+        let reader = primitive.reader(|buffer| Some(&self.buffers[buffer.index()]));
+
+        let positions: Vec<[f32; 3]> = reader
+            .read_positions()
+            .ok_or("primitive has no POSITION attribute")?
+            .collect();
+        let count = positions.len();
+        // an empty mesh would need a zero-sized vertex buffer, which Vulkan doesn't allow
+        if count == 0 {
+            return Err("primitive has no vertices".to_string());
+        }
+        let normals: Option<Vec<[f32; 3]>> = reader.read_normals().map(|iter| iter.collect());
+        let uvs: Option<Vec<[f32; 2]>> = reader.read_tex_coords(0).map(|iter| iter.into_f32().collect());
+        let colors: Option<Vec<[f32; 4]>> = reader.read_colors(0).map(|iter| iter.into_rgba_f32().collect());
+        let indices: Vec<u32> = match reader.read_indices() {
+            Some(iter) => iter.into_u32().collect(),
+            None => (0..count as u32).collect(),
+        };
+        if let Some(&out_of_range) = indices.iter().find(|&&i| i as usize >= count) {
+            return Err(format!("index {} out of range ({} vertices)", out_of_range, count));
+        }
+
+        let (topology, indices) = match primitive.mode() {
+            gltf::mesh::Mode::Triangles => (vk::PrimitiveTopology::TRIANGLE_LIST, indices),
+            gltf::mesh::Mode::TriangleStrip => (vk::PrimitiveTopology::TRIANGLE_LIST, strip_to_list(&indices)),
+            gltf::mesh::Mode::TriangleFan => (vk::PrimitiveTopology::TRIANGLE_LIST, fan_to_list(&indices)),
+            gltf::mesh::Mode::Lines => (vk::PrimitiveTopology::LINE_LIST, indices),
+            gltf::mesh::Mode::LineStrip => (vk::PrimitiveTopology::LINE_LIST, line_strip_to_list(&indices, false)),
+            gltf::mesh::Mode::LineLoop => (vk::PrimitiveTopology::LINE_LIST, line_strip_to_list(&indices, true)),
+            gltf::mesh::Mode::Points => return Err("point primitives are not supported".to_string()),
+        };
+        if indices.is_empty() {
+            return Err("primitive has too few indices to form a triangle or line".to_string());
+        }
+
+        let material = primitive.material().index();
+        let base_color = primitive.material().pbr_metallic_roughness().base_color_factor();
+        let mut vertices: Vec<Vertex> = (0..count)
+            .map(|i| {
+                let color = colors.as_ref().map_or([1.0; 4], |colors| colors[i]);
+                let mut vertex = Vertex::new(
+                    positions[i],
+                    [
+                        color[0] * base_color[0],
+                        color[1] * base_color[1],
+                        color[2] * base_color[2],
+                    ],
+                );
+                if let Some(normals) = normals.as_ref() {
+                    vertex.normal = normals[i];
+                }
+                if let Some(uvs) = uvs.as_ref() {
+                    vertex.uv = uvs[i];
+                }
+                vertex
+            })
+            .collect();
+
+        if normals.is_none() && topology == vk::PrimitiveTopology::TRIANGLE_LIST {
+            generate_normals(&mut vertices, &indices);
+        }
+
+        Ok(MeshData {
+            name: mesh.name().unwrap_or("").to_string(),
+            vertices,
+            indices: MeshIndices::from_u32(indices),
+            topology,
+            material,
+        })
+    }
+}
+
+fn strip_to_list(indices: &[u32]) -> Vec<u32> {
+    let mut list = Vec::new();
+    for i in 0..indices.len().saturating_sub(2) {
+        // every other triangle in a strip has reversed winding
+        if i % 2 == 0 {
+            list.extend_from_slice(&[indices[i], indices[i + 1], indices[i + 2]]);
+        } else {
+            list.extend_from_slice(&[indices[i + 1], indices[i], indices[i + 2]]);
+        }
+    }
+    list
+}
+
+fn fan_to_list(indices: &[u32]) -> Vec<u32> {
+    let mut list = Vec::new();
+    for i in 1..indices.len().saturating_sub(1) {
+        list.extend_from_slice(&[indices[0], indices[i], indices[i + 1]]);
+    }
+    list
+}
+
+fn line_strip_to_list(indices: &[u32], closed: bool) -> Vec<u32> {
+    let mut list: Vec<u32> = indices.windows(2).flat_map(|pair| [pair[0], pair[1]]).collect();
+    if closed && indices.len() > 2 {
+        list.extend_from_slice(&[indices[indices.len() - 1], indices[0]]);
+    }
+    list
+}
+
+fn generate_normals(vertices: &mut [Vertex], indices: &[u32]) {
+    let mut accumulated = vec![Vector3::new(0.0, 0.0, 0.0); vertices.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|i| i as usize);
+        let pa = Vector3::from(vertices[a].pos);
+        let pb = Vector3::from(vertices[b].pos);
+        let pc = Vector3::from(vertices[c].pos);
+        let face_normal = (pb - pa).cross(pc - pa);
+        accumulated[a] += face_normal;
+        accumulated[b] += face_normal;
+        accumulated[c] += face_normal;
+    }
+    for (vertex, normal) in vertices.iter_mut().zip(accumulated) {
+        if normal.magnitude2() > 0.0 {
+            vertex.normal = normal.normalize().into();
+        }
+    }
+}
+
+fn convert_image(data: gltf::image::Data) -> TextureData {
+    use gltf::image::Format;
+
+    let channels = |format: Format| match format {
+        Format::R8 | Format::R16 => 1,
+        Format::R8G8 | Format::R16G16 => 2,
+        Format::R8G8B8 | Format::R16G16B16 | Format::R32G32B32FLOAT => 3,
+        Format::R8G8B8A8 | Format::R16G16B16A16 | Format::R32G32B32A32FLOAT => 4,
+    };
+    let component_count = channels(data.format);
+    let components: Vec<f32> = match data.format {
+        Format::R8 | Format::R8G8 | Format::R8G8B8 | Format::R8G8B8A8 => {
+            data.pixels.iter().map(|&c| c as f32 / 255.0).collect()
+        }
+        Format::R16 | Format::R16G16 | Format::R16G16B16 | Format::R16G16B16A16 => data
+            .pixels
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]) as f32 / 65535.0)
+            .collect(),
+        Format::R32G32B32FLOAT | Format::R32G32B32A32FLOAT => data
+            .pixels
+            .chunks_exact(4)
+            .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+            .collect(),
+    };
+
+    let mut pixels = Vec::with_capacity((data.width * data.height * 4) as usize);
+    for texel in components.chunks_exact(component_count) {
+        let rgba = match component_count {
+            1 => Vector4::new(texel[0], texel[0], texel[0], 1.0),
+            2 => Vector4::new(texel[0], texel[1], 0.0, 1.0),
+            3 => Vector4::new(texel[0], texel[1], texel[2], 1.0),
+            _ => Vector4::new(texel[0], texel[1], texel[2], texel[3]),
+        };
+        pixels.extend_from_slice(&[
+            (rgba.x.clamp(0.0, 1.0) * 255.0).round() as u8,
+            (rgba.y.clamp(0.0, 1.0) * 255.0).round() as u8,
+            (rgba.z.clamp(0.0, 1.0) * 255.0).round() as u8,
+            (rgba.w.clamp(0.0, 1.0) * 255.0).round() as u8,
+        ]);
+    }
+
+    TextureData {
+        width: data.width,
+        height: data.height,
+        pixels,
+    }
+}
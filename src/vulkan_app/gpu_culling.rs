@@ -0,0 +1,296 @@
+use ash::vk;
+use cgmath::Matrix4;
+
+use super::{app::DrawCommand, buffers, culling::Frustum, descriptors, mesh::Mesh, pipeline};
+
+pub const MAX_GPU_INSTANCES: usize = 16384;
+const WORKGROUP_SIZE: u32 = 64;
+
+// Matches `Instance` in cull.comp and indirect.vert (std430).
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct GpuInstance {
+    pub model: Matrix4<f32>,
+    pub color: [f32; 4],
+    pub bounding_sphere: [f32; 4],
+    pub index_count: u32,
+    pub batch: u32,
+    pub batch_offset: u32,
+    pub object_id: u32,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct CullPushConstants {
+    planes: [[f32; 4]; 6],
+    instance_count: u32,
+    compact: u32,
+}
+
+// A run of instances sharing a mesh and material. Its indirect commands occupy
+// `first..first + count` in the command buffer and its visible count sits at the batch index.
+#[derive(Clone, Copy)]
+pub struct DrawBatch {
+    pub mesh: usize,
+    pub material: usize,
+    pub first: u32,
+    pub count: u32,
+}
+
+pub struct GpuCulling {
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    pub descriptor_pool: vk::DescriptorPool,
+    pub descriptor_set: vk::DescriptorSet,
+    pub pipeline_layout: vk::PipelineLayout,
+    pub pipeline: vk::Pipeline,
+    pub instance_buffer: vk::Buffer,
+    pub instance_buffer_memory: vk::DeviceMemory,
+    pub draw_command_buffer: vk::Buffer,
+    pub draw_command_buffer_memory: vk::DeviceMemory,
+    pub count_buffer: vk::Buffer,
+    pub count_buffer_memory: vk::DeviceMemory,
+    pub batches: Vec<DrawBatch>,
+    // Whether commands are compacted and drawn with vkCmdDrawIndexedIndirectCount.
+    pub compact: bool,
+    // Set once draws past MAX_GPU_INSTANCES have been sent to the CPU path and reported.
+    pub overflow_warned: bool,
+    push_constants: CullPushConstants,
+}
+
+impl GpuCulling {
+    pub fn new(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        pdevice: vk::PhysicalDevice,
+        compact: bool,
+    ) -> Self {
+        let (instance_buffer, instance_buffer_memory) = buffers::create_buffer(
+            instance,
+            device,
+            pdevice,
+            (std::mem::size_of::<GpuInstance>() * MAX_GPU_INSTANCES) as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        );
+        let (draw_command_buffer, draw_command_buffer_memory) = buffers::create_buffer(
+            instance,
+            device,
+            pdevice,
+            (std::mem::size_of::<vk::DrawIndexedIndirectCommand>() * MAX_GPU_INSTANCES) as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::INDIRECT_BUFFER,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        );
+        // host visible so the visible counts can be read back for the culling statistics
+        let (count_buffer, count_buffer_memory) = buffers::create_buffer(
+            instance,
+            device,
+            pdevice,
+            (std::mem::size_of::<u32>() * MAX_GPU_INSTANCES) as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER
+                | vk::BufferUsageFlags::INDIRECT_BUFFER
+                | vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        );
+
+        // the instance buffer is also read by indirect.vert through set 2
+        let descriptor_set_layout = descriptors::create_storage_buffer_descriptor_set_layout(
+            device,
+            &[
+                vk::ShaderStageFlags::COMPUTE | vk::ShaderStageFlags::VERTEX,
+                vk::ShaderStageFlags::COMPUTE,
+                vk::ShaderStageFlags::COMPUTE,
+            ],
+        );
+        let (descriptor_pool, descriptor_set) = descriptors::create_storage_buffer_descriptor_set(
+            device,
+            descriptor_set_layout,
+            &[instance_buffer, draw_command_buffer, count_buffer],
+        );
+        let (pipeline, pipeline_layout) = pipeline::create_compute_pipeline(
+            device,
+            include_bytes!(env!("CULL_COMP_SHADER_PATH")),
+            std::slice::from_ref(&descriptor_set_layout),
+            std::mem::size_of::<CullPushConstants>() as u32,
+        );
+
+        Self {
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_set,
+            pipeline_layout,
+            pipeline,
+            instance_buffer,
+            instance_buffer_memory,
+            draw_command_buffer,
+            draw_command_buffer_memory,
+            count_buffer,
+            count_buffer_memory,
+            batches: Vec::new(),
+            compact,
+            overflow_warned: false,
+            push_constants: CullPushConstants {
+                planes: [[0.0; 4]; 6],
+                instance_count: 0,
+                compact: compact as u32,
+            },
+        }
+    }
+
+    pub fn instance_count(&self) -> u32 {
+        self.push_constants.instance_count
+    }
+
+    // Sorts the draws into batches and writes them to the instance buffer for this frame.
+    // The caller keeps the draws within MAX_GPU_INSTANCES.
+    pub fn upload(&mut self, device: &ash::Device, draws: &[DrawCommand], meshes: &[Mesh], frustum: &Frustum) {
+        assert!(draws.len() <= MAX_GPU_INSTANCES);
+        let mut sorted: Vec<&DrawCommand> = draws.iter().collect();
+        sorted.sort_by_key(|draw| (draw.mesh, draw.material));
+
+        self.batches.clear();
+        let mut instances = Vec::with_capacity(sorted.len());
+        for (i, draw) in sorted.iter().enumerate() {
+            let starts_batch = self
+                .batches
+                .last()
+                .map_or(true, |batch| batch.mesh != draw.mesh || batch.material != draw.material);
+            if starts_batch {
+                self.batches.push(DrawBatch {
+                    mesh: draw.mesh,
+                    material: draw.material,
+                    first: i as u32,
+                    count: 0,
+                });
+            }
+            let batch_index = self.batches.len() - 1;
+            let batch = &mut self.batches[batch_index];
+            batch.count += 1;
+
+            let mesh = &meshes[draw.mesh];
+            let sphere = mesh.bounding_sphere;
+            instances.push(GpuInstance {
+                model: draw.transform,
+                color: draw.color,
+                bounding_sphere: [sphere.center.x, sphere.center.y, sphere.center.z, sphere.radius],
+                index_count: mesh.index_count,
+                batch: batch_index as u32,
+                batch_offset: batch.first,
+                object_id: draw.object_id,
+            });
+        }
+
+        for (plane, out) in frustum.planes.iter().zip(self.push_constants.planes.iter_mut()) {
+            *out = [plane.normal.x, plane.normal.y, plane.normal.z, plane.distance];
+        }
+        self.push_constants.instance_count = instances.len() as u32;
+
+        if instances.is_empty() {
+            return;
+        }
+        let size = (std::mem::size_of::<GpuInstance>() * instances.len()) as vk::DeviceSize;
+        unsafe {
+            let data_ptr = device
+                .map_memory(self.instance_buffer_memory, 0, size, vk::MemoryMapFlags::empty())
+                .unwrap();
+            let mut align = ash::util::Align::new(data_ptr, std::mem::align_of::<GpuInstance>() as _, size);
+            align.copy_from_slice(&instances);
+            device.unmap_memory(self.instance_buffer_memory);
+        }
+    }
+
+    // Visible instances per batch counted by the last completed culling pass. Only valid once
+    // its command buffer has finished, i.e. after waiting for the in-flight fence.
+    pub fn visible_counts(&self, device: &ash::Device) -> Vec<u32> {
+        if self.batches.is_empty() {
+            return Vec::new();
+        }
+        let size = (std::mem::size_of::<u32>() * self.batches.len()) as vk::DeviceSize;
+        unsafe {
+            let data_ptr = device
+                .map_memory(self.count_buffer_memory, 0, size, vk::MemoryMapFlags::empty())
+                .unwrap();
+            let counts = std::slice::from_raw_parts(data_ptr as *const u32, self.batches.len()).to_vec();
+            device.unmap_memory(self.count_buffer_memory);
+            counts
+        }
+    }
+
+    // Resets the counts and runs the culling shader. Must be recorded outside a render pass.
+    pub fn record_cull(&self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        let instance_count = self.push_constants.instance_count;
+        if instance_count == 0 {
+            return;
+        }
+
+        unsafe {
+            device.cmd_fill_buffer(
+                command_buffer,
+                self.count_buffer,
+                0,
+                (std::mem::size_of::<u32>() * self.batches.len()) as vk::DeviceSize,
+                0,
+            );
+            let clear_barrier = vk::MemoryBarrier::builder()
+                .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                std::slice::from_ref(&clear_barrier),
+                &[],
+                &[],
+            );
+
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, self.pipeline);
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline_layout,
+                0,
+                &[self.descriptor_set],
+                &[],
+            );
+            device.cmd_push_constants(
+                command_buffer,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                std::slice::from_raw_parts(
+                    &self.push_constants as *const CullPushConstants as *const u8,
+                    std::mem::size_of::<CullPushConstants>(),
+                ),
+            );
+            device.cmd_dispatch(command_buffer, instance_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+
+            let cull_barrier = vk::MemoryBarrier::builder()
+                .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+                .dst_access_mask(vk::AccessFlags::INDIRECT_COMMAND_READ | vk::AccessFlags::SHADER_READ);
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::DRAW_INDIRECT | vk::PipelineStageFlags::VERTEX_SHADER,
+                vk::DependencyFlags::empty(),
+                std::slice::from_ref(&cull_barrier),
+                &[],
+                &[],
+            );
+        }
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+            device.destroy_descriptor_pool(self.descriptor_pool, None);
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            device.destroy_buffer(self.instance_buffer, None);
+            device.free_memory(self.instance_buffer_memory, None);
+            device.destroy_buffer(self.draw_command_buffer, None);
+            device.free_memory(self.draw_command_buffer_memory, None);
+            device.destroy_buffer(self.count_buffer, None);
+            device.free_memory(self.count_buffer_memory, None);
+        }
+    }
+}
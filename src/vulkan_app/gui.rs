@@ -0,0 +1,348 @@
+use std::collections::HashMap;
+use std::mem::offset_of;
+
+use ash::vk;
+
+use super::texture::Texture;
+use super::{buffers, VulkanApp};
+
+// Everything egui produced for one frame, handed to `VulkanApp::submit_gui`.
+pub struct GuiFrame {
+    pub primitives: Vec<egui::ClippedPrimitive>,
+    pub textures_delta: egui::TexturesDelta,
+    pub pixels_per_point: f32,
+}
+
+// A CPU copy of the pixels is kept so partial updates can be applied before re-uploading.
+struct GuiTexture {
+    texture: Texture,
+    descriptor_set: vk::DescriptorSet,
+    size: [usize; 2],
+    pixels: Vec<u8>,
+}
+
+struct GuiDraw {
+    scissor: vk::Rect2D,
+    texture: egui::TextureId,
+    first_index: u32,
+    index_count: u32,
+    vertex_offset: i32,
+}
+
+pub struct GuiRenderer {
+    textures: HashMap<egui::TextureId, GuiTexture>,
+    // Freed on the next update, once the frame that last used them has finished.
+    pending_free: Vec<egui::TextureId>,
+    vertex_buffer: vk::Buffer,
+    vertex_buffer_memory: vk::DeviceMemory,
+    vertex_capacity: usize,
+    index_buffer: vk::Buffer,
+    index_buffer_memory: vk::DeviceMemory,
+    index_capacity: usize,
+    draws: Vec<GuiDraw>,
+    screen_size: [f32; 2],
+}
+
+pub fn vertex_binding_description() -> vk::VertexInputBindingDescription {
+    vk::VertexInputBindingDescription::builder()
+        .binding(0)
+        .stride(std::mem::size_of::<egui::epaint::Vertex>() as u32)
+        .input_rate(vk::VertexInputRate::VERTEX)
+        .build()
+}
+
+pub fn vertex_attribute_descriptions() -> [vk::VertexInputAttributeDescription; 3] {
+    [
+        vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(0)
+            .format(vk::Format::R32G32_SFLOAT)
+            .offset(offset_of!(egui::epaint::Vertex, pos) as u32)
+            .build(),
+        vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(1)
+            .format(vk::Format::R32G32_SFLOAT)
+            .offset(offset_of!(egui::epaint::Vertex, uv) as u32)
+            .build(),
+        // sRGB bytes, gui.vert converts them to linear
+        vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(2)
+            .format(vk::Format::R8G8B8A8_UNORM)
+            .offset(offset_of!(egui::epaint::Vertex, color) as u32)
+            .build(),
+    ]
+}
+
+impl GuiRenderer {
+    pub fn new() -> Self {
+        Self {
+            textures: HashMap::new(),
+            pending_free: Vec::new(),
+            vertex_buffer: vk::Buffer::null(),
+            vertex_buffer_memory: vk::DeviceMemory::null(),
+            vertex_capacity: 0,
+            index_buffer: vk::Buffer::null(),
+            index_buffer_memory: vk::DeviceMemory::null(),
+            index_capacity: 0,
+            draws: Vec::new(),
+            screen_size: [0.0, 0.0],
+        }
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        for texture in self.textures.values() {
+            texture.texture.destroy(device);
+        }
+        unsafe {
+            device.destroy_buffer(self.vertex_buffer, None);
+            device.free_memory(self.vertex_buffer_memory, None);
+            device.destroy_buffer(self.index_buffer, None);
+            device.free_memory(self.index_buffer_memory, None);
+        }
+    }
+}
+
+// Host-visible buffer of at least `size` bytes, grown in powers of two so resizing stays rare.
+fn ensure_capacity(
+    instance: &ash::Instance,
+    device: &ash::Device,
+    pdevice: vk::PhysicalDevice,
+    buffer: &mut vk::Buffer,
+    memory: &mut vk::DeviceMemory,
+    capacity: &mut usize,
+    size: usize,
+    usage: vk::BufferUsageFlags,
+) {
+    if size <= *capacity {
+        return;
+    }
+    unsafe {
+        device.destroy_buffer(*buffer, None);
+        device.free_memory(*memory, None);
+    }
+    *capacity = size.next_power_of_two();
+    (*buffer, *memory) = buffers::create_buffer(
+        instance,
+        device,
+        pdevice,
+        *capacity as vk::DeviceSize,
+        usage,
+        vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+    );
+}
+
+fn upload<T: Copy>(device: &ash::Device, memory: vk::DeviceMemory, data: &[T]) {
+    let size = std::mem::size_of_val(data) as vk::DeviceSize;
+    unsafe {
+        let data_ptr = device
+            .map_memory(memory, 0, size, vk::MemoryMapFlags::empty())
+            .unwrap();
+        let mut align = ash::util::Align::new(data_ptr, std::mem::align_of::<T>() as _, size);
+        align.copy_from_slice(data);
+        device.unmap_memory(memory);
+    }
+}
+
+fn image_pixels(image: &egui::ImageData) -> Vec<u8> {
+    match image {
+        egui::ImageData::Color(image) => image.pixels.iter().flat_map(|color| color.to_array()).collect(),
+        egui::ImageData::Font(image) => image.srgba_pixels(None).flat_map(|color| color.to_array()).collect(),
+    }
+}
+
+impl VulkanApp {
+    // Queues egui's output to be drawn over the next frame.
+    pub fn submit_gui(&mut self, frame: GuiFrame) {
+        self.pending_gui = Some(frame);
+    }
+
+    // Applies the submitted texture changes and uploads the meshes. Called after the frame fence,
+    // so nothing on the GPU still reads the resources being replaced. Without a submitted frame
+    // the GUI is hidden.
+    pub(super) fn update_gui(&mut self) {
+        let frame = self.pending_gui.take();
+
+        for id in std::mem::take(&mut self.gui.pending_free) {
+            if let Some(texture) = self.gui.textures.remove(&id) {
+                self.destroy_gui_texture(texture);
+            }
+        }
+
+        self.gui.draws.clear();
+        let Some(frame) = frame else { return };
+
+        for (id, delta) in frame.textures_delta.set.iter() {
+            self.set_gui_texture(*id, delta);
+        }
+        self.gui.pending_free.extend(frame.textures_delta.free.iter().copied());
+
+        let extent = self.swapchain_extent;
+        let scale = frame.pixels_per_point;
+        self.gui.screen_size = [extent.width as f32 / scale, extent.height as f32 / scale];
+
+        let mut vertices: Vec<egui::epaint::Vertex> = Vec::new();
+        let mut indices: Vec<u32> = Vec::new();
+        for primitive in frame.primitives.iter() {
+            let egui::epaint::Primitive::Mesh(mesh) = &primitive.primitive else { continue };
+            if mesh.indices.is_empty() {
+                continue;
+            }
+
+            // clip rects are in points and may reach past the window
+            let clip = primitive.clip_rect;
+            let min_x = (clip.min.x * scale).round().clamp(0.0, extent.width as f32) as u32;
+            let min_y = (clip.min.y * scale).round().clamp(0.0, extent.height as f32) as u32;
+            let max_x = (clip.max.x * scale).round().clamp(0.0, extent.width as f32) as u32;
+            let max_y = (clip.max.y * scale).round().clamp(0.0, extent.height as f32) as u32;
+            if max_x <= min_x || max_y <= min_y {
+                continue;
+            }
+
+            self.gui.draws.push(GuiDraw {
+                scissor: vk::Rect2D {
+                    offset: vk::Offset2D { x: min_x as i32, y: min_y as i32 },
+                    extent: vk::Extent2D { width: max_x - min_x, height: max_y - min_y },
+                },
+                texture: mesh.texture_id,
+                first_index: indices.len() as u32,
+                index_count: mesh.indices.len() as u32,
+                vertex_offset: vertices.len() as i32,
+            });
+            vertices.extend_from_slice(&mesh.vertices);
+            indices.extend_from_slice(&mesh.indices);
+        }
+        if self.gui.draws.is_empty() {
+            return;
+        }
+
+        let gui = &mut self.gui;
+        ensure_capacity(
+            &self.instance,
+            &self.device,
+            self.physical_device,
+            &mut gui.vertex_buffer,
+            &mut gui.vertex_buffer_memory,
+            &mut gui.vertex_capacity,
+            std::mem::size_of_val(vertices.as_slice()),
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+        );
+        ensure_capacity(
+            &self.instance,
+            &self.device,
+            self.physical_device,
+            &mut gui.index_buffer,
+            &mut gui.index_buffer_memory,
+            &mut gui.index_capacity,
+            std::mem::size_of_val(indices.as_slice()),
+            vk::BufferUsageFlags::INDEX_BUFFER,
+        );
+        upload(&self.device, gui.vertex_buffer_memory, &vertices);
+        upload(&self.device, gui.index_buffer_memory, &indices);
+    }
+
+    fn set_gui_texture(&mut self, id: egui::TextureId, delta: &egui::epaint::ImageDelta) {
+        let [width, height] = delta.image.size();
+        let patch = image_pixels(&delta.image);
+
+        let (size, pixels) = match (delta.pos, self.gui.textures.remove(&id)) {
+            (Some([x, y]), Some(mut existing)) => {
+                let mut pixels = std::mem::take(&mut existing.pixels);
+                let row_bytes = width * 4;
+                for row in 0..height {
+                    let destination = ((y + row) * existing.size[0] + x) * 4;
+                    pixels[destination..destination + row_bytes]
+                        .copy_from_slice(&patch[row * row_bytes..(row + 1) * row_bytes]);
+                }
+                let size = existing.size;
+                self.destroy_gui_texture(existing);
+                (size, pixels)
+            }
+            (_, existing) => {
+                if let Some(existing) = existing {
+                    self.destroy_gui_texture(existing);
+                }
+                ([width, height], patch)
+            }
+        };
+
+        let texture = Texture::from_pixels(
+            &self.instance,
+            &self.device,
+            self.physical_device,
+            self.command_pool,
+            self.graphics_queue,
+            size[0] as u32,
+            size[1] as u32,
+            &pixels,
+            vk::Format::R8G8B8A8_SRGB,
+            vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        );
+        let descriptor_set = self.material_descriptors.allocate(&self.device, &texture);
+        self.gui.textures.insert(
+            id,
+            GuiTexture {
+                texture,
+                descriptor_set,
+                size,
+                pixels,
+            },
+        );
+    }
+
+    fn destroy_gui_texture(&mut self, texture: GuiTexture) {
+        self.material_descriptors
+            .free(&self.device, std::slice::from_ref(&texture.descriptor_set));
+        texture.texture.destroy(&self.device);
+    }
+
+    // Drawn after the stats overlay, so the GUI stays on top of it.
+    pub(super) fn record_gui(&self, command_buffer: vk::CommandBuffer) {
+        let gui = &self.gui;
+        if gui.draws.is_empty() {
+            return;
+        }
+
+        let [encoding, paper_white] = self.output_push_constants();
+        let push_constants = [gui.screen_size[0], gui.screen_size[1], encoding, paper_white];
+        unsafe {
+            self.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.gui_pipeline,
+            );
+            self.device.cmd_push_constants(
+                command_buffer,
+                self.gui_pipeline_layout,
+                vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                0,
+                std::slice::from_raw_parts(push_constants.as_ptr() as *const u8, std::mem::size_of_val(&push_constants)),
+            );
+            self.device
+                .cmd_bind_vertex_buffers(command_buffer, 0, &[gui.vertex_buffer], &[0]);
+            self.device
+                .cmd_bind_index_buffer(command_buffer, gui.index_buffer, 0, vk::IndexType::UINT32);
+            for draw in gui.draws.iter() {
+                let Some(texture) = gui.textures.get(&draw.texture) else { continue };
+                self.device.cmd_set_scissor(command_buffer, 0, std::slice::from_ref(&draw.scissor));
+                self.device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.gui_pipeline_layout,
+                    0,
+                    &[texture.descriptor_set],
+                    &[],
+                );
+                self.device.cmd_draw_indexed(
+                    command_buffer,
+                    draw.index_count,
+                    1,
+                    draw.first_index,
+                    draw.vertex_offset,
+                    0,
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,451 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use ash::vk;
+
+use super::compute::ComputeBinding;
+use super::skybox::SkyboxError;
+use super::texture::Texture;
+use super::{buffers, descriptors, images, VulkanApp};
+
+const IRRADIANCE_SIZE: u32 = 32;
+const PREFILTERED_SIZE: u32 = 128;
+// Roughness goes from 0 at level 0 to 1 at the last level.
+const PREFILTERED_MIP_LEVELS: u32 = 5;
+const BRDF_LUT_SIZE: u32 = 128;
+const WORKGROUP_SIZE: u32 = 8;
+// rgba16f is the only HDR format every device can both write from shaders and filter.
+const FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+const TEXEL_SIZE: u32 = 8;
+
+const CACHE_DIR: &str = "cache/ibl";
+// Bump whenever the shaders or map sizes change so stale caches are ignored.
+const CACHE_MAGIC: &[u8; 4] = b"IBL1";
+
+#[derive(Clone, Copy, Debug)]
+pub struct LightingSettings {
+    pub intensity: f32,
+    // Applied to every surface, as materials don't carry their own yet.
+    pub roughness: f32,
+    pub metallic: f32,
+}
+
+impl Default for LightingSettings {
+    fn default() -> Self {
+        Self {
+            intensity: 1.0,
+            roughness: 0.5,
+            metallic: 0.0,
+        }
+    }
+}
+
+// CPU copy of the precomputed maps in FORMAT, laid out as `Texture::cubemap` expects.
+struct IblData {
+    irradiance_size: u32,
+    irradiance: Vec<u8>,
+    prefiltered_size: u32,
+    prefiltered_mip_levels: u32,
+    prefiltered: Vec<u8>,
+    brdf_lut_size: u32,
+    brdf_lut: Vec<u8>,
+}
+
+impl IblData {
+    // Black 1x1 maps so set 3 is always valid, lighting stays off until an environment is loaded.
+    fn placeholder() -> Self {
+        Self {
+            irradiance_size: 1,
+            irradiance: vec![0; (6 * TEXEL_SIZE) as usize],
+            prefiltered_size: 1,
+            prefiltered_mip_levels: 1,
+            prefiltered: vec![0; (6 * TEXEL_SIZE) as usize],
+            brdf_lut_size: 1,
+            brdf_lut: vec![0; TEXEL_SIZE as usize],
+        }
+    }
+}
+
+pub struct IblMaps {
+    pub irradiance: Texture,
+    pub prefiltered: Texture,
+    pub brdf_lut: Texture,
+    // Bound at set 3 of the main pipeline layout.
+    pub descriptor_pool: vk::DescriptorPool,
+    pub descriptor_set: vk::DescriptorSet,
+    // False while the placeholder maps are bound.
+    pub loaded: bool,
+}
+
+impl IblMaps {
+    pub fn placeholder(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        pdevice: vk::PhysicalDevice,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+        descriptor_set_layout: vk::DescriptorSetLayout,
+    ) -> Self {
+        Self::upload(
+            instance,
+            device,
+            pdevice,
+            command_pool,
+            queue,
+            descriptor_set_layout,
+            &IblData::placeholder(),
+            false,
+        )
+    }
+
+    fn upload(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        pdevice: vk::PhysicalDevice,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+        descriptor_set_layout: vk::DescriptorSetLayout,
+        data: &IblData,
+        loaded: bool,
+    ) -> Self {
+        let irradiance = Texture::cubemap(
+            instance,
+            device,
+            pdevice,
+            command_pool,
+            queue,
+            data.irradiance_size,
+            1,
+            &data.irradiance,
+            FORMAT,
+        );
+        let prefiltered = Texture::cubemap(
+            instance,
+            device,
+            pdevice,
+            command_pool,
+            queue,
+            data.prefiltered_size,
+            data.prefiltered_mip_levels,
+            &data.prefiltered,
+            FORMAT,
+        );
+        let brdf_lut = Texture::from_pixels(
+            instance,
+            device,
+            pdevice,
+            command_pool,
+            queue,
+            data.brdf_lut_size,
+            data.brdf_lut_size,
+            &data.brdf_lut,
+            FORMAT,
+            vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        );
+        let (descriptor_pool, descriptor_set) = descriptors::create_sampler_descriptor_set(
+            device,
+            descriptor_set_layout,
+            &[&irradiance, &prefiltered, &brdf_lut],
+        );
+
+        Self {
+            irradiance,
+            prefiltered,
+            brdf_lut,
+            descriptor_pool,
+            descriptor_set,
+            loaded,
+        }
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            device.destroy_descriptor_pool(self.descriptor_pool, None);
+        }
+        self.irradiance.destroy(device);
+        self.prefiltered.destroy(device);
+        self.brdf_lut.destroy(device);
+    }
+}
+
+// Identifies the source files by path, size and modification time. None when they can't be
+// inspected, in which case nothing is cached.
+fn cache_key(path: &Path) -> Option<u64> {
+    let mut hasher = DefaultHasher::new();
+    CACHE_MAGIC.hash(&mut hasher);
+    path.canonicalize().ok()?.hash(&mut hasher);
+
+    let mut files = if path.is_dir() {
+        std::fs::read_dir(path)
+            .ok()?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .collect()
+    } else {
+        vec![path.to_path_buf()]
+    };
+    files.sort();
+    for file in files {
+        let metadata = std::fs::metadata(&file).ok()?;
+        file.file_name().hash(&mut hasher);
+        metadata.len().hash(&mut hasher);
+        metadata.modified().ok()?.hash(&mut hasher);
+    }
+    Some(hasher.finish())
+}
+
+fn cache_path(key: u64) -> PathBuf {
+    Path::new(CACHE_DIR).join(format!("{:016x}.bin", key))
+}
+
+fn read_cache(path: &Path, key: u64) -> Option<IblData> {
+    let mut bytes = Vec::new();
+    std::fs::File::open(path).ok()?.read_to_end(&mut bytes).ok()?;
+
+    let (magic, rest) = bytes.split_at_checked(4)?;
+    let (header, rest) = rest.split_at_checked(24)?;
+    let word = |i: usize| u32::from_le_bytes(header[8 + i * 4..12 + i * 4].try_into().unwrap());
+    if magic != CACHE_MAGIC || u64::from_le_bytes(header[..8].try_into().unwrap()) != key {
+        return None;
+    }
+
+    let (irradiance_size, prefiltered_size, prefiltered_mip_levels, brdf_lut_size) = (word(0), word(1), word(2), word(3));
+    let cube_bytes = |size: u32, mip_levels: u32| {
+        (0..mip_levels).map(|mip| 6 * (size >> mip).max(1).pow(2) * TEXEL_SIZE).sum::<u32>() as usize
+    };
+    let (irradiance, rest) = rest.split_at_checked(cube_bytes(irradiance_size, 1))?;
+    let (prefiltered, rest) = rest.split_at_checked(cube_bytes(prefiltered_size, prefiltered_mip_levels))?;
+    if rest.len() != (brdf_lut_size * brdf_lut_size * TEXEL_SIZE) as usize {
+        return None;
+    }
+
+    Some(IblData {
+        irradiance_size,
+        irradiance: irradiance.to_vec(),
+        prefiltered_size,
+        prefiltered_mip_levels,
+        prefiltered: prefiltered.to_vec(),
+        brdf_lut_size,
+        brdf_lut: rest.to_vec(),
+    })
+}
+
+fn write_cache(path: &Path, key: u64, data: &IblData) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    file.write_all(CACHE_MAGIC)?;
+    file.write_all(&key.to_le_bytes())?;
+    for word in [
+        data.irradiance_size,
+        data.prefiltered_size,
+        data.prefiltered_mip_levels,
+        data.brdf_lut_size,
+    ] {
+        file.write_all(&word.to_le_bytes())?;
+    }
+    file.write_all(&data.irradiance)?;
+    file.write_all(&data.prefiltered)?;
+    file.write_all(&data.brdf_lut)?;
+    file.flush()
+}
+
+impl VulkanApp {
+    // Loads `path` as the skybox (see `load_cubemap`) and lights the scene with it. The
+    // convolved maps are cached on disk, so later runs with the same files skip the compute.
+    pub fn load_environment(&mut self, path: &Path) -> Result<(), SkyboxError> {
+        self.load_skybox(path)?;
+
+        let key = cache_key(path);
+        let cached = key.and_then(|key| read_cache(&cache_path(key), key));
+        let data = match cached {
+            Some(data) => data,
+            None => {
+                let environment = &self.skybox.as_ref().unwrap().texture;
+                let environment = ComputeBinding::SampledImage {
+                    image: environment.image,
+                    view: environment.view,
+                    sampler: environment.sampler,
+                };
+                let data = self.compute_ibl(environment);
+                if let Some(key) = key {
+                    if let Err(error) = write_cache(&cache_path(key), key, &data) {
+                        eprintln!("Failed to cache lighting for {}: {}", path.display(), error);
+                    }
+                }
+                data
+            }
+        };
+
+        let maps = IblMaps::upload(
+            &self.instance,
+            &self.device,
+            self.physical_device,
+            self.command_pool,
+            self.graphics_queue,
+            self.ibl_descriptor_set_layout,
+            &data,
+            true,
+        );
+        unsafe {
+            self.device.device_wait_idle().unwrap();
+        }
+        std::mem::replace(&mut self.ibl, maps).destroy(&self.device);
+        Ok(())
+    }
+
+    // Whether the scene is lit by an environment rather than the flat placeholder maps.
+    pub fn environment_loaded(&self) -> bool {
+        self.ibl.loaded
+    }
+
+    pub fn lighting_mut(&mut self) -> &mut LightingSettings {
+        &mut self.lighting
+    }
+
+    // Convolves the environment into storage images with one-off compute passes and reads the
+    // results back for caching.
+    fn compute_ibl(&mut self, environment: ComputeBinding) -> IblData {
+        let usage = vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::TRANSFER_SRC;
+        let (irradiance_image, irradiance_memory) =
+            images::create_cube_image(&self.instance, &self.device, self.physical_device, IRRADIANCE_SIZE, 1, FORMAT, usage);
+        let (prefiltered_image, prefiltered_memory) = images::create_cube_image(
+            &self.instance,
+            &self.device,
+            self.physical_device,
+            PREFILTERED_SIZE,
+            PREFILTERED_MIP_LEVELS,
+            FORMAT,
+            usage,
+        );
+        let (brdf_lut_image, brdf_lut_memory) = images::create_image(
+            &self.instance,
+            &self.device,
+            self.physical_device,
+            BRDF_LUT_SIZE,
+            BRDF_LUT_SIZE,
+            FORMAT,
+            vk::SampleCountFlags::TYPE_1,
+            vk::ImageTiling::OPTIMAL,
+            usage,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        );
+        for (image, layer_count) in [(irradiance_image, 6), (prefiltered_image, 6), (brdf_lut_image, 1)] {
+            images::transition_image_layout(
+                &self.device,
+                self.command_pool,
+                self.graphics_queue,
+                image,
+                layer_count,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::GENERAL,
+            );
+        }
+
+        let irradiance_view = images::create_cube_storage_view(&self.device, irradiance_image, FORMAT, 0);
+        let prefiltered_views: Vec<vk::ImageView> = (0..PREFILTERED_MIP_LEVELS)
+            .map(|mip| images::create_cube_storage_view(&self.device, prefiltered_image, FORMAT, mip))
+            .collect();
+        let brdf_lut_view =
+            images::create_image_view(&self.device, brdf_lut_image, FORMAT, vk::ImageAspectFlags::COLOR);
+
+        let first_pass = self.compute_passes.len();
+        let groups = |size: u32| size.div_ceil(WORKGROUP_SIZE);
+        let pass = self.create_compute_pass(
+            include_bytes!(env!("IBL_IRRADIANCE_COMP_SHADER_PATH")),
+            &[
+                environment,
+                ComputeBinding::StorageImage { image: irradiance_image, view: irradiance_view },
+            ],
+            0,
+        );
+        self.run_compute(pass, &[], [groups(IRRADIANCE_SIZE), groups(IRRADIANCE_SIZE), 6]);
+        // one pass per level, since each writes through its own storage view
+        for (mip, &view) in prefiltered_views.iter().enumerate() {
+            let pass = self.create_compute_pass(
+                include_bytes!(env!("IBL_PREFILTER_COMP_SHADER_PATH")),
+                &[
+                    environment,
+                    ComputeBinding::StorageImage { image: prefiltered_image, view },
+                ],
+                std::mem::size_of::<f32>() as u32,
+            );
+            let roughness = mip as f32 / (PREFILTERED_MIP_LEVELS - 1) as f32;
+            let size = (PREFILTERED_SIZE >> mip).max(1);
+            self.run_compute(pass, &roughness.to_ne_bytes(), [groups(size), groups(size), 6]);
+        }
+        let pass = self.create_compute_pass(
+            include_bytes!(env!("IBL_BRDF_COMP_SHADER_PATH")),
+            &[ComputeBinding::StorageImage { image: brdf_lut_image, view: brdf_lut_view }],
+            0,
+        );
+        self.run_compute(pass, &[], [groups(BRDF_LUT_SIZE), groups(BRDF_LUT_SIZE), 1]);
+
+        let data = IblData {
+            irradiance_size: IRRADIANCE_SIZE,
+            irradiance: self.read_back(irradiance_image, IRRADIANCE_SIZE, 1, 6),
+            prefiltered_size: PREFILTERED_SIZE,
+            prefiltered_mip_levels: PREFILTERED_MIP_LEVELS,
+            prefiltered: self.read_back(prefiltered_image, PREFILTERED_SIZE, PREFILTERED_MIP_LEVELS, 6),
+            brdf_lut_size: BRDF_LUT_SIZE,
+            brdf_lut: self.read_back(brdf_lut_image, BRDF_LUT_SIZE, 1, 1),
+        };
+
+        for pass in self.compute_passes.drain(first_pass..) {
+            pass.destroy(&self.device);
+        }
+        unsafe {
+            for view in prefiltered_views.into_iter().chain([irradiance_view, brdf_lut_view]) {
+                self.device.destroy_image_view(view, None);
+            }
+            for (image, memory) in [
+                (irradiance_image, irradiance_memory),
+                (prefiltered_image, prefiltered_memory),
+                (brdf_lut_image, brdf_lut_memory),
+            ] {
+                self.device.destroy_image(image, None);
+                self.device.free_memory(memory, None);
+            }
+        }
+        data
+    }
+
+    fn read_back(&self, image: vk::Image, size: u32, mip_levels: u32, layer_count: u32) -> Vec<u8> {
+        let byte_count: u32 = (0..mip_levels)
+            .map(|mip| (size >> mip).max(1).pow(2) * layer_count * TEXEL_SIZE)
+            .sum();
+        let (buffer, memory) = buffers::create_buffer(
+            &self.instance,
+            &self.device,
+            self.physical_device,
+            byte_count as vk::DeviceSize,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        );
+        images::copy_image_mips_to_buffer(
+            &self.device,
+            self.command_pool,
+            self.graphics_queue,
+            image,
+            buffer,
+            size,
+            mip_levels,
+            layer_count,
+            TEXEL_SIZE,
+        );
+
+        unsafe {
+            let data_ptr = self
+                .device
+                .map_memory(memory, 0, byte_count as vk::DeviceSize, vk::MemoryMapFlags::empty())
+                .unwrap();
+            let pixels = std::slice::from_raw_parts(data_ptr as *const u8, byte_count as usize).to_vec();
+            self.device.unmap_memory(memory);
+            self.device.destroy_buffer(buffer, None);
+            self.device.free_memory(memory, None);
+            pixels
+        }
+    }
+}
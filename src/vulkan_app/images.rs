@@ -1,12 +1,13 @@
 use ash::{vk};
 
-use super::{buffers, VulkanApp};
+use super::{buffers, commands, VulkanApp};
 
 pub fn create_depth_resources(
     instance: &ash::Instance,
     device: &ash::Device,
     pdevice: vk::PhysicalDevice,
     extent: vk::Extent2D,
+    samples: vk::SampleCountFlags,
 ) -> (vk::Image, vk::DeviceMemory, vk::ImageView) {
     let depth_format = find_depth_format(instance, pdevice);
     let (depth_image, depth_image_memory) = create_image(
@@ -16,6 +17,7 @@ pub fn create_depth_resources(
         extent.width,
         extent.height,
         depth_format,
+        samples,
         vk::ImageTiling::OPTIMAL,
         vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
         vk::MemoryPropertyFlags::DEVICE_LOCAL,
@@ -25,6 +27,52 @@ pub fn create_depth_resources(
     (depth_image, depth_image_memory, depth_image_view)
 }
 
+// Multisampled color target that the render pass resolves into the swapchain image. Null
+// handles without multisampling.
+pub fn create_color_resources(
+    instance: &ash::Instance,
+    device: &ash::Device,
+    pdevice: vk::PhysicalDevice,
+    extent: vk::Extent2D,
+    format: vk::Format,
+    samples: vk::SampleCountFlags,
+) -> (vk::Image, vk::DeviceMemory, vk::ImageView) {
+    if samples == vk::SampleCountFlags::TYPE_1 {
+        return (vk::Image::null(), vk::DeviceMemory::null(), vk::ImageView::null());
+    }
+    let (color_image, color_image_memory) = create_image(
+        instance,
+        device,
+        pdevice,
+        extent.width,
+        extent.height,
+        format,
+        samples,
+        vk::ImageTiling::OPTIMAL,
+        vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+    );
+    let color_image_view = create_image_view(device, color_image, format, vk::ImageAspectFlags::COLOR);
+
+    (color_image, color_image_memory, color_image_view)
+}
+
+// Highest sample count not above `requested` that both color and depth attachments support.
+pub fn choose_sample_count(
+    instance: &ash::Instance,
+    pdevice: vk::PhysicalDevice,
+    requested: u32,
+) -> vk::SampleCountFlags {
+    let limits = unsafe { instance.get_physical_device_properties(pdevice) }.limits;
+    let supported = limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts;
+    [64, 32, 16, 8, 4, 2]
+        .into_iter()
+        .filter(|&count| count <= requested)
+        .map(vk::SampleCountFlags::from_raw)
+        .find(|&samples| supported.contains(samples))
+        .unwrap_or(vk::SampleCountFlags::TYPE_1)
+}
+
 pub fn find_depth_format(instance: &ash::Instance, pdevice: vk::PhysicalDevice) -> vk::Format {
     find_supported_format(
         instance,
@@ -61,6 +109,7 @@ pub fn create_image(
     width: u32,
     height: u32,
     format: vk::Format,
+    samples: vk::SampleCountFlags,
     tiling: vk::ImageTiling,
     usage: vk::ImageUsageFlags,
     properties: vk::MemoryPropertyFlags,
@@ -75,7 +124,7 @@ pub fn create_image(
         .initial_layout(vk::ImageLayout::UNDEFINED)
         .usage(usage)
         .sharing_mode(vk::SharingMode::EXCLUSIVE)
-        .samples(vk::SampleCountFlags::TYPE_1);
+        .samples(samples);
 
     let image = unsafe { device.create_image(&image_info, None).unwrap() };
 
@@ -114,3 +163,267 @@ pub fn create_image_view(
 
     unsafe { device.create_image_view(&view_info, None).unwrap() }
 }
+
+// Six-layer image that can be viewed as a cube map, one layer per face (+X, -X, +Y, -Y, +Z, -Z).
+pub fn create_cube_image(
+    instance: &ash::Instance,
+    device: &ash::Device,
+    pdevice: vk::PhysicalDevice,
+    size: u32,
+    mip_levels: u32,
+    format: vk::Format,
+    usage: vk::ImageUsageFlags,
+) -> (vk::Image, vk::DeviceMemory) {
+    let image_info = vk::ImageCreateInfo::builder()
+        .flags(vk::ImageCreateFlags::CUBE_COMPATIBLE)
+        .image_type(vk::ImageType::TYPE_2D)
+        .extent(vk::Extent3D { width: size, height: size, depth: 1 })
+        .mip_levels(mip_levels)
+        .array_layers(6)
+        .format(format)
+        .tiling(vk::ImageTiling::OPTIMAL)
+        .initial_layout(vk::ImageLayout::UNDEFINED)
+        .usage(usage)
+        .sharing_mode(vk::SharingMode::EXCLUSIVE)
+        .samples(vk::SampleCountFlags::TYPE_1);
+
+    let image = unsafe { device.create_image(&image_info, None).unwrap() };
+
+    let mem_requirements = unsafe { device.get_image_memory_requirements(image) };
+    let mem_type_index = buffers::find_memory_type(
+        instance,
+        pdevice,
+        mem_requirements.memory_type_bits,
+        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+    );
+
+    let alloc_info = vk::MemoryAllocateInfo::builder()
+        .allocation_size(mem_requirements.size)
+        .memory_type_index(mem_type_index);
+
+    let image_memory = unsafe { device.allocate_memory(&alloc_info, None).unwrap() };
+    unsafe {
+        device.bind_image_memory(image, image_memory, 0).unwrap();
+    }
+
+    (image, image_memory)
+}
+
+pub fn create_cube_image_view(device: &ash::Device, image: vk::Image, format: vk::Format) -> vk::ImageView {
+    let view_info = vk::ImageViewCreateInfo::builder()
+        .image(image)
+        .view_type(vk::ImageViewType::CUBE)
+        .format(format)
+        .subresource_range(vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: vk::REMAINING_MIP_LEVELS,
+            base_array_layer: 0,
+            layer_count: 6,
+        });
+
+    unsafe { device.create_image_view(&view_info, None).unwrap() }
+}
+
+// All six faces of one mip level as a 2D array, which is how compute shaders write cube maps.
+pub fn create_cube_storage_view(
+    device: &ash::Device,
+    image: vk::Image,
+    format: vk::Format,
+    mip_level: u32,
+) -> vk::ImageView {
+    let view_info = vk::ImageViewCreateInfo::builder()
+        .image(image)
+        .view_type(vk::ImageViewType::TYPE_2D_ARRAY)
+        .format(format)
+        .subresource_range(vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: mip_level,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 6,
+        });
+
+    unsafe { device.create_image_view(&view_info, None).unwrap() }
+}
+
+pub fn transition_image_layout(
+    device: &ash::Device,
+    command_pool: vk::CommandPool,
+    queue: vk::Queue,
+    image: vk::Image,
+    layer_count: u32,
+    old_layout: vk::ImageLayout,
+    new_layout: vk::ImageLayout,
+) {
+    let (src_access_mask, dst_access_mask, src_stage, dst_stage) = match (old_layout, new_layout) {
+        (vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL) => (
+            vk::AccessFlags::empty(),
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            vk::PipelineStageFlags::TRANSFER,
+        ),
+        (vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL) => (
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::AccessFlags::SHADER_READ,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+        ),
+        (vk::ImageLayout::UNDEFINED, vk::ImageLayout::GENERAL) => (
+            vk::AccessFlags::empty(),
+            vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+        ),
+        _ => panic!("Unsupported layout transition {:?} -> {:?}", old_layout, new_layout),
+    };
+
+    let barrier = vk::ImageMemoryBarrier::builder()
+        .old_layout(old_layout)
+        .new_layout(new_layout)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(image)
+        .subresource_range(vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: vk::REMAINING_MIP_LEVELS,
+            base_array_layer: 0,
+            layer_count,
+        })
+        .src_access_mask(src_access_mask)
+        .dst_access_mask(dst_access_mask);
+
+    let command_buffer = commands::begin_single_time_commands(device, command_pool);
+    unsafe {
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            src_stage,
+            dst_stage,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            std::slice::from_ref(&barrier),
+        );
+    }
+    commands::end_single_time_commands(device, command_pool, queue, command_buffer);
+}
+
+pub fn copy_buffer_to_image(
+    device: &ash::Device,
+    command_pool: vk::CommandPool,
+    queue: vk::Queue,
+    buffer: vk::Buffer,
+    image: vk::Image,
+    width: u32,
+    height: u32,
+    layer_count: u32,
+) {
+    let region = vk::BufferImageCopy::builder()
+        .buffer_offset(0)
+        .buffer_row_length(0)
+        .buffer_image_height(0)
+        .image_subresource(vk::ImageSubresourceLayers {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_level: 0,
+            base_array_layer: 0,
+            layer_count,
+        })
+        .image_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
+        .image_extent(vk::Extent3D { width, height, depth: 1 });
+
+    let command_buffer = commands::begin_single_time_commands(device, command_pool);
+    unsafe {
+        device.cmd_copy_buffer_to_image(
+            command_buffer,
+            buffer,
+            image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            std::slice::from_ref(&region),
+        );
+    }
+    commands::end_single_time_commands(device, command_pool, queue, command_buffer);
+}
+
+// One region per mip level of a square image, with the levels packed back to back in the buffer
+// and every layer of a level contiguous.
+fn mip_regions(size: u32, mip_levels: u32, layer_count: u32, texel_size: u32) -> Vec<vk::BufferImageCopy> {
+    let mut offset = 0;
+    (0..mip_levels)
+        .map(|mip_level| {
+            let mip_size = (size >> mip_level).max(1);
+            let region = vk::BufferImageCopy::builder()
+                .buffer_offset(offset)
+                .buffer_row_length(0)
+                .buffer_image_height(0)
+                .image_subresource(vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level,
+                    base_array_layer: 0,
+                    layer_count,
+                })
+                .image_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
+                .image_extent(vk::Extent3D { width: mip_size, height: mip_size, depth: 1 })
+                .build();
+            offset += (mip_size * mip_size * layer_count * texel_size) as vk::DeviceSize;
+            region
+        })
+        .collect()
+}
+
+pub fn copy_buffer_to_image_mips(
+    device: &ash::Device,
+    command_pool: vk::CommandPool,
+    queue: vk::Queue,
+    buffer: vk::Buffer,
+    image: vk::Image,
+    size: u32,
+    mip_levels: u32,
+    layer_count: u32,
+    texel_size: u32,
+) {
+    let regions = mip_regions(size, mip_levels, layer_count, texel_size);
+    let command_buffer = commands::begin_single_time_commands(device, command_pool);
+    unsafe {
+        device.cmd_copy_buffer_to_image(
+            command_buffer,
+            buffer,
+            image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &regions,
+        );
+    }
+    commands::end_single_time_commands(device, command_pool, queue, command_buffer);
+}
+
+// Reads back an image written by compute shaders. The image must be in GENERAL layout.
+pub fn copy_image_mips_to_buffer(
+    device: &ash::Device,
+    command_pool: vk::CommandPool,
+    queue: vk::Queue,
+    image: vk::Image,
+    buffer: vk::Buffer,
+    size: u32,
+    mip_levels: u32,
+    layer_count: u32,
+    texel_size: u32,
+) {
+    let regions = mip_regions(size, mip_levels, layer_count, texel_size);
+    let barrier = vk::MemoryBarrier::builder()
+        .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+        .dst_access_mask(vk::AccessFlags::TRANSFER_READ);
+    let command_buffer = commands::begin_single_time_commands(device, command_pool);
+    unsafe {
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            std::slice::from_ref(&barrier),
+            &[],
+            &[],
+        );
+        device.cmd_copy_image_to_buffer(command_buffer, image, vk::ImageLayout::GENERAL, buffer, &regions);
+    }
+    commands::end_single_time_commands(device, command_pool, queue, command_buffer);
+}
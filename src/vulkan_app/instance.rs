@@ -2,9 +2,11 @@ use ash::{vk, Entry};
 use raw_window_handle::HasRawDisplayHandle;
 use std::ffi::{CStr, CString};
 
-use super::utils::{vulkan_debug_callback, QueueFamilyIndices, SwapchainSupportDetails};
+use super::utils::{vulkan_debug_callback, DeviceFeatures, QueueFamilyIndices, SwapchainSupportDetails};
 
-pub fn create_instance(entry: &Entry, window: &winit::window::Window) -> ash::Instance {
+const VALIDATION_LAYER: &CStr = c"VK_LAYER_KHRONOS_validation";
+
+pub fn create_instance(entry: &Entry, window: &winit::window::Window, validation: bool) -> ash::Instance {
     let app_name = CString::new("Vulkan Triangle").unwrap();
     let engine_name = CString::new("No Engine").unwrap();
     let app_info = vk::ApplicationInfo::builder()
@@ -12,16 +14,39 @@ pub fn create_instance(entry: &Entry, window: &winit::window::Window) -> ash::In
         .application_version(vk::make_api_version(0, 1, 0, 0))
         .engine_name(&engine_name)
         .engine_version(vk::make_api_version(0, 1, 0, 0))
-        .api_version(vk::API_VERSION_1_0);
+        // 1.1 for vkGetPhysicalDeviceMemoryProperties2, used to report memory usage
+        .api_version(vk::API_VERSION_1_1);
 
     let mut extension_names =
         ash_window::enumerate_required_extensions(window.raw_display_handle())
             .unwrap()
             .to_vec();
     extension_names.push(ash::extensions::ext::DebugUtils::name().as_ptr());
+    // surfaces only report HDR color spaces with VK_EXT_swapchain_colorspace enabled
+    let available_extensions = entry.enumerate_instance_extension_properties(None).unwrap();
+    let has_colorspace_extension = available_extensions
+        .iter()
+        .any(|ext| unsafe { CStr::from_ptr(ext.extension_name.as_ptr()) } == vk::ExtSwapchainColorspaceFn::name());
+    if has_colorspace_extension {
+        extension_names.push(vk::ExtSwapchainColorspaceFn::name().as_ptr());
+    }
+
+    let mut layer_names = Vec::new();
+    if validation {
+        let available_layers = entry.enumerate_instance_layer_properties().unwrap();
+        let has_validation_layer = available_layers
+            .iter()
+            .any(|layer| unsafe { CStr::from_ptr(layer.layer_name.as_ptr()) } == VALIDATION_LAYER);
+        if has_validation_layer {
+            layer_names.push(VALIDATION_LAYER.as_ptr());
+        } else {
+            eprintln!("Validation requested but {} is not installed", VALIDATION_LAYER.to_string_lossy());
+        }
+    }
 
     let create_info = vk::InstanceCreateInfo::builder()
         .application_info(&app_info)
+        .enabled_layer_names(&layer_names)
         .enabled_extension_names(&extension_names);
 
     unsafe {
@@ -61,11 +86,48 @@ pub fn pick_physical_device(
     instance: &ash::Instance,
     surface_loader: &ash::extensions::khr::Surface,
     surface: vk::SurfaceKHR,
+    requested: Option<&str>,
 ) -> (vk::PhysicalDevice, QueueFamilyIndices) {
     let physical_devices = unsafe { instance.enumerate_physical_devices().unwrap() };
-    let physical_device = physical_devices
-        .into_iter()
-        .find(|pdevice| is_device_suitable(instance, surface_loader, surface, *pdevice))
+    let names: Vec<String> = physical_devices
+        .iter()
+        .map(|&pdevice| {
+            let properties = unsafe { instance.get_physical_device_properties(pdevice) };
+            unsafe { CStr::from_ptr(properties.device_name.as_ptr()) }
+                .to_string_lossy()
+                .into_owned()
+        })
+        .collect();
+
+    // an index into the enumeration order, otherwise a case-insensitive part of the name
+    let requested_device = requested.and_then(|requested| {
+        let position = match requested.parse::<usize>() {
+            Ok(index) => (index < physical_devices.len()).then_some(index),
+            Err(_) => {
+                let requested = requested.to_lowercase();
+                names.iter().position(|name| name.to_lowercase().contains(&requested))
+            }
+        };
+        let found = position
+            .map(|index| physical_devices[index])
+            .filter(|&pdevice| is_device_suitable(instance, surface_loader, surface, pdevice));
+        if found.is_none() {
+            let list: Vec<String> = names.iter().enumerate().map(|(i, name)| format!("{}: {}", i, name)).collect();
+            eprintln!(
+                "GPU '{}' is not available or not suitable, using the first suitable one. GPUs: {}",
+                requested,
+                list.join(", ")
+            );
+        }
+        found
+    });
+    let physical_device = requested_device
+        .or_else(|| {
+            physical_devices
+                .iter()
+                .copied()
+                .find(|&pdevice| is_device_suitable(instance, surface_loader, surface, pdevice))
+        })
         .expect("Failed to find a suitable GPU!");
 
     let indices = find_queue_families(instance, surface_loader, surface, physical_device);
@@ -144,6 +206,14 @@ pub fn find_queue_families(
         }
     }
 
+    indices.compute_family = queue_families
+        .iter()
+        .position(|queue_family| {
+            queue_family.queue_flags.contains(vk::QueueFlags::COMPUTE)
+                && !queue_family.queue_flags.contains(vk::QueueFlags::GRAPHICS)
+        })
+        .map(|i| i as u32);
+
     indices
 }
 
@@ -151,10 +221,13 @@ pub fn create_logical_device(
     instance: &ash::Instance,
     pdevice: vk::PhysicalDevice,
     indices: &QueueFamilyIndices,
-) -> (ash::Device, vk::Queue, vk::Queue) {
+) -> (ash::Device, vk::Queue, vk::Queue, vk::Queue, DeviceFeatures) {
     let mut unique_queue_families = std::collections::HashSet::new();
     unique_queue_families.insert(indices.graphics_family.unwrap());
     unique_queue_families.insert(indices.present_family.unwrap());
+    if let Some(compute_family) = indices.compute_family {
+        unique_queue_families.insert(compute_family);
+    }
 
     let queue_priorities = [1.0];
     let mut queue_create_infos = vec![];
@@ -166,8 +239,40 @@ pub fn create_logical_device(
         queue_create_infos.push(queue_create_info);
     }
 
-    let physical_device_features = vk::PhysicalDeviceFeatures::builder();
-    let required_extensions = [ash::extensions::khr::Swapchain::name().as_ptr()];
+    // Features used by GPU-driven rendering are enabled when present and reported back.
+    let supported_features = unsafe { instance.get_physical_device_features(pdevice) };
+    let available_extensions = unsafe {
+        instance
+            .enumerate_device_extension_properties(pdevice)
+            .unwrap()
+    };
+    let has_extension = |name: &CStr| {
+        available_extensions
+            .iter()
+            .any(|ext| unsafe { CStr::from_ptr(ext.extension_name.as_ptr()) } == name)
+    };
+
+    let features = DeviceFeatures {
+        draw_indirect_first_instance: supported_features.draw_indirect_first_instance == vk::TRUE,
+        multi_draw_indirect: supported_features.multi_draw_indirect == vk::TRUE,
+        draw_indirect_count: has_extension(ash::extensions::khr::DrawIndirectCount::name()),
+        memory_budget: has_extension(vk::ExtMemoryBudgetFn::name()),
+        fill_mode_non_solid: supported_features.fill_mode_non_solid == vk::TRUE,
+        pipeline_statistics_query: supported_features.pipeline_statistics_query == vk::TRUE,
+    };
+
+    let physical_device_features = vk::PhysicalDeviceFeatures::builder()
+        .draw_indirect_first_instance(features.draw_indirect_first_instance)
+        .multi_draw_indirect(features.multi_draw_indirect)
+        .fill_mode_non_solid(features.fill_mode_non_solid)
+        .pipeline_statistics_query(features.pipeline_statistics_query);
+    let mut required_extensions = vec![ash::extensions::khr::Swapchain::name().as_ptr()];
+    if features.draw_indirect_count {
+        required_extensions.push(ash::extensions::khr::DrawIndirectCount::name().as_ptr());
+    }
+    if features.memory_budget {
+        required_extensions.push(vk::ExtMemoryBudgetFn::name().as_ptr());
+    }
 
     let create_info = vk::DeviceCreateInfo::builder()
         .queue_create_infos(&queue_create_infos)
@@ -178,6 +283,41 @@ pub fn create_logical_device(
 
     let graphics_queue = unsafe { device.get_device_queue(indices.graphics_family.unwrap(), 0) };
     let present_queue = unsafe { device.get_device_queue(indices.present_family.unwrap(), 0) };
+    // without a dedicated family, compute work goes to the graphics queue
+    let compute_queue = indices
+        .compute_family
+        .map_or(graphics_queue, |family| unsafe { device.get_device_queue(family, 0) });
+
+    (device, graphics_queue, present_queue, compute_queue, features)
+}
+
+// Device-local memory as (used, total) bytes. Usage is only known with VK_EXT_memory_budget.
+pub fn query_device_memory(
+    instance: &ash::Instance,
+    pdevice: vk::PhysicalDevice,
+    memory_budget: bool,
+) -> (Option<u64>, u64) {
+    let mut budget = vk::PhysicalDeviceMemoryBudgetPropertiesEXT::default();
+    let memory_properties = {
+        let mut properties = vk::PhysicalDeviceMemoryProperties2::builder();
+        if memory_budget {
+            properties = properties.push_next(&mut budget);
+        }
+        unsafe { instance.get_physical_device_memory_properties2(pdevice, &mut properties) };
+        properties.memory_properties
+    };
 
-    (device, graphics_queue, present_queue)
+    let device_local_heaps: Vec<usize> = (0..memory_properties.memory_heap_count as usize)
+        .filter(|&i| {
+            memory_properties.memory_heaps[i]
+                .flags
+                .contains(vk::MemoryHeapFlags::DEVICE_LOCAL)
+        })
+        .collect();
+    let total = device_local_heaps
+        .iter()
+        .map(|&i| memory_properties.memory_heaps[i].size)
+        .sum();
+    let used = memory_budget.then(|| device_local_heaps.iter().map(|&i| budget.heap_usage[i]).sum());
+    (used, total)
 }
@@ -0,0 +1,234 @@
+use ash::vk;
+use cgmath::{InnerSpace, Vector3};
+
+use super::{buffers, utils::QueueFamilyIndices, vertex::{Vertex, generate_wireframe_vertices}};
+
+const CUBE_COLOR: [f32; 3] = [0.298, 0.686, 0.314];
+
+const CUBE_VERTICES: [Vertex; 8] = [
+    Vertex::new([-0.5, -0.5, 0.5], CUBE_COLOR),
+    Vertex::new([0.5, -0.5, 0.5], CUBE_COLOR),
+    Vertex::new([0.5, 0.5, 0.5], CUBE_COLOR),
+    Vertex::new([-0.5, 0.5, 0.5], CUBE_COLOR),
+    Vertex::new([-0.5, -0.5, -0.5], CUBE_COLOR),
+    Vertex::new([0.5, -0.5, -0.5], CUBE_COLOR),
+    Vertex::new([0.5, 0.5, -0.5], CUBE_COLOR),
+    Vertex::new([-0.5, 0.5, -0.5], CUBE_COLOR),
+];
+
+const CUBE_INDICES: [u16; 36] = [
+    0, 1, 2, 2, 3, 0, // front
+    4, 6, 5, 4, 7, 6, // back
+    0, 7, 4, 0, 3, 7, // left
+    1, 5, 6, 6, 2, 1, // right
+    3, 2, 6, 6, 7, 3, // top
+    0, 5, 1, 5, 0, 4, // bottom
+];
+
+pub enum MeshIndices {
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+
+impl MeshIndices {
+    // Picks the narrowest index type that can address every vertex.
+    pub fn from_u32(indices: Vec<u32>) -> Self {
+        if indices.iter().all(|&i| i <= u16::MAX as u32) {
+            MeshIndices::U16(indices.into_iter().map(|i| i as u16).collect())
+        } else {
+            MeshIndices::U32(indices)
+        }
+    }
+
+    pub fn sequential(count: usize) -> Self {
+        Self::from_u32((0..count as u32).collect())
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            MeshIndices::U16(data) => data.len(),
+            MeshIndices::U32(data) => data.len(),
+        }
+    }
+
+    pub fn index_type(&self) -> vk::IndexType {
+        match self {
+            MeshIndices::U16(_) => vk::IndexType::UINT16,
+            MeshIndices::U32(_) => vk::IndexType::UINT32,
+        }
+    }
+}
+
+// Converts a Y-up position or direction into the renderer's Z-up convention.
+pub fn y_up_to_z_up(v: [f32; 3]) -> [f32; 3] {
+    [v[0], -v[2], v[1]]
+}
+
+pub struct MeshData {
+    pub name: String,
+    pub vertices: Vec<Vertex>,
+    pub indices: MeshIndices,
+    pub topology: vk::PrimitiveTopology,
+    // index into the material list of the loader that produced the mesh
+    pub material: Option<usize>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+}
+
+impl Aabb {
+    pub fn from_vertices(vertices: &[Vertex]) -> Self {
+        let mut iter = vertices.iter().map(|v| Vector3::from(v.pos));
+        let first = match iter.next() {
+            Some(p) => p,
+            None => return Self { min: Vector3::new(0.0, 0.0, 0.0), max: Vector3::new(0.0, 0.0, 0.0) },
+        };
+        iter.fold(Self { min: first, max: first }, |aabb, p| Self {
+            min: Vector3::new(aabb.min.x.min(p.x), aabb.min.y.min(p.y), aabb.min.z.min(p.z)),
+            max: Vector3::new(aabb.max.x.max(p.x), aabb.max.y.max(p.y), aabb.max.z.max(p.z)),
+        })
+    }
+
+    pub fn center(&self) -> Vector3<f32> {
+        (self.min + self.max) * 0.5
+    }
+
+    // Half the size of the box along each axis.
+    pub fn extents(&self) -> Vector3<f32> {
+        (self.max - self.min) * 0.5
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct BoundingSphere {
+    pub center: Vector3<f32>,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    // Sphere around the box center; looser than a minimal sphere but cheap and never misses a vertex.
+    pub fn from_aabb(aabb: &Aabb) -> Self {
+        Self {
+            center: aabb.center(),
+            radius: aabb.extents().magnitude(),
+        }
+    }
+}
+
+pub struct Mesh {
+    pub vertex_buffer: vk::Buffer,
+    pub vertex_buffer_memory: vk::DeviceMemory,
+    pub index_buffer: vk::Buffer,
+    pub index_buffer_memory: vk::DeviceMemory,
+    pub index_count: u32,
+    pub index_type: vk::IndexType,
+    pub topology: vk::PrimitiveTopology,
+    pub bounds: Aabb,
+    pub bounding_sphere: BoundingSphere,
+}
+
+impl Mesh {
+    pub fn new(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        pdevice: vk::PhysicalDevice,
+        queue_family_indices: &QueueFamilyIndices,
+        vertices: &[Vertex],
+        indices: &MeshIndices,
+        topology: vk::PrimitiveTopology,
+    ) -> Self {
+        let (vertex_buffer, vertex_buffer_memory) = buffers::create_vertex_buffer(
+            instance,
+            device,
+            pdevice,
+            queue_family_indices,
+            vertices,
+        );
+        let (index_buffer, index_buffer_memory) = match indices {
+            MeshIndices::U16(data) => buffers::create_index_buffer(
+                instance,
+                device,
+                pdevice,
+                queue_family_indices,
+                data,
+            ),
+            MeshIndices::U32(data) => buffers::create_index_buffer(
+                instance,
+                device,
+                pdevice,
+                queue_family_indices,
+                data,
+            ),
+        };
+        let bounds = Aabb::from_vertices(vertices);
+
+        Self {
+            vertex_buffer,
+            vertex_buffer_memory,
+            index_buffer,
+            index_buffer_memory,
+            index_count: indices.len() as u32,
+            index_type: indices.index_type(),
+            topology,
+            bounds,
+            bounding_sphere: BoundingSphere::from_aabb(&bounds),
+        }
+    }
+
+    pub fn cube(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        pdevice: vk::PhysicalDevice,
+        queue_family_indices: &QueueFamilyIndices,
+    ) -> Self {
+        Self::new(
+            instance,
+            device,
+            pdevice,
+            queue_family_indices,
+            &CUBE_VERTICES,
+            &MeshIndices::U16(CUBE_INDICES.to_vec()),
+            vk::PrimitiveTopology::TRIANGLE_LIST,
+        )
+    }
+
+    pub fn wireframe_grid(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        pdevice: vk::PhysicalDevice,
+        queue_family_indices: &QueueFamilyIndices,
+        divisions: u32,
+        color: [f32; 3],
+    ) -> Self {
+        let vertices = generate_wireframe_vertices(divisions, color);
+        Self::new(
+            instance,
+            device,
+            pdevice,
+            queue_family_indices,
+            &vertices,
+            &MeshIndices::sequential(vertices.len()),
+            vk::PrimitiveTopology::LINE_LIST,
+        )
+    }
+
+    pub fn triangle_count(&self) -> u64 {
+        if self.topology == vk::PrimitiveTopology::TRIANGLE_LIST {
+            (self.index_count / 3) as u64
+        } else {
+            0
+        }
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            device.destroy_buffer(self.index_buffer, None);
+            device.free_memory(self.index_buffer_memory, None);
+            device.destroy_buffer(self.vertex_buffer, None);
+            device.free_memory(self.vertex_buffer_memory, None);
+        }
+    }
+}
@@ -1,11 +1,26 @@
-pub const WIDTH: u32 = 800;
-pub const HEIGHT: u32 = 600;
-
-pub use app::VulkanApp;
+pub use app::{RenderMode, VulkanApp};
+pub use swapchain::PresentMode;
+pub use gui::GuiFrame;
+pub use gltf_loader::ImportedCamera;
+pub use particles::{EmitterSettings, SpawnShape};
 
 mod app;
 mod utils;
 mod vertex;
+mod mesh;
+mod culling;
+mod gpu_culling;
+mod compute;
+mod particles;
+mod skybox;
+mod ibl;
+mod overlay;
+mod gui;
+mod profiler;
+mod queries;
+mod screenshot;
+mod obj_loader;
+mod gltf_loader;
 
 mod instance;
 mod swapchain;
@@ -14,3 +29,4 @@ mod buffers;
 mod images;
 mod commands;
 mod descriptors;
+mod texture;
@@ -0,0 +1,350 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use ash::vk;
+use cgmath::{InnerSpace, Vector3};
+
+use super::mesh::{y_up_to_z_up, MeshData, MeshIndices};
+use super::vertex::Vertex;
+
+const DEFAULT_COLOR: [f32; 3] = [0.8, 0.8, 0.8];
+
+#[derive(Debug)]
+pub struct ObjError {
+    pub path: PathBuf,
+    pub line: usize,
+    pub message: String,
+}
+
+impl ObjError {
+    fn new(path: &Path, line: usize, message: impl Into<String>) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // line 0 means the error is not tied to a specific line (e.g. I/O failures)
+        if self.line > 0 {
+            write!(f, "{}:{}: {}", self.path.display(), self.line, self.message)
+        } else {
+            write!(f, "{}: {}", self.path.display(), self.message)
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct VertexKey {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+    color: [u32; 3],
+}
+
+struct MeshBuilder {
+    name: String,
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+    lookup: HashMap<VertexKey, u32>,
+    // position index and whether the file supplied a normal, per output vertex
+    sources: Vec<(usize, bool)>,
+}
+
+impl MeshBuilder {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            lookup: HashMap::new(),
+            sources: Vec::new(),
+        }
+    }
+
+    fn vertex(
+        &mut self,
+        key: VertexKey,
+        positions: &[[f32; 3]],
+        uvs: &[[f32; 2]],
+        normals: &[[f32; 3]],
+        color: [f32; 3],
+    ) -> u32 {
+        if let Some(&index) = self.lookup.get(&key) {
+            return index;
+        }
+
+        let mut vertex = Vertex::new(y_up_to_z_up(positions[key.position]), color);
+        if let Some(uv) = key.uv {
+            // OBJ has v pointing up, Vulkan samples with v pointing down
+            vertex.uv = [uvs[uv][0], 1.0 - uvs[uv][1]];
+        }
+        if let Some(normal) = key.normal {
+            vertex.normal = y_up_to_z_up(normals[normal]);
+        }
+
+        let index = self.vertices.len() as u32;
+        self.vertices.push(vertex);
+        self.sources.push((key.position, key.normal.is_some()));
+        self.lookup.insert(key, index);
+        index
+    }
+
+    fn finish(mut self) -> Option<MeshData> {
+        if self.indices.is_empty() {
+            return None;
+        }
+
+        // Faces without `vn` get smooth normals averaged over every face sharing the position.
+        if self.sources.iter().any(|&(_, has_normal)| !has_normal) {
+            let mut accumulated: HashMap<usize, Vector3<f32>> = HashMap::new();
+            for triangle in self.indices.chunks_exact(3) {
+                let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|i| i as usize);
+                let pa = Vector3::from(self.vertices[a].pos);
+                let pb = Vector3::from(self.vertices[b].pos);
+                let pc = Vector3::from(self.vertices[c].pos);
+                let face_normal = (pb - pa).cross(pc - pa);
+                for i in [a, b, c] {
+                    *accumulated
+                        .entry(self.sources[i].0)
+                        .or_insert(Vector3::new(0.0, 0.0, 0.0)) += face_normal;
+                }
+            }
+            for (vertex, &(position, has_normal)) in self.vertices.iter_mut().zip(self.sources.iter()) {
+                if has_normal {
+                    continue;
+                }
+                let normal = accumulated[&position];
+                if normal.magnitude2() > 0.0 {
+                    vertex.normal = normal.normalize().into();
+                }
+            }
+        }
+
+        Some(MeshData {
+            name: self.name,
+            vertices: self.vertices,
+            indices: MeshIndices::from_u32(self.indices),
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            material: None,
+        })
+    }
+}
+
+pub fn load_obj(path: &Path) -> Result<Vec<MeshData>, ObjError> {
+    let source = std::fs::read_to_string(path).map_err(|e| ObjError::new(path, 0, e.to_string()))?;
+
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    let mut normals: Vec<[f32; 3]> = Vec::new();
+    let mut uvs: Vec<[f32; 2]> = Vec::new();
+    let mut materials: HashMap<String, [f32; 3]> = HashMap::new();
+    let mut color = DEFAULT_COLOR;
+    let mut builders = vec![MeshBuilder::new("default")];
+
+    for (line_index, raw_line) in source.lines().enumerate() {
+        let line_number = line_index + 1;
+        let line = raw_line.split('#').next().unwrap_or("").trim();
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        match keyword {
+            "v" => positions.push(parse_floats::<3>(&args, 3, path, line_number)?),
+            "vn" => normals.push(parse_floats::<3>(&args, 3, path, line_number)?),
+            "vt" => uvs.push(parse_floats::<2>(&args, 1, path, line_number)?),
+            "f" => {
+                if args.len() < 3 {
+                    return Err(ObjError::new(
+                        path,
+                        line_number,
+                        format!("face has {} vertices, at least 3 are required", args.len()),
+                    ));
+                }
+                let mut corners = Vec::with_capacity(args.len());
+                for corner in args.iter() {
+                    let mut key = parse_corner(corner, &positions, &uvs, &normals, path, line_number)?;
+                    key.color = color.map(f32::to_bits);
+                    corners.push(key);
+                }
+
+                let builder = builders.last_mut().unwrap();
+                let corners: Vec<u32> = corners
+                    .into_iter()
+                    .map(|key| builder.vertex(key, &positions, &uvs, &normals, color))
+                    .collect();
+                // fan triangulation, fine for the convex polygons exporters emit
+                for i in 1..corners.len() - 1 {
+                    builder.indices.extend_from_slice(&[corners[0], corners[i], corners[i + 1]]);
+                }
+            }
+            "o" => {
+                let name = args.join(" ");
+                let builder = builders.last_mut().unwrap();
+                if builder.indices.is_empty() {
+                    builder.name = name;
+                } else {
+                    builders.push(MeshBuilder::new(&name));
+                }
+            }
+            "mtllib" => {
+                let base = path.parent().unwrap_or_else(|| Path::new(""));
+                let mtl_path = base.join(args.join(" "));
+                match std::fs::read_to_string(&mtl_path) {
+                    Ok(mtl_source) => materials.extend(parse_mtl(&mtl_path, &mtl_source)?),
+                    Err(e) => eprintln!(
+                        "{}:{}: could not read material library {}: {}",
+                        path.display(),
+                        line_number,
+                        mtl_path.display(),
+                        e
+                    ),
+                }
+            }
+            "usemtl" => {
+                let name = args.join(" ");
+                color = match materials.get(&name) {
+                    Some(&diffuse) => diffuse,
+                    None => {
+                        eprintln!("{}:{}: unknown material '{}'", path.display(), line_number, name);
+                        DEFAULT_COLOR
+                    }
+                };
+            }
+            // groups, smoothing groups and other statements do not affect the mesh layout
+            _ => {}
+        }
+    }
+
+    let meshes: Vec<MeshData> = builders.into_iter().filter_map(MeshBuilder::finish).collect();
+    if meshes.is_empty() {
+        return Err(ObjError::new(path, 0, "file contains no faces"));
+    }
+
+    Ok(meshes)
+}
+
+fn parse_mtl(path: &Path, source: &str) -> Result<HashMap<String, [f32; 3]>, ObjError> {
+    let mut materials = HashMap::new();
+    let mut current: Option<String> = None;
+
+    for (line_index, raw_line) in source.lines().enumerate() {
+        let line_number = line_index + 1;
+        let line = raw_line.split('#').next().unwrap_or("").trim();
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        match keyword {
+            "newmtl" => {
+                let name = args.join(" ");
+                if name.is_empty() {
+                    return Err(ObjError::new(path, line_number, "newmtl without a name"));
+                }
+                materials.insert(name.clone(), DEFAULT_COLOR);
+                current = Some(name);
+            }
+            "Kd" => {
+                let name = current.as_ref().ok_or_else(|| {
+                    ObjError::new(path, line_number, "Kd before any newmtl statement")
+                })?;
+                materials.insert(name.clone(), parse_floats::<3>(&args, 3, path, line_number)?);
+            }
+            _ => {}
+        }
+    }
+
+    Ok(materials)
+}
+
+// Parses up to N floats, requiring at least `required`; missing trailing values are zero.
+fn parse_floats<const N: usize>(
+    args: &[&str],
+    required: usize,
+    path: &Path,
+    line: usize,
+) -> Result<[f32; N], ObjError> {
+    if args.len() < required {
+        return Err(ObjError::new(
+            path,
+            line,
+            format!("expected {} values, found {}", required, args.len()),
+        ));
+    }
+
+    let mut values = [0.0; N];
+    for (value, arg) in values.iter_mut().zip(args.iter()) {
+        *value = arg
+            .parse()
+            .map_err(|_| ObjError::new(path, line, format!("invalid number '{}'", arg)))?;
+    }
+    Ok(values)
+}
+
+fn parse_corner(
+    corner: &str,
+    positions: &[[f32; 3]],
+    uvs: &[[f32; 2]],
+    normals: &[[f32; 3]],
+    path: &Path,
+    line: usize,
+) -> Result<VertexKey, ObjError> {
+    let mut parts = corner.split('/');
+    let position = match parts.next() {
+        Some(part) if !part.is_empty() => resolve_index(part, positions.len(), "position", path, line)?,
+        _ => return Err(ObjError::new(path, line, format!("face vertex '{}' has no position", corner))),
+    };
+    let uv = match parts.next() {
+        Some(part) if !part.is_empty() => Some(resolve_index(part, uvs.len(), "texture coordinate", path, line)?),
+        _ => None,
+    };
+    let normal = match parts.next() {
+        Some(part) if !part.is_empty() => Some(resolve_index(part, normals.len(), "normal", path, line)?),
+        _ => None,
+    };
+
+    Ok(VertexKey {
+        position,
+        uv,
+        normal,
+        color: [0; 3],
+    })
+}
+
+// OBJ indices are 1-based, negative values count back from the last defined element.
+fn resolve_index(
+    text: &str,
+    count: usize,
+    kind: &str,
+    path: &Path,
+    line: usize,
+) -> Result<usize, ObjError> {
+    let index: i64 = text
+        .parse()
+        .map_err(|_| ObjError::new(path, line, format!("invalid {} index '{}'", kind, text)))?;
+    let resolved = if index > 0 {
+        index - 1
+    } else {
+        count as i64 + index
+    };
+
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(ObjError::new(
+            path,
+            line,
+            format!("{} index {} out of range ({} defined)", kind, index, count),
+        ));
+    }
+    Ok(resolved as usize)
+}
@@ -0,0 +1,473 @@
+use std::collections::VecDeque;
+use std::mem::offset_of;
+
+use ash::vk;
+
+use super::texture::Texture;
+use super::{app::DrawCommand, buffers, descriptors, instance, VulkanApp};
+
+const MAX_QUADS: usize = 4096;
+const FRAME_HISTORY: usize = 120;
+
+// Glyphs are 5x7 pixels stored in 6x8 cells, 16 cells per atlas row.
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+const CELL_WIDTH: u32 = 6;
+const CELL_HEIGHT: u32 = 8;
+const ATLAS_COLUMNS: u32 = 16;
+const FIRST_CHAR: u8 = b' ';
+// One past the last printable glyph, used as a fully opaque cell for panels and graph bars.
+const SOLID_GLYPH: u32 = FONT.len() as u32;
+
+const TEXT_SCALE: f32 = 2.0;
+const MARGIN: f32 = 8.0;
+const PADDING: f32 = 6.0;
+const LINE_SPACING: f32 = 4.0;
+const GRAPH_HEIGHT: f32 = 60.0;
+const GRAPH_BAR_WIDTH: f32 = 2.0;
+// Frame time shown at the top of the graph; slower frames are clipped.
+const GRAPH_MAX_MS: f32 = 50.0;
+
+const TEXT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const PANEL_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+
+// Rows top to bottom, bit 4 is the leftmost pixel. Covers ' ' through '_', lowercase letters are
+// drawn with their uppercase glyphs.
+const FONT: [[u8; 7]; 64] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x04, 0x04, 0x04, 0x04, 0x00, 0x00, 0x04], // '!'
+    [0x0a, 0x0a, 0x0a, 0x00, 0x00, 0x00, 0x00], // '"'
+    [0x0a, 0x0a, 0x1f, 0x0a, 0x1f, 0x0a, 0x0a], // '#'
+    [0x04, 0x0f, 0x14, 0x0e, 0x05, 0x1e, 0x04], // '$'
+    [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03], // '%'
+    [0x0c, 0x12, 0x14, 0x08, 0x15, 0x12, 0x0d], // '&'
+    [0x0c, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00], // '''
+    [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02], // '('
+    [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08], // ')'
+    [0x00, 0x04, 0x15, 0x0e, 0x15, 0x04, 0x00], // '*'
+    [0x00, 0x04, 0x04, 0x1f, 0x04, 0x04, 0x00], // '+'
+    [0x00, 0x00, 0x00, 0x00, 0x0c, 0x04, 0x08], // ','
+    [0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00], // '-'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c], // '.'
+    [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00], // '/'
+    [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e], // '0'
+    [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e], // '1'
+    [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f], // '2'
+    [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e], // '3'
+    [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02], // '4'
+    [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e], // '5'
+    [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e], // '6'
+    [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08], // '7'
+    [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e], // '8'
+    [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c], // '9'
+    [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x0c, 0x00], // ':'
+    [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x04, 0x08], // ';'
+    [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02], // '<'
+    [0x00, 0x00, 0x1f, 0x00, 0x1f, 0x00, 0x00], // '='
+    [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08], // '>'
+    [0x0e, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04], // '?'
+    [0x0e, 0x11, 0x01, 0x0d, 0x15, 0x15, 0x0e], // '@'
+    [0x0e, 0x11, 0x11, 0x11, 0x1f, 0x11, 0x11], // 'A'
+    [0x1e, 0x11, 0x11, 0x1e, 0x11, 0x11, 0x1e], // 'B'
+    [0x0e, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0e], // 'C'
+    [0x1c, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1c], // 'D'
+    [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x1f], // 'E'
+    [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x10], // 'F'
+    [0x0e, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0f], // 'G'
+    [0x11, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11], // 'H'
+    [0x0e, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e], // 'I'
+    [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0c], // 'J'
+    [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11], // 'K'
+    [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1f], // 'L'
+    [0x11, 0x1b, 0x15, 0x15, 0x11, 0x11, 0x11], // 'M'
+    [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11], // 'N'
+    [0x0e, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e], // 'O'
+    [0x1e, 0x11, 0x11, 0x1e, 0x10, 0x10, 0x10], // 'P'
+    [0x0e, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0d], // 'Q'
+    [0x1e, 0x11, 0x11, 0x1e, 0x14, 0x12, 0x11], // 'R'
+    [0x0f, 0x10, 0x10, 0x0e, 0x01, 0x01, 0x1e], // 'S'
+    [0x1f, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04], // 'T'
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e], // 'U'
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x0a, 0x04], // 'V'
+    [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0a], // 'W'
+    [0x11, 0x11, 0x0a, 0x04, 0x0a, 0x11, 0x11], // 'X'
+    [0x11, 0x11, 0x11, 0x0a, 0x04, 0x04, 0x04], // 'Y'
+    [0x1f, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1f], // 'Z'
+    [0x0e, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0e], // '['
+    [0x00, 0x10, 0x08, 0x04, 0x02, 0x01, 0x00], // '\'
+    [0x0e, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0e], // ']'
+    [0x04, 0x0a, 0x11, 0x00, 0x00, 0x00, 0x00], // '^'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1f], // '_'
+];
+
+// Positions are in pixels from the top left corner, uvs in atlas texels.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct OverlayVertex {
+    pub position: [f32; 2],
+    pub uv: [f32; 2],
+    pub color: [f32; 4],
+}
+
+impl OverlayVertex {
+    pub fn get_binding_description() -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription::builder()
+            .binding(0)
+            .stride(std::mem::size_of::<Self>() as u32)
+            .input_rate(vk::VertexInputRate::VERTEX)
+            .build()
+    }
+
+    pub fn get_attribute_descriptions() -> [vk::VertexInputAttributeDescription; 3] {
+        [
+            vk::VertexInputAttributeDescription::builder()
+                .binding(0)
+                .location(0)
+                .format(vk::Format::R32G32_SFLOAT)
+                .offset(offset_of!(Self, position) as u32)
+                .build(),
+            vk::VertexInputAttributeDescription::builder()
+                .binding(0)
+                .location(1)
+                .format(vk::Format::R32G32_SFLOAT)
+                .offset(offset_of!(Self, uv) as u32)
+                .build(),
+            vk::VertexInputAttributeDescription::builder()
+                .binding(0)
+                .location(2)
+                .format(vk::Format::R32G32B32A32_SFLOAT)
+                .offset(offset_of!(Self, color) as u32)
+                .build(),
+        ]
+    }
+}
+
+pub struct Overlay {
+    pub enabled: bool,
+    pub font: Texture,
+    // Allocated from the material pool, like the skybox.
+    pub descriptor_set: vk::DescriptorSet,
+    pub vertex_buffer: vk::Buffer,
+    pub vertex_buffer_memory: vk::DeviceMemory,
+    vertex_count: u32,
+    // Seconds per frame, oldest first.
+    frame_times: VecDeque<f32>,
+    last_frame: Option<std::time::Instant>,
+}
+
+impl Overlay {
+    pub fn new(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        pdevice: vk::PhysicalDevice,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+        material_descriptors: &mut descriptors::MaterialDescriptors,
+    ) -> Self {
+        let (width, height, pixels) = font_atlas();
+        let font = Texture::from_rgba8(
+            instance,
+            device,
+            pdevice,
+            command_pool,
+            queue,
+            width,
+            height,
+            &pixels,
+            vk::Format::R8G8B8A8_UNORM,
+        );
+        let descriptor_set = material_descriptors.allocate(device, &font);
+
+        // rewritten every frame after the fence wait, so host memory is enough
+        let (vertex_buffer, vertex_buffer_memory) = buffers::create_buffer(
+            instance,
+            device,
+            pdevice,
+            (MAX_QUADS * 6 * std::mem::size_of::<OverlayVertex>()) as vk::DeviceSize,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        );
+
+        Self {
+            enabled: false,
+            font,
+            descriptor_set,
+            vertex_buffer,
+            vertex_buffer_memory,
+            vertex_count: 0,
+            frame_times: VecDeque::with_capacity(FRAME_HISTORY),
+            last_frame: None,
+        }
+    }
+
+    // Called once per frame whether or not the overlay is shown, so the graph is full when it
+    // is toggled on.
+    pub fn record_frame(&mut self) {
+        let now = std::time::Instant::now();
+        if let Some(last) = self.last_frame {
+            if self.frame_times.len() == FRAME_HISTORY {
+                self.frame_times.pop_front();
+            }
+            self.frame_times.push_back(now.duration_since(last).as_secs_f32());
+        }
+        self.last_frame = Some(now);
+    }
+
+    // Mean over the recorded history, which smooths the readout over about two seconds.
+    pub fn average_frame_time(&self) -> f32 {
+        if self.frame_times.is_empty() {
+            return 0.0;
+        }
+        self.frame_times.iter().sum::<f32>() / self.frame_times.len() as f32
+    }
+
+    // Lays out a panel with `lines` of text above the frame time graph and uploads the quads.
+    fn build(&mut self, device: &ash::Device, lines: &[String]) {
+        let advance = CELL_WIDTH as f32 * TEXT_SCALE;
+        let line_height = GLYPH_HEIGHT as f32 * TEXT_SCALE + LINE_SPACING;
+        let text_width = lines.iter().map(|line| line.len()).max().unwrap_or(0) as f32 * advance;
+        let graph_width = FRAME_HISTORY as f32 * GRAPH_BAR_WIDTH;
+        let panel_width = text_width.max(graph_width) + 2.0 * PADDING;
+        let panel_height = lines.len() as f32 * line_height + GRAPH_HEIGHT + 2.0 * PADDING;
+
+        let mut vertices = Vec::with_capacity(MAX_QUADS * 6);
+        push_solid(&mut vertices, [MARGIN, MARGIN], [panel_width, panel_height], PANEL_COLOR);
+
+        let left = MARGIN + PADDING;
+        for (row, line) in lines.iter().enumerate() {
+            let y = MARGIN + PADDING + row as f32 * line_height;
+            for (column, character) in line.bytes().enumerate() {
+                if character == b' ' {
+                    continue;
+                }
+                let glyph = glyph_index(character);
+                let cell = [(glyph % ATLAS_COLUMNS * CELL_WIDTH) as f32, (glyph / ATLAS_COLUMNS * CELL_HEIGHT) as f32];
+                push_quad(
+                    &mut vertices,
+                    [left + column as f32 * advance, y],
+                    [GLYPH_WIDTH as f32 * TEXT_SCALE, GLYPH_HEIGHT as f32 * TEXT_SCALE],
+                    cell,
+                    [GLYPH_WIDTH as f32, GLYPH_HEIGHT as f32],
+                    TEXT_COLOR,
+                );
+            }
+        }
+
+        // newest frame on the right, coloured against the 60 and 30 FPS budgets
+        let graph_bottom = MARGIN + panel_height - PADDING;
+        let graph_left = left + graph_width - self.frame_times.len() as f32 * GRAPH_BAR_WIDTH;
+        for (i, &frame_time) in self.frame_times.iter().enumerate() {
+            let ms = frame_time * 1000.0;
+            let color = if ms <= 1000.0 / 60.0 {
+                [0.2, 0.9, 0.2, 0.9]
+            } else if ms <= 1000.0 / 30.0 {
+                [0.9, 0.8, 0.2, 0.9]
+            } else {
+                [0.9, 0.2, 0.2, 0.9]
+            };
+            let height = (ms / GRAPH_MAX_MS).min(1.0) * GRAPH_HEIGHT;
+            push_solid(
+                &mut vertices,
+                [graph_left + i as f32 * GRAPH_BAR_WIDTH, graph_bottom - height],
+                [GRAPH_BAR_WIDTH, height],
+                color,
+            );
+        }
+
+        vertices.truncate(MAX_QUADS * 6);
+        self.vertex_count = vertices.len() as u32;
+        if vertices.is_empty() {
+            return;
+        }
+        let size = (vertices.len() * std::mem::size_of::<OverlayVertex>()) as vk::DeviceSize;
+        unsafe {
+            let data_ptr = device
+                .map_memory(self.vertex_buffer_memory, 0, size, vk::MemoryMapFlags::empty())
+                .unwrap();
+            let mut align = ash::util::Align::new(data_ptr, std::mem::align_of::<OverlayVertex>() as _, size);
+            align.copy_from_slice(&vertices);
+            device.unmap_memory(self.vertex_buffer_memory);
+        }
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        self.font.destroy(device);
+        unsafe {
+            device.destroy_buffer(self.vertex_buffer, None);
+            device.free_memory(self.vertex_buffer_memory, None);
+        }
+    }
+}
+
+// Builds the RGBA atlas for `FONT` plus the solid cell; coverage is stored in alpha.
+fn font_atlas() -> (u32, u32, Vec<u8>) {
+    let glyph_count = SOLID_GLYPH + 1;
+    let width = ATLAS_COLUMNS * CELL_WIDTH;
+    let height = glyph_count.div_ceil(ATLAS_COLUMNS) * CELL_HEIGHT;
+    let mut pixels = vec![0u8; (width * height * 4) as usize];
+
+    for glyph in 0..glyph_count {
+        let origin_x = glyph % ATLAS_COLUMNS * CELL_WIDTH;
+        let origin_y = glyph / ATLAS_COLUMNS * CELL_HEIGHT;
+        for y in 0..GLYPH_HEIGHT {
+            for x in 0..GLYPH_WIDTH {
+                let lit = match FONT.get(glyph as usize) {
+                    Some(rows) => rows[y as usize] & (0x10 >> x) != 0,
+                    None => true,
+                };
+                if lit {
+                    let offset = (((origin_y + y) * width + origin_x + x) * 4) as usize;
+                    pixels[offset..offset + 4].copy_from_slice(&[255; 4]);
+                }
+            }
+        }
+    }
+    (width, height, pixels)
+}
+
+fn glyph_index(character: u8) -> u32 {
+    let character = character.to_ascii_uppercase();
+    match character.checked_sub(FIRST_CHAR) {
+        Some(index) if (index as usize) < FONT.len() => index as u32,
+        _ => (b'?' - FIRST_CHAR) as u32,
+    }
+}
+
+fn push_quad(
+    vertices: &mut Vec<OverlayVertex>,
+    position: [f32; 2],
+    size: [f32; 2],
+    uv: [f32; 2],
+    uv_size: [f32; 2],
+    color: [f32; 4],
+) {
+    let corner = |x: f32, y: f32| OverlayVertex {
+        position: [position[0] + x * size[0], position[1] + y * size[1]],
+        uv: [uv[0] + x * uv_size[0], uv[1] + y * uv_size[1]],
+        color,
+    };
+    vertices.extend_from_slice(&[
+        corner(0.0, 0.0),
+        corner(0.0, 1.0),
+        corner(1.0, 1.0),
+        corner(0.0, 0.0),
+        corner(1.0, 1.0),
+        corner(1.0, 0.0),
+    ]);
+}
+
+// Every vertex samples the middle of the solid cell, so the whole quad is fully covered.
+fn push_solid(vertices: &mut Vec<OverlayVertex>, position: [f32; 2], size: [f32; 2], color: [f32; 4]) {
+    let uv = [
+        (SOLID_GLYPH % ATLAS_COLUMNS * CELL_WIDTH) as f32 + 2.5,
+        (SOLID_GLYPH / ATLAS_COLUMNS * CELL_HEIGHT) as f32 + 3.5,
+    ];
+    push_quad(vertices, position, size, uv, [0.0, 0.0], color);
+}
+
+impl VulkanApp {
+    pub fn overlay_enabled(&self) -> bool {
+        self.overlay.enabled
+    }
+
+    pub fn toggle_overlay(&mut self) {
+        self.overlay.enabled = !self.overlay.enabled;
+    }
+
+    // Refreshes the overlay text from this frame's draws and the camera.
+    pub(super) fn update_overlay(&mut self, camera: &crate::camera::Camera, draws: &[DrawCommand]) {
+        let frame_time = self.overlay.average_frame_time();
+        let fps = if frame_time > 0.0 { 1.0 / frame_time } else { 0.0 };
+
+        // mirrors the paths in record_indirect_draws, plus one draw per emitter and the sky
+        let culling = &self.gpu_culling;
+        let single_call =
+            (self.draw_indirect_count_loader.is_some() && culling.compact) || self.device_features.multi_draw_indirect;
+        let indirect_draws: u32 = culling
+            .batches
+            .iter()
+            .map(|batch| if single_call { 1 } else { batch.count })
+            .sum();
+        let draw_calls = draws.len() as u32
+            + indirect_draws
+            + self.particles.emitter_count() as u32
+            + self.skybox.is_some() as u32
+            + self.occlusion_queries.proxies.len() as u32;
+
+        let stats = self.cull_stats;
+        let (used, total) =
+            instance::query_device_memory(&self.instance, self.physical_device, self.device_features.memory_budget);
+        let megabytes = |bytes: u64| bytes / (1024 * 1024);
+        let memory = match used {
+            Some(used) => format!("GPU MEM: {} / {} MB", megabytes(used), megabytes(total)),
+            None => format!("GPU MEM: {} MB", megabytes(total)),
+        };
+
+        let mut lines = vec![
+            format!("FPS: {:.0} ({:.2} MS)", fps, frame_time * 1000.0),
+            format!("DRAW CALLS: {}", draw_calls),
+            format!("TRIANGLES: {}", stats.triangles),
+            format!(
+                "OBJECTS: {} DRAWN, {} CULLED, {} OCCLUDED",
+                stats.drawn, stats.culled, stats.occluded
+            ),
+            format!(
+                "POS: {:.2} {:.2} {:.2}",
+                camera.position.x, camera.position.y, camera.position.z
+            ),
+            format!("YAW: {:.1} PITCH: {:.1}", camera.yaw, camera.pitch),
+            memory,
+        ];
+        if let Some(statistics) = self.pipeline_statistics() {
+            lines.push(format!("VERTEX INVOCATIONS: {}", statistics.vertex_invocations));
+            lines.push(format!("FRAGMENT INVOCATIONS: {}", statistics.fragment_invocations));
+            lines.push(format!("CLIPPED PRIMITIVES: {}", statistics.clipping_primitives));
+        }
+        let timings = self.profiler.timings();
+        if !timings.is_empty() {
+            lines.push("GPU TIME (MS AVG):".to_string());
+        }
+        for timing in timings {
+            let name = timing.name.rsplit('/').next().unwrap_or(&timing.name);
+            lines.push(format!("{}{}: {:.3}", "  ".repeat(timing.depth + 1), name, timing.average_ms));
+        }
+        self.overlay.build(&self.device, &lines);
+    }
+
+    // Drawn last in the render pass so it sits on top of everything else.
+    pub(super) fn record_overlay(&self, command_buffer: vk::CommandBuffer) {
+        if !self.overlay.enabled || self.overlay.vertex_count == 0 {
+            return;
+        }
+
+        let extent = self.swapchain_extent;
+        let [encoding, paper_white] = self.output_push_constants();
+        let push_constants = [extent.width as f32, extent.height as f32, encoding, paper_white];
+        unsafe {
+            self.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.overlay_pipeline,
+            );
+            self.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.overlay_pipeline_layout,
+                0,
+                &[self.overlay.descriptor_set],
+                &[],
+            );
+            self.device.cmd_push_constants(
+                command_buffer,
+                self.overlay_pipeline_layout,
+                vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                0,
+                std::slice::from_raw_parts(push_constants.as_ptr() as *const u8, std::mem::size_of_val(&push_constants)),
+            );
+            self.device
+                .cmd_bind_vertex_buffers(command_buffer, 0, &[self.overlay.vertex_buffer], &[0]);
+            self.device
+                .cmd_draw(command_buffer, self.overlay.vertex_count, 1, 0, 0);
+        }
+    }
+}
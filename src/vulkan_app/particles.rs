@@ -0,0 +1,335 @@
+use ash::vk;
+use cgmath::{Deg, InnerSpace, Matrix4, SquareMatrix, Vector3};
+
+use super::compute::{ComputeBinding, ComputePass};
+use super::{buffers, commands, descriptors, VulkanApp};
+use crate::scene::NodeId;
+
+pub const MAX_PARTICLES: u32 = 65536;
+const WORKGROUP_SIZE: u32 = 64;
+// Longest step fed to the simulation, so a stalled frame doesn't fling particles away.
+const MAX_TIME_STEP: f32 = 0.1;
+
+// Matches `Particle` in particle.comp and particle.vert (std430).
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct Particle {
+    position_age: [f32; 4],
+    velocity_lifetime: [f32; 4],
+    color: [f32; 4],
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct EmitterPushConstants {
+    position_shape: [f32; 4],
+    shape_params: [f32; 4],
+    direction_cone: [f32; 4],
+    gravity_dt: [f32; 4],
+    start_color: [f32; 4],
+    end_color: [f32; 4],
+    first_particle: u32,
+    particle_count: u32,
+    spawn_start: u32,
+    spawn_count: u32,
+    speed: f32,
+    lifetime: f32,
+    seed: u32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum SpawnShape {
+    Point,
+    Sphere { radius: f32 },
+    Box { half_extents: Vector3<f32> },
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct EmitterSettings {
+    // Relative to the attached node, or world space when there is none.
+    pub position: Vector3<f32>,
+    pub direction: Vector3<f32>,
+    // Half angle of the cone particles are launched into.
+    pub cone_angle: Deg<f32>,
+    pub shape: SpawnShape,
+    // Particles per second.
+    pub rate: f32,
+    pub speed: f32,
+    pub lifetime: f32,
+    pub gravity: Vector3<f32>,
+    pub start_color: [f32; 4],
+    pub end_color: [f32; 4],
+    pub size: f32,
+    pub node: Option<NodeId>,
+}
+
+impl Default for EmitterSettings {
+    fn default() -> Self {
+        Self {
+            position: Vector3::new(0.0, 0.0, 0.0),
+            direction: Vector3::new(0.0, 0.0, 1.0),
+            cone_angle: Deg(15.0),
+            shape: SpawnShape::Point,
+            rate: 100.0,
+            speed: 2.0,
+            lifetime: 2.0,
+            gravity: Vector3::new(0.0, 0.0, -9.81),
+            start_color: [1.0, 1.0, 1.0, 1.0],
+            end_color: [1.0, 1.0, 1.0, 0.0],
+            size: 0.05,
+            node: None,
+        }
+    }
+}
+
+// Every particle lives exactly `lifetime` seconds, so each emitter's slice of the particle
+// buffer is a ring: new particles always replace the oldest ones.
+struct Emitter {
+    settings: EmitterSettings,
+    first_particle: u32,
+    capacity: u32,
+    next_spawn: u32,
+    spawn_accumulator: f32,
+}
+
+pub struct ParticleSystem {
+    pub particle_buffer: vk::Buffer,
+    pub particle_buffer_memory: vk::DeviceMemory,
+    // Read-only view of the particles for particle.vert, bound at set 1.
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    pub descriptor_pool: vk::DescriptorPool,
+    pub descriptor_set: vk::DescriptorSet,
+    pub simulation_pass: usize,
+    emitters: Vec<Emitter>,
+    allocated: u32,
+    last_update: Option<std::time::Instant>,
+    frame: u32,
+}
+
+impl ParticleSystem {
+    // The simulation shader is registered in `compute_passes` so it can go through the
+    // regular dispatch queue.
+    pub fn new(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        pdevice: vk::PhysicalDevice,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+        compute_passes: &mut Vec<ComputePass>,
+    ) -> Self {
+        let buffer_size = (std::mem::size_of::<Particle>() as u32 * MAX_PARTICLES) as vk::DeviceSize;
+        let (particle_buffer, particle_buffer_memory) = buffers::create_buffer(
+            instance,
+            device,
+            pdevice,
+            buffer_size,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        );
+        // all zero means age 0 and lifetime 0, i.e. every particle starts out dead
+        let command_buffer = commands::begin_single_time_commands(device, command_pool);
+        unsafe {
+            device.cmd_fill_buffer(command_buffer, particle_buffer, 0, buffer_size, 0);
+        }
+        commands::end_single_time_commands(device, command_pool, queue, command_buffer);
+
+        let descriptor_set_layout =
+            descriptors::create_storage_buffer_descriptor_set_layout(device, &[vk::ShaderStageFlags::VERTEX]);
+        let (descriptor_pool, descriptor_set) = descriptors::create_storage_buffer_descriptor_set(
+            device,
+            descriptor_set_layout,
+            &[particle_buffer],
+        );
+
+        compute_passes.push(ComputePass::new(
+            device,
+            include_bytes!(env!("PARTICLE_COMP_SHADER_PATH")),
+            &[ComputeBinding::StorageBuffer(particle_buffer)],
+            std::mem::size_of::<EmitterPushConstants>() as u32,
+        ));
+
+        Self {
+            particle_buffer,
+            particle_buffer_memory,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_set,
+            simulation_pass: compute_passes.len() - 1,
+            emitters: Vec::new(),
+            allocated: 0,
+            last_update: None,
+            frame: 0,
+        }
+    }
+
+    // Capacity is sized from rate * lifetime; raising the rate later shortens the visible
+    // lifetime instead of growing the emitter. Returns None once the buffer is full.
+    pub fn add_emitter(&mut self, settings: EmitterSettings) -> Option<usize> {
+        let capacity = ((settings.rate * settings.lifetime).ceil() as u32).max(1);
+        if self.allocated + capacity > MAX_PARTICLES {
+            return None;
+        }
+
+        self.emitters.push(Emitter {
+            settings,
+            first_particle: self.allocated,
+            capacity,
+            next_spawn: 0,
+            spawn_accumulator: 0.0,
+        });
+        self.allocated += capacity;
+        Some(self.emitters.len() - 1)
+    }
+
+    pub fn emitter_mut(&mut self, index: usize) -> &mut EmitterSettings {
+        &mut self.emitters[index].settings
+    }
+
+    pub fn emitter_count(&self) -> usize {
+        self.emitters.len()
+    }
+
+    // Particles already in flight keep simulating until they are overwritten by a new emitter.
+    pub fn clear(&mut self) {
+        self.emitters.clear();
+        self.allocated = 0;
+    }
+
+    // Advances the spawn bookkeeping and returns one set of push constants per emitter.
+    // `node_transform` resolves an attached node to its world matrix.
+    fn step(&mut self, node_transform: impl Fn(NodeId) -> Option<Matrix4<f32>>) -> Vec<EmitterPushConstants> {
+        let now = std::time::Instant::now();
+        let dt = self
+            .last_update
+            .map_or(0.0, |last| now.duration_since(last).as_secs_f32())
+            .min(MAX_TIME_STEP);
+        self.last_update = Some(now);
+        self.frame = self.frame.wrapping_add(1);
+
+        let mut params = Vec::with_capacity(self.emitters.len());
+        for emitter in self.emitters.iter_mut() {
+            let settings = &emitter.settings;
+            let transform = settings
+                .node
+                .and_then(&node_transform)
+                .unwrap_or(Matrix4::identity());
+            let position = (transform * settings.position.extend(1.0)).truncate();
+            let direction = (transform * settings.direction.extend(0.0)).truncate();
+            let direction = if direction.magnitude2() > 0.0 {
+                direction.normalize()
+            } else {
+                Vector3::new(0.0, 0.0, 1.0)
+            };
+
+            emitter.spawn_accumulator += settings.rate.max(0.0) * dt;
+            let spawn_count = (emitter.spawn_accumulator.floor() as u32).min(emitter.capacity);
+            emitter.spawn_accumulator -= spawn_count as f32;
+            let spawn_start = emitter.next_spawn;
+            emitter.next_spawn = (emitter.next_spawn + spawn_count) % emitter.capacity;
+
+            let (shape, shape_params) = match settings.shape {
+                SpawnShape::Point => (0.0, [0.0; 4]),
+                SpawnShape::Sphere { radius } => (1.0, [radius, 0.0, 0.0, 0.0]),
+                SpawnShape::Box { half_extents } => (2.0, [half_extents.x, half_extents.y, half_extents.z, 0.0]),
+            };
+            let cone_cos = cgmath::Rad::from(settings.cone_angle).0.cos();
+
+            params.push(EmitterPushConstants {
+                position_shape: [position.x, position.y, position.z, shape],
+                shape_params,
+                direction_cone: [direction.x, direction.y, direction.z, cone_cos],
+                gravity_dt: [settings.gravity.x, settings.gravity.y, settings.gravity.z, dt],
+                start_color: settings.start_color,
+                end_color: settings.end_color,
+                first_particle: emitter.first_particle,
+                particle_count: emitter.capacity,
+                spawn_start,
+                spawn_count,
+                speed: settings.speed,
+                lifetime: settings.lifetime,
+                seed: self.frame.wrapping_mul(0x9e37_79b9),
+            });
+        }
+        params
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            device.destroy_descriptor_pool(self.descriptor_pool, None);
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            device.destroy_buffer(self.particle_buffer, None);
+            device.free_memory(self.particle_buffer_memory, None);
+        }
+    }
+}
+
+impl VulkanApp {
+    pub fn add_emitter(&mut self, settings: EmitterSettings) -> Option<usize> {
+        self.particles.add_emitter(settings)
+    }
+
+    pub fn emitter_mut(&mut self, index: usize) -> &mut EmitterSettings {
+        self.particles.emitter_mut(index)
+    }
+
+    pub fn emitter_count(&self) -> usize {
+        self.particles.emitter_count()
+    }
+
+    // For attaching emitters by name; see `Scene::find`.
+    pub fn find_node(&self, name: &str) -> Option<NodeId> {
+        self.scene.find(name)
+    }
+
+    // Queues this frame's simulation step for every emitter.
+    pub(super) fn update_particles(&mut self) {
+        let scene = &self.scene;
+        let params = self.particles.step(|node| {
+            (node < scene.len()).then(|| scene.node(node).world_matrix())
+        });
+        let pass = self.particles.simulation_pass;
+        for params in params {
+            let push_constants = unsafe {
+                std::slice::from_raw_parts(
+                    &params as *const EmitterPushConstants as *const u8,
+                    std::mem::size_of::<EmitterPushConstants>(),
+                )
+            };
+            self.queue_dispatch(pass, push_constants, [params.particle_count.div_ceil(WORKGROUP_SIZE), 1, 1]);
+        }
+    }
+
+    // Billboards for every emitter, drawn additively after the opaque geometry.
+    pub(super) fn record_particles(&self, command_buffer: vk::CommandBuffer, image_index: usize) {
+        if self.particles.emitters.is_empty() {
+            return;
+        }
+
+        unsafe {
+            self.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.particle_pipeline,
+            );
+            self.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.particle_pipeline_layout,
+                0,
+                &[self.descriptor_sets[image_index], self.particles.descriptor_set],
+                &[],
+            );
+            for emitter in self.particles.emitters.iter() {
+                self.device.cmd_push_constants(
+                    command_buffer,
+                    self.particle_pipeline_layout,
+                    vk::ShaderStageFlags::VERTEX,
+                    0,
+                    &emitter.settings.size.to_ne_bytes(),
+                );
+                self.device
+                    .cmd_draw(command_buffer, emitter.capacity * 6, 1, emitter.first_particle * 6, 0);
+            }
+        }
+    }
+}
@@ -1,23 +1,36 @@
 use std::ffi::CString;
 
 use ash::{vk};
+use cgmath::Matrix4;
 
-use super::{vertex::Vertex, VulkanApp};
+use super::{gui, overlay::OverlayVertex, utils::ObjectPushConstants, vertex::Vertex, VulkanApp};
 
 pub fn create_render_pass(
     device: &ash::Device,
     format: vk::Format,
     depth_format: vk::Format,
+    samples: vk::SampleCountFlags,
 ) -> vk::RenderPass {
+    // With multisampling the color attachment is a transient image resolved into the swapchain
+    // image at the end of the subpass.
+    let multisampled = samples != vk::SampleCountFlags::TYPE_1;
     let color_attachment = vk::AttachmentDescription::builder()
         .format(format)
-        .samples(vk::SampleCountFlags::TYPE_1)
+        .samples(samples)
         .load_op(vk::AttachmentLoadOp::CLEAR)
-        .store_op(vk::AttachmentStoreOp::STORE)
+        .store_op(if multisampled {
+            vk::AttachmentStoreOp::DONT_CARE
+        } else {
+            vk::AttachmentStoreOp::STORE
+        })
         .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
         .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
         .initial_layout(vk::ImageLayout::UNDEFINED)
-        .final_layout(vk::ImageLayout::PRESENT_SRC_KHR);
+        .final_layout(if multisampled {
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+        } else {
+            vk::ImageLayout::PRESENT_SRC_KHR
+        });
 
     let color_attachment_ref = vk::AttachmentReference::builder()
         .attachment(0)
@@ -25,7 +38,7 @@ pub fn create_render_pass(
 
     let depth_attachment = vk::AttachmentDescription::builder()
         .format(depth_format)
-        .samples(vk::SampleCountFlags::TYPE_1)
+        .samples(samples)
         .load_op(vk::AttachmentLoadOp::CLEAR)
         .store_op(vk::AttachmentStoreOp::DONT_CARE)
         .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
@@ -37,10 +50,27 @@ pub fn create_render_pass(
         .attachment(1)
         .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
 
-    let subpass = vk::SubpassDescription::builder()
+    let resolve_attachment = vk::AttachmentDescription::builder()
+        .format(format)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .store_op(vk::AttachmentStoreOp::STORE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::UNDEFINED)
+        .final_layout(vk::ImageLayout::PRESENT_SRC_KHR);
+
+    let resolve_attachment_ref = vk::AttachmentReference::builder()
+        .attachment(2)
+        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+
+    let mut subpass = vk::SubpassDescription::builder()
         .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
         .color_attachments(std::slice::from_ref(&color_attachment_ref))
         .depth_stencil_attachment(&depth_attachment_ref);
+    if multisampled {
+        subpass = subpass.resolve_attachments(std::slice::from_ref(&resolve_attachment_ref));
+    }
 
     let dependency = vk::SubpassDependency::builder()
         .src_subpass(vk::SUBPASS_EXTERNAL)
@@ -59,9 +89,10 @@ pub fn create_render_pass(
                 | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
         );
 
-    let attachments = [color_attachment.build(), depth_attachment.build()];
+    let attachments = [color_attachment.build(), depth_attachment.build(), resolve_attachment.build()];
+    let attachment_count = if multisampled { 3 } else { 2 };
     let render_pass_info = vk::RenderPassCreateInfo::builder()
-        .attachments(&attachments)
+        .attachments(&attachments[..attachment_count])
         .subpasses(std::slice::from_ref(&subpass))
         .dependencies(std::slice::from_ref(&dependency));
 
@@ -72,7 +103,8 @@ pub fn create_graphics_pipeline(
     device: &ash::Device,
     render_pass: vk::RenderPass,
     extent: vk::Extent2D,
-    descriptor_set_layout: vk::DescriptorSetLayout,
+    samples: vk::SampleCountFlags,
+    descriptor_set_layouts: &[vk::DescriptorSetLayout],
 ) -> (vk::Pipeline, vk::PipelineLayout) {
     let vert_shader_code = include_bytes!(env!("VERT_SHADER_PATH"));
     let frag_shader_code = include_bytes!(env!("FRAG_SHADER_PATH"));
@@ -131,7 +163,7 @@ pub fn create_graphics_pipeline(
 
     let multisampling = vk::PipelineMultisampleStateCreateInfo::builder()
         .sample_shading_enable(false)
-        .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+        .rasterization_samples(samples);
 
     let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::builder()
         .depth_test_enable(true)
@@ -148,8 +180,13 @@ pub fn create_graphics_pipeline(
         .logic_op_enable(false)
         .attachments(std::slice::from_ref(&color_blend_attachment));
 
+    let push_constant_range = vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::VERTEX)
+        .offset(0)
+        .size(std::mem::size_of::<ObjectPushConstants>() as u32);
     let pipeline_layout_info = vk::PipelineLayoutCreateInfo::builder()
-        .set_layouts(std::slice::from_ref(&descriptor_set_layout));
+        .set_layouts(descriptor_set_layouts)
+        .push_constant_ranges(std::slice::from_ref(&push_constant_range));
     let pipeline_layout = unsafe {
         device
             .create_pipeline_layout(&pipeline_layout_info, None)
@@ -191,9 +228,97 @@ pub fn create_wireframe_pipeline(
     device: &ash::Device,
     render_pass: vk::RenderPass,
     extent: vk::Extent2D,
+    samples: vk::SampleCountFlags,
     pipeline_layout: vk::PipelineLayout,
 ) -> vk::Pipeline {
-    let vert_shader_code = include_bytes!(env!("VERT_SHADER_PATH"));
+    create_pipeline_variant(
+        device,
+        render_pass,
+        extent,
+        samples,
+        pipeline_layout,
+        include_bytes!(env!("VERT_SHADER_PATH")),
+        vk::PrimitiveTopology::LINE_LIST,
+        vk::PolygonMode::LINE,
+        0.03,
+    )
+}
+
+// Reads per-instance transforms from the GPU culling instance buffer bound at set 2.
+pub fn create_indirect_pipeline(
+    device: &ash::Device,
+    render_pass: vk::RenderPass,
+    extent: vk::Extent2D,
+    samples: vk::SampleCountFlags,
+    pipeline_layout: vk::PipelineLayout,
+) -> vk::Pipeline {
+    create_pipeline_variant(
+        device,
+        render_pass,
+        extent,
+        samples,
+        pipeline_layout,
+        include_bytes!(env!("INDIRECT_VERT_SHADER_PATH")),
+        vk::PrimitiveTopology::TRIANGLE_LIST,
+        vk::PolygonMode::FILL,
+        1.0,
+    )
+}
+
+// Triangle meshes drawn as their edges for `RenderMode::Wireframe`, needs fillModeNonSolid.
+pub fn create_triangle_wireframe_pipeline(
+    device: &ash::Device,
+    render_pass: vk::RenderPass,
+    extent: vk::Extent2D,
+    samples: vk::SampleCountFlags,
+    pipeline_layout: vk::PipelineLayout,
+) -> vk::Pipeline {
+    create_pipeline_variant(
+        device,
+        render_pass,
+        extent,
+        samples,
+        pipeline_layout,
+        include_bytes!(env!("VERT_SHADER_PATH")),
+        vk::PrimitiveTopology::TRIANGLE_LIST,
+        vk::PolygonMode::LINE,
+        1.0,
+    )
+}
+
+// The wireframe counterpart of `create_indirect_pipeline`.
+pub fn create_indirect_wireframe_pipeline(
+    device: &ash::Device,
+    render_pass: vk::RenderPass,
+    extent: vk::Extent2D,
+    samples: vk::SampleCountFlags,
+    pipeline_layout: vk::PipelineLayout,
+) -> vk::Pipeline {
+    create_pipeline_variant(
+        device,
+        render_pass,
+        extent,
+        samples,
+        pipeline_layout,
+        include_bytes!(env!("INDIRECT_VERT_SHADER_PATH")),
+        vk::PrimitiveTopology::TRIANGLE_LIST,
+        vk::PolygonMode::LINE,
+        1.0,
+    )
+}
+
+// Pipeline sharing the main pipeline layout but with its own vertex shader and rasterization.
+fn create_pipeline_variant(
+    device: &ash::Device,
+    render_pass: vk::RenderPass,
+    extent: vk::Extent2D,
+    samples: vk::SampleCountFlags,
+    pipeline_layout: vk::PipelineLayout,
+    vert_shader_code: &[u8],
+    topology: vk::PrimitiveTopology,
+    polygon_mode: vk::PolygonMode,
+    line_width: f32,
+) -> vk::Pipeline {
     let frag_shader_code = include_bytes!(env!("FRAG_SHADER_PATH"));
 
     let vert_shader_module = create_shader_module(device, vert_shader_code);
@@ -220,7 +345,7 @@ pub fn create_wireframe_pipeline(
         .vertex_attribute_descriptions(&attribute_descriptions);
 
     let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::builder()
-        .topology(vk::PrimitiveTopology::LINE_LIST)
+        .topology(topology)
         .primitive_restart_enable(false);
 
     let viewport = vk::Viewport::builder()
@@ -242,15 +367,15 @@ pub fn create_wireframe_pipeline(
     let rasterizer = vk::PipelineRasterizationStateCreateInfo::builder()
         .depth_clamp_enable(false)
         .rasterizer_discard_enable(false)
-        .polygon_mode(vk::PolygonMode::LINE)
-        .line_width(0.03)
+        .polygon_mode(polygon_mode)
+        .line_width(line_width)
         .cull_mode(vk::CullModeFlags::BACK)
         .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
         .depth_bias_enable(false);
 
     let multisampling = vk::PipelineMultisampleStateCreateInfo::builder()
         .sample_shading_enable(false)
-        .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+        .rasterization_samples(samples);
 
     let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::builder()
         .depth_test_enable(true)
@@ -298,6 +423,693 @@ pub fn create_wireframe_pipeline(
     pipeline
 }
 
+// Camera-facing quads expanded from the particle buffer in the vertex shader, no vertex input.
+// Additive blending with depth testing but no depth writes, so it must run after opaque draws.
+pub fn create_particle_pipeline(
+    device: &ash::Device,
+    render_pass: vk::RenderPass,
+    extent: vk::Extent2D,
+    samples: vk::SampleCountFlags,
+    descriptor_set_layouts: &[vk::DescriptorSetLayout],
+) -> (vk::Pipeline, vk::PipelineLayout) {
+    let vert_shader_code = include_bytes!(env!("PARTICLE_VERT_SHADER_PATH"));
+    let frag_shader_code = include_bytes!(env!("PARTICLE_FRAG_SHADER_PATH"));
+
+    let vert_shader_module = create_shader_module(device, vert_shader_code);
+    let frag_shader_module = create_shader_module(device, frag_shader_code);
+
+    let main_function_name = CString::new("main").unwrap();
+
+    let vert_shader_stage_info = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::VERTEX)
+        .module(vert_shader_module)
+        .name(&main_function_name);
+
+    let frag_shader_stage_info = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::FRAGMENT)
+        .module(frag_shader_module)
+        .name(&main_function_name);
+
+    let shader_stages = [vert_shader_stage_info.build(), frag_shader_stage_info.build()];
+
+    let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder();
+
+    let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+        .primitive_restart_enable(false);
+
+    let viewport = vk::Viewport::builder()
+        .x(0.0)
+        .y(0.0)
+        .width(extent.width as f32)
+        .height(extent.height as f32)
+        .min_depth(0.0)
+        .max_depth(1.0);
+
+    let scissor = vk::Rect2D::builder()
+        .offset(vk::Offset2D { x: 0, y: 0 })
+        .extent(extent);
+
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder()
+        .viewports(std::slice::from_ref(&viewport))
+        .scissors(std::slice::from_ref(&scissor));
+
+    let rasterizer = vk::PipelineRasterizationStateCreateInfo::builder()
+        .depth_clamp_enable(false)
+        .rasterizer_discard_enable(false)
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .depth_bias_enable(false);
+
+    let multisampling = vk::PipelineMultisampleStateCreateInfo::builder()
+        .sample_shading_enable(false)
+        .rasterization_samples(samples);
+
+    let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(true)
+        .depth_write_enable(false)
+        .depth_compare_op(vk::CompareOp::LESS)
+        .depth_bounds_test_enable(false)
+        .stencil_test_enable(false);
+
+    let color_blend_attachment = vk::PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(vk::ColorComponentFlags::RGBA)
+        .blend_enable(true)
+        .src_color_blend_factor(vk::BlendFactor::ONE)
+        .dst_color_blend_factor(vk::BlendFactor::ONE)
+        .color_blend_op(vk::BlendOp::ADD)
+        .src_alpha_blend_factor(vk::BlendFactor::ZERO)
+        .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+        .alpha_blend_op(vk::BlendOp::ADD);
+
+    let color_blending = vk::PipelineColorBlendStateCreateInfo::builder()
+        .logic_op_enable(false)
+        .attachments(std::slice::from_ref(&color_blend_attachment));
+
+    // particle size
+    let push_constant_range = vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::VERTEX)
+        .offset(0)
+        .size(std::mem::size_of::<f32>() as u32);
+    let pipeline_layout_info = vk::PipelineLayoutCreateInfo::builder()
+        .set_layouts(descriptor_set_layouts)
+        .push_constant_ranges(std::slice::from_ref(&push_constant_range));
+    let pipeline_layout = unsafe {
+        device
+            .create_pipeline_layout(&pipeline_layout_info, None)
+            .unwrap()
+    };
+
+    let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(&shader_stages)
+        .vertex_input_state(&vertex_input_info)
+        .input_assembly_state(&input_assembly)
+        .viewport_state(&viewport_state)
+        .rasterization_state(&rasterizer)
+        .multisample_state(&multisampling)
+        .depth_stencil_state(&depth_stencil)
+        .color_blend_state(&color_blending)
+        .layout(pipeline_layout)
+        .render_pass(render_pass)
+        .subpass(0);
+
+    let pipeline = unsafe {
+        device
+            .create_graphics_pipelines(
+                vk::PipelineCache::null(),
+                std::slice::from_ref(&pipeline_info),
+                None,
+            )
+            .unwrap()[0]
+    };
+
+    unsafe {
+        device.destroy_shader_module(vert_shader_module, None);
+        device.destroy_shader_module(frag_shader_module, None);
+    }
+
+    (pipeline, pipeline_layout)
+}
+
+pub fn create_skybox_pipeline(
+    device: &ash::Device,
+    render_pass: vk::RenderPass,
+    extent: vk::Extent2D,
+    samples: vk::SampleCountFlags,
+    descriptor_set_layouts: &[vk::DescriptorSetLayout],
+) -> (vk::Pipeline, vk::PipelineLayout) {
+    let vert_shader_code = include_bytes!(env!("SKYBOX_VERT_SHADER_PATH"));
+    let frag_shader_code = include_bytes!(env!("SKYBOX_FRAG_SHADER_PATH"));
+
+    let vert_shader_module = create_shader_module(device, vert_shader_code);
+    let frag_shader_module = create_shader_module(device, frag_shader_code);
+
+    let main_function_name = CString::new("main").unwrap();
+
+    let vert_shader_stage_info = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::VERTEX)
+        .module(vert_shader_module)
+        .name(&main_function_name);
+
+    let frag_shader_stage_info = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::FRAGMENT)
+        .module(frag_shader_module)
+        .name(&main_function_name);
+
+    let shader_stages = [vert_shader_stage_info.build(), frag_shader_stage_info.build()];
+
+    let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder();
+
+    let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+        .primitive_restart_enable(false);
+
+    let viewport = vk::Viewport::builder()
+        .x(0.0)
+        .y(0.0)
+        .width(extent.width as f32)
+        .height(extent.height as f32)
+        .min_depth(0.0)
+        .max_depth(1.0);
+
+    let scissor = vk::Rect2D::builder()
+        .offset(vk::Offset2D { x: 0, y: 0 })
+        .extent(extent);
+
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder()
+        .viewports(std::slice::from_ref(&viewport))
+        .scissors(std::slice::from_ref(&scissor));
+
+    let rasterizer = vk::PipelineRasterizationStateCreateInfo::builder()
+        .depth_clamp_enable(false)
+        .rasterizer_discard_enable(false)
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .depth_bias_enable(false);
+
+    let multisampling = vk::PipelineMultisampleStateCreateInfo::builder()
+        .sample_shading_enable(false)
+        .rasterization_samples(samples);
+
+    let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(true)
+        .depth_write_enable(false)
+        // the sky sits exactly at the cleared depth of 1.0
+        .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL)
+        .depth_bounds_test_enable(false)
+        .stencil_test_enable(false);
+
+    let color_blend_attachment = vk::PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(vk::ColorComponentFlags::RGBA)
+        .blend_enable(false);
+
+    let color_blending = vk::PipelineColorBlendStateCreateInfo::builder()
+        .logic_op_enable(false)
+        .attachments(std::slice::from_ref(&color_blend_attachment));
+
+    let pipeline_layout_info = vk::PipelineLayoutCreateInfo::builder()
+        .set_layouts(descriptor_set_layouts);
+    let pipeline_layout = unsafe {
+        device
+            .create_pipeline_layout(&pipeline_layout_info, None)
+            .unwrap()
+    };
+
+    let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(&shader_stages)
+        .vertex_input_state(&vertex_input_info)
+        .input_assembly_state(&input_assembly)
+        .viewport_state(&viewport_state)
+        .rasterization_state(&rasterizer)
+        .multisample_state(&multisampling)
+        .depth_stencil_state(&depth_stencil)
+        .color_blend_state(&color_blending)
+        .layout(pipeline_layout)
+        .render_pass(render_pass)
+        .subpass(0);
+
+    let pipeline = unsafe {
+        device
+            .create_graphics_pipelines(
+                vk::PipelineCache::null(),
+                std::slice::from_ref(&pipeline_info),
+                None,
+            )
+            .unwrap()[0]
+    };
+
+    unsafe {
+        device.destroy_shader_module(vert_shader_module, None);
+        device.destroy_shader_module(frag_shader_module, None);
+    }
+
+    (pipeline, pipeline_layout)
+}
+
+// Bounding boxes of occluded objects, tested against the depth buffer without writing anything.
+pub fn create_occlusion_pipeline(
+    device: &ash::Device,
+    render_pass: vk::RenderPass,
+    extent: vk::Extent2D,
+    samples: vk::SampleCountFlags,
+    descriptor_set_layouts: &[vk::DescriptorSetLayout],
+) -> (vk::Pipeline, vk::PipelineLayout) {
+    let vert_shader_code = include_bytes!(env!("OCCLUSION_VERT_SHADER_PATH"));
+
+    let vert_shader_module = create_shader_module(device, vert_shader_code);
+
+    let main_function_name = CString::new("main").unwrap();
+
+    // no fragment stage, the query only counts the samples passing the depth test
+    let vert_shader_stage_info = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::VERTEX)
+        .module(vert_shader_module)
+        .name(&main_function_name);
+
+    let shader_stages = [vert_shader_stage_info.build()];
+
+    let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder();
+
+    let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+        .primitive_restart_enable(false);
+
+    let viewport = vk::Viewport::builder()
+        .x(0.0)
+        .y(0.0)
+        .width(extent.width as f32)
+        .height(extent.height as f32)
+        .min_depth(0.0)
+        .max_depth(1.0);
+
+    let scissor = vk::Rect2D::builder()
+        .offset(vk::Offset2D { x: 0, y: 0 })
+        .extent(extent);
+
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder()
+        .viewports(std::slice::from_ref(&viewport))
+        .scissors(std::slice::from_ref(&scissor));
+
+    let rasterizer = vk::PipelineRasterizationStateCreateInfo::builder()
+        .depth_clamp_enable(false)
+        .rasterizer_discard_enable(false)
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .depth_bias_enable(false);
+
+    let multisampling = vk::PipelineMultisampleStateCreateInfo::builder()
+        .sample_shading_enable(false)
+        .rasterization_samples(samples);
+
+    let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(true)
+        .depth_write_enable(false)
+        .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL)
+        .depth_bounds_test_enable(false)
+        .stencil_test_enable(false);
+
+    let color_blend_attachment = vk::PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(vk::ColorComponentFlags::empty())
+        .blend_enable(false);
+
+    let color_blending = vk::PipelineColorBlendStateCreateInfo::builder()
+        .logic_op_enable(false)
+        .attachments(std::slice::from_ref(&color_blend_attachment));
+
+    let push_constant_range = vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::VERTEX)
+        .offset(0)
+        .size(std::mem::size_of::<Matrix4<f32>>() as u32);
+    let pipeline_layout_info = vk::PipelineLayoutCreateInfo::builder()
+        .set_layouts(descriptor_set_layouts)
+        .push_constant_ranges(std::slice::from_ref(&push_constant_range));
+    let pipeline_layout = unsafe {
+        device
+            .create_pipeline_layout(&pipeline_layout_info, None)
+            .unwrap()
+    };
+
+    let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(&shader_stages)
+        .vertex_input_state(&vertex_input_info)
+        .input_assembly_state(&input_assembly)
+        .viewport_state(&viewport_state)
+        .rasterization_state(&rasterizer)
+        .multisample_state(&multisampling)
+        .depth_stencil_state(&depth_stencil)
+        .color_blend_state(&color_blending)
+        .layout(pipeline_layout)
+        .render_pass(render_pass)
+        .subpass(0);
+
+    let pipeline = unsafe {
+        device
+            .create_graphics_pipelines(
+                vk::PipelineCache::null(),
+                std::slice::from_ref(&pipeline_info),
+                None,
+            )
+            .unwrap()[0]
+    };
+
+    unsafe {
+        device.destroy_shader_module(vert_shader_module, None);
+    }
+
+    (pipeline, pipeline_layout)
+}
+
+// Screen-space text and panels with alpha blending and no depth test, recorded last in the pass.
+pub fn create_overlay_pipeline(
+    device: &ash::Device,
+    render_pass: vk::RenderPass,
+    extent: vk::Extent2D,
+    samples: vk::SampleCountFlags,
+    descriptor_set_layouts: &[vk::DescriptorSetLayout],
+) -> (vk::Pipeline, vk::PipelineLayout) {
+    let vert_shader_code = include_bytes!(env!("OVERLAY_VERT_SHADER_PATH"));
+    let frag_shader_code = include_bytes!(env!("OVERLAY_FRAG_SHADER_PATH"));
+
+    let vert_shader_module = create_shader_module(device, vert_shader_code);
+    let frag_shader_module = create_shader_module(device, frag_shader_code);
+
+    let main_function_name = CString::new("main").unwrap();
+
+    let vert_shader_stage_info = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::VERTEX)
+        .module(vert_shader_module)
+        .name(&main_function_name);
+
+    let frag_shader_stage_info = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::FRAGMENT)
+        .module(frag_shader_module)
+        .name(&main_function_name);
+
+    let shader_stages = [vert_shader_stage_info.build(), frag_shader_stage_info.build()];
+
+    let binding_description = OverlayVertex::get_binding_description();
+    let attribute_descriptions = OverlayVertex::get_attribute_descriptions();
+    let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder()
+        .vertex_binding_descriptions(std::slice::from_ref(&binding_description))
+        .vertex_attribute_descriptions(&attribute_descriptions);
+
+    let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+        .primitive_restart_enable(false);
+
+    let viewport = vk::Viewport::builder()
+        .x(0.0)
+        .y(0.0)
+        .width(extent.width as f32)
+        .height(extent.height as f32)
+        .min_depth(0.0)
+        .max_depth(1.0);
+
+    let scissor = vk::Rect2D::builder()
+        .offset(vk::Offset2D { x: 0, y: 0 })
+        .extent(extent);
+
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder()
+        .viewports(std::slice::from_ref(&viewport))
+        .scissors(std::slice::from_ref(&scissor));
+
+    let rasterizer = vk::PipelineRasterizationStateCreateInfo::builder()
+        .depth_clamp_enable(false)
+        .rasterizer_discard_enable(false)
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .depth_bias_enable(false);
+
+    let multisampling = vk::PipelineMultisampleStateCreateInfo::builder()
+        .sample_shading_enable(false)
+        .rasterization_samples(samples);
+
+    let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(false)
+        .depth_write_enable(false)
+        .depth_compare_op(vk::CompareOp::ALWAYS)
+        .depth_bounds_test_enable(false)
+        .stencil_test_enable(false);
+
+    let color_blend_attachment = vk::PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(vk::ColorComponentFlags::RGBA)
+        .blend_enable(true)
+        .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+        .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        .color_blend_op(vk::BlendOp::ADD)
+        .src_alpha_blend_factor(vk::BlendFactor::ONE)
+        .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        .alpha_blend_op(vk::BlendOp::ADD);
+
+    let color_blending = vk::PipelineColorBlendStateCreateInfo::builder()
+        .logic_op_enable(false)
+        .attachments(std::slice::from_ref(&color_blend_attachment));
+
+    // screen size in pixels
+    let push_constant_range = vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
+        .offset(0)
+        // screen size, then the output encoding from VulkanApp::output_push_constants
+        .size(4 * std::mem::size_of::<f32>() as u32);
+    let pipeline_layout_info = vk::PipelineLayoutCreateInfo::builder()
+        .set_layouts(descriptor_set_layouts)
+        .push_constant_ranges(std::slice::from_ref(&push_constant_range));
+    let pipeline_layout = unsafe {
+        device
+            .create_pipeline_layout(&pipeline_layout_info, None)
+            .unwrap()
+    };
+
+    let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(&shader_stages)
+        .vertex_input_state(&vertex_input_info)
+        .input_assembly_state(&input_assembly)
+        .viewport_state(&viewport_state)
+        .rasterization_state(&rasterizer)
+        .multisample_state(&multisampling)
+        .depth_stencil_state(&depth_stencil)
+        .color_blend_state(&color_blending)
+        .layout(pipeline_layout)
+        .render_pass(render_pass)
+        .subpass(0);
+
+    let pipeline = unsafe {
+        device
+            .create_graphics_pipelines(
+                vk::PipelineCache::null(),
+                std::slice::from_ref(&pipeline_info),
+                None,
+            )
+            .unwrap()[0]
+    };
+
+    unsafe {
+        device.destroy_shader_module(vert_shader_module, None);
+        device.destroy_shader_module(frag_shader_module, None);
+    }
+
+    (pipeline, pipeline_layout)
+}
+
+// egui meshes in screen space. Colors are premultiplied and every mesh sets its own scissor.
+pub fn create_gui_pipeline(
+    device: &ash::Device,
+    render_pass: vk::RenderPass,
+    extent: vk::Extent2D,
+    samples: vk::SampleCountFlags,
+    descriptor_set_layouts: &[vk::DescriptorSetLayout],
+) -> (vk::Pipeline, vk::PipelineLayout) {
+    let vert_shader_code = include_bytes!(env!("GUI_VERT_SHADER_PATH"));
+    let frag_shader_code = include_bytes!(env!("GUI_FRAG_SHADER_PATH"));
+
+    let vert_shader_module = create_shader_module(device, vert_shader_code);
+    let frag_shader_module = create_shader_module(device, frag_shader_code);
+
+    let main_function_name = CString::new("main").unwrap();
+
+    let vert_shader_stage_info = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::VERTEX)
+        .module(vert_shader_module)
+        .name(&main_function_name);
+
+    let frag_shader_stage_info = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::FRAGMENT)
+        .module(frag_shader_module)
+        .name(&main_function_name);
+
+    let shader_stages = [vert_shader_stage_info.build(), frag_shader_stage_info.build()];
+
+    let binding_description = gui::vertex_binding_description();
+    let attribute_descriptions = gui::vertex_attribute_descriptions();
+    let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder()
+        .vertex_binding_descriptions(std::slice::from_ref(&binding_description))
+        .vertex_attribute_descriptions(&attribute_descriptions);
+
+    let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+        .primitive_restart_enable(false);
+
+    let viewport = vk::Viewport::builder()
+        .x(0.0)
+        .y(0.0)
+        .width(extent.width as f32)
+        .height(extent.height as f32)
+        .min_depth(0.0)
+        .max_depth(1.0);
+
+    let scissor = vk::Rect2D::builder()
+        .offset(vk::Offset2D { x: 0, y: 0 })
+        .extent(extent);
+
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder()
+        .viewports(std::slice::from_ref(&viewport))
+        .scissors(std::slice::from_ref(&scissor));
+
+    let dynamic_states = [vk::DynamicState::SCISSOR];
+    let dynamic_state = vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+
+    let rasterizer = vk::PipelineRasterizationStateCreateInfo::builder()
+        .depth_clamp_enable(false)
+        .rasterizer_discard_enable(false)
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .depth_bias_enable(false);
+
+    let multisampling = vk::PipelineMultisampleStateCreateInfo::builder()
+        .sample_shading_enable(false)
+        .rasterization_samples(samples);
+
+    let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(false)
+        .depth_write_enable(false)
+        .depth_compare_op(vk::CompareOp::ALWAYS)
+        .depth_bounds_test_enable(false)
+        .stencil_test_enable(false);
+
+    let color_blend_attachment = vk::PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(vk::ColorComponentFlags::RGBA)
+        .blend_enable(true)
+        .src_color_blend_factor(vk::BlendFactor::ONE)
+        .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        .color_blend_op(vk::BlendOp::ADD)
+        .src_alpha_blend_factor(vk::BlendFactor::ONE)
+        .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        .alpha_blend_op(vk::BlendOp::ADD);
+
+    let color_blending = vk::PipelineColorBlendStateCreateInfo::builder()
+        .logic_op_enable(false)
+        .attachments(std::slice::from_ref(&color_blend_attachment));
+
+    // screen size in points
+    let push_constant_range = vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
+        .offset(0)
+        // screen size, then the output encoding from VulkanApp::output_push_constants
+        .size(4 * std::mem::size_of::<f32>() as u32);
+    let pipeline_layout_info = vk::PipelineLayoutCreateInfo::builder()
+        .set_layouts(descriptor_set_layouts)
+        .push_constant_ranges(std::slice::from_ref(&push_constant_range));
+    let pipeline_layout = unsafe {
+        device
+            .create_pipeline_layout(&pipeline_layout_info, None)
+            .unwrap()
+    };
+
+    let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(&shader_stages)
+        .vertex_input_state(&vertex_input_info)
+        .input_assembly_state(&input_assembly)
+        .viewport_state(&viewport_state)
+        .rasterization_state(&rasterizer)
+        .multisample_state(&multisampling)
+        .depth_stencil_state(&depth_stencil)
+        .color_blend_state(&color_blending)
+        .dynamic_state(&dynamic_state)
+        .layout(pipeline_layout)
+        .render_pass(render_pass)
+        .subpass(0);
+
+    let pipeline = unsafe {
+        device
+            .create_graphics_pipelines(
+                vk::PipelineCache::null(),
+                std::slice::from_ref(&pipeline_info),
+                None,
+            )
+            .unwrap()[0]
+    };
+
+    unsafe {
+        device.destroy_shader_module(vert_shader_module, None);
+        device.destroy_shader_module(frag_shader_module, None);
+    }
+
+    (pipeline, pipeline_layout)
+}
+
+pub fn create_compute_pipeline(
+    device: &ash::Device,
+    shader_code: &[u8],
+    descriptor_set_layouts: &[vk::DescriptorSetLayout],
+    push_constant_size: u32,
+) -> (vk::Pipeline, vk::PipelineLayout) {
+    let shader_module = create_shader_module(device, shader_code);
+    let main_function_name = CString::new("main").unwrap();
+
+    let stage_info = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::COMPUTE)
+        .module(shader_module)
+        .name(&main_function_name);
+
+    let push_constant_range = vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::COMPUTE)
+        .offset(0)
+        .size(push_constant_size);
+    let push_constant_ranges: &[vk::PushConstantRange] = if push_constant_size > 0 {
+        std::slice::from_ref(&push_constant_range)
+    } else {
+        &[]
+    };
+    let pipeline_layout_info = vk::PipelineLayoutCreateInfo::builder()
+        .set_layouts(descriptor_set_layouts)
+        .push_constant_ranges(push_constant_ranges);
+    let pipeline_layout = unsafe {
+        device
+            .create_pipeline_layout(&pipeline_layout_info, None)
+            .unwrap()
+    };
+
+    let pipeline_info = vk::ComputePipelineCreateInfo::builder()
+        .stage(stage_info.build())
+        .layout(pipeline_layout);
+
+    let pipeline = unsafe {
+        device
+            .create_compute_pipelines(
+                vk::PipelineCache::null(),
+                std::slice::from_ref(&pipeline_info),
+                None,
+            )
+            .unwrap()[0]
+    };
+
+    unsafe {
+        device.destroy_shader_module(shader_module, None);
+    }
+
+    (pipeline, pipeline_layout)
+}
+
 fn create_shader_module(device: &ash::Device, code: &[u8]) -> vk::ShaderModule {
     let create_info = vk::ShaderModuleCreateInfo::builder().code(unsafe {
         std::slice::from_raw_parts(code.as_ptr() as *const u32, code.len() / 4)
@@ -309,13 +1121,19 @@ pub fn create_framebuffers(
     device: &ash::Device,
     image_views: &[vk::ImageView],
     depth_image_view: vk::ImageView,
+    // Multisampled color target, null without multisampling.
+    color_image_view: vk::ImageView,
     render_pass: vk::RenderPass,
     extent: vk::Extent2D,
 ) -> Vec<vk::Framebuffer> {
     image_views
         .iter()
         .map(|&view| {
-            let attachments = [view, depth_image_view];
+            let attachments = if color_image_view == vk::ImageView::null() {
+                vec![view, depth_image_view]
+            } else {
+                vec![color_image_view, depth_image_view, view]
+            };
             let framebuffer_info = vk::FramebufferCreateInfo::builder()
                 .render_pass(render_pass)
                 .attachments(&attachments)
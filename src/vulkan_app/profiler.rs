@@ -0,0 +1,202 @@
+use std::collections::{HashMap, VecDeque};
+
+use ash::vk;
+
+// Results are read back this many frames after they were recorded, by which time the fence
+// wait in draw_frame guarantees they are available.
+const FRAME_LAG: usize = 3;
+const MAX_SCOPES: u32 = 32;
+const QUERIES_PER_FRAME: u32 = MAX_SCOPES * 2;
+// Samples in each rolling average, about a second at 60 FPS.
+const AVERAGE_WINDOW: usize = 60;
+
+#[derive(Clone, Debug)]
+pub struct ScopeTiming {
+    // Nested scopes are named by their path, e.g. "frame/opaque".
+    pub name: String,
+    pub depth: usize,
+    pub average_ms: f32,
+}
+
+struct RecordedScope {
+    name: String,
+    depth: usize,
+    start_query: u32,
+}
+
+pub struct GpuProfiler {
+    pub enabled: bool,
+    query_pool: vk::QueryPool,
+    // Nanoseconds per timestamp tick.
+    timestamp_period: f32,
+    timestamp_mask: u64,
+    frame: usize,
+    // Scopes written into each frame's range of the pool, waiting to be read back.
+    recorded: [Vec<RecordedScope>; FRAME_LAG],
+    // Open scopes of the frame being recorded; None for scopes past MAX_SCOPES.
+    stack: Vec<Option<usize>>,
+    next_query: u32,
+    history: HashMap<String, VecDeque<f32>>,
+    timings: Vec<ScopeTiming>,
+}
+
+impl GpuProfiler {
+    // Disabled when the graphics queue family has no timestamp support.
+    pub fn new(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        pdevice: vk::PhysicalDevice,
+        queue_family_index: u32,
+    ) -> Self {
+        let properties = unsafe { instance.get_physical_device_properties(pdevice) };
+        let queue_families = unsafe { instance.get_physical_device_queue_family_properties(pdevice) };
+        let valid_bits = queue_families[queue_family_index as usize].timestamp_valid_bits;
+        let enabled = valid_bits > 0;
+
+        let query_pool = if enabled {
+            let pool_info = vk::QueryPoolCreateInfo::builder()
+                .query_type(vk::QueryType::TIMESTAMP)
+                .query_count(QUERIES_PER_FRAME * FRAME_LAG as u32);
+            unsafe { device.create_query_pool(&pool_info, None).unwrap() }
+        } else {
+            vk::QueryPool::null()
+        };
+
+        Self {
+            enabled,
+            query_pool,
+            timestamp_period: properties.limits.timestamp_period,
+            timestamp_mask: if valid_bits >= 64 { u64::MAX } else { (1u64 << valid_bits) - 1 },
+            frame: 0,
+            recorded: Default::default(),
+            stack: Vec::new(),
+            next_query: 0,
+            history: HashMap::new(),
+            timings: Vec::new(),
+        }
+    }
+
+    // Must be recorded outside a render pass, before any scope of the frame. Collects the
+    // results of the frame that last used this part of the pool and resets it.
+    pub fn begin_frame(&mut self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        if !self.enabled {
+            return;
+        }
+        self.frame = (self.frame + 1) % FRAME_LAG;
+        self.collect(device);
+
+        self.stack.clear();
+        self.next_query = 0;
+        unsafe {
+            device.cmd_reset_query_pool(
+                command_buffer,
+                self.query_pool,
+                self.first_query(),
+                QUERIES_PER_FRAME,
+            );
+        }
+    }
+
+    // Scopes nest; each one must be closed by `end_scope` in the same command buffer.
+    pub fn begin_scope(&mut self, device: &ash::Device, command_buffer: vk::CommandBuffer, name: &str) {
+        if !self.enabled {
+            return;
+        }
+        if self.next_query + 2 > QUERIES_PER_FRAME {
+            self.stack.push(None);
+            return;
+        }
+
+        let depth = self.stack.len();
+        let name = match self.stack.iter().rev().find_map(|scope| *scope) {
+            Some(parent) => format!("{}/{}", self.recorded[self.frame][parent].name, name),
+            None => name.to_string(),
+        };
+        let start_query = self.first_query() + self.next_query;
+        self.next_query += 2;
+        unsafe {
+            device.cmd_write_timestamp(
+                command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                self.query_pool,
+                start_query,
+            );
+        }
+        self.recorded[self.frame].push(RecordedScope { name, depth, start_query });
+        self.stack.push(Some(self.recorded[self.frame].len() - 1));
+    }
+
+    pub fn end_scope(&mut self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        if !self.enabled {
+            return;
+        }
+        let Some(Some(index)) = self.stack.pop() else { return };
+        unsafe {
+            device.cmd_write_timestamp(
+                command_buffer,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                self.query_pool,
+                self.recorded[self.frame][index].start_query + 1,
+            );
+        }
+    }
+
+    // Scopes of the most recently completed frame, in recording order.
+    pub fn timings(&self) -> &[ScopeTiming] {
+        &self.timings
+    }
+
+    fn first_query(&self) -> u32 {
+        self.frame as u32 * QUERIES_PER_FRAME
+    }
+
+    fn collect(&mut self, device: &ash::Device) {
+        let scopes = std::mem::take(&mut self.recorded[self.frame]);
+        if scopes.is_empty() {
+            return;
+        }
+
+        // scopes take consecutive pairs of queries from the start of the frame's range
+        let query_count = scopes.len() as u32 * 2;
+        let mut ticks = vec![0u64; query_count as usize];
+        let result = unsafe {
+            device.get_query_pool_results(
+                self.query_pool,
+                self.first_query(),
+                query_count,
+                &mut ticks,
+                vk::QueryResultFlags::TYPE_64,
+            )
+        };
+        // NOT_READY only happens if the frame never finished, e.g. around a swapchain rebuild
+        if result.is_err() {
+            return;
+        }
+
+        let first_query = self.first_query();
+        self.timings.clear();
+        for scope in scopes {
+            let start = ticks[(scope.start_query - first_query) as usize];
+            let end = ticks[(scope.start_query - first_query + 1) as usize];
+            let elapsed = end.wrapping_sub(start) & self.timestamp_mask;
+            let milliseconds = (elapsed as f64 * self.timestamp_period as f64 / 1_000_000.0) as f32;
+
+            let samples = self.history.entry(scope.name.clone()).or_default();
+            if samples.len() == AVERAGE_WINDOW {
+                samples.pop_front();
+            }
+            samples.push_back(milliseconds);
+            self.timings.push(ScopeTiming {
+                name: scope.name,
+                depth: scope.depth,
+                average_ms: samples.iter().sum::<f32>() / samples.len() as f32,
+            });
+        }
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            device.destroy_query_pool(self.query_pool, None);
+        }
+    }
+}
@@ -0,0 +1,300 @@
+use std::collections::HashSet;
+
+use ash::vk;
+use cgmath::{Matrix4, Vector3};
+
+use super::mesh::Aabb;
+use super::VulkanApp;
+
+pub const MAX_OCCLUSION_QUERIES: u32 = 4096;
+// Flat objects still get a box with some thickness, so their proxy can pass samples.
+const MIN_PROXY_HALF_EXTENT: f32 = 0.01;
+
+// Counters in the order Vulkan writes them, i.e. by ascending flag bit.
+const STATISTICS_FLAGS: vk::QueryPipelineStatisticFlags = vk::QueryPipelineStatisticFlags::from_raw(
+    vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_VERTICES.as_raw()
+        | vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_PRIMITIVES.as_raw()
+        | vk::QueryPipelineStatisticFlags::VERTEX_SHADER_INVOCATIONS.as_raw()
+        | vk::QueryPipelineStatisticFlags::CLIPPING_INVOCATIONS.as_raw()
+        | vk::QueryPipelineStatisticFlags::CLIPPING_PRIMITIVES.as_raw()
+        | vk::QueryPipelineStatisticFlags::FRAGMENT_SHADER_INVOCATIONS.as_raw(),
+);
+const STATISTICS_COUNT: usize = 6;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PipelineStatistics {
+    pub input_vertices: u64,
+    pub input_primitives: u64,
+    pub vertex_invocations: u64,
+    pub clipping_invocations: u64,
+    pub clipping_primitives: u64,
+    pub fragment_invocations: u64,
+}
+
+// One query around the main render pass. Results are read after the next frame's fence wait.
+pub struct StatisticsQuery {
+    pub enabled: bool,
+    query_pool: vk::QueryPool,
+    recorded: bool,
+    latest: Option<PipelineStatistics>,
+}
+
+impl StatisticsQuery {
+    // Disabled without the pipelineStatisticsQuery feature.
+    pub fn new(device: &ash::Device, supported: bool) -> Self {
+        let query_pool = if supported {
+            let pool_info = vk::QueryPoolCreateInfo::builder()
+                .query_type(vk::QueryType::PIPELINE_STATISTICS)
+                .query_count(1)
+                .pipeline_statistics(STATISTICS_FLAGS);
+            unsafe { device.create_query_pool(&pool_info, None).unwrap() }
+        } else {
+            vk::QueryPool::null()
+        };
+
+        Self {
+            enabled: supported,
+            query_pool,
+            recorded: false,
+            latest: None,
+        }
+    }
+
+    // Reads the previous frame's counters and resets the query. Outside a render pass.
+    pub fn begin_frame(&mut self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        if !self.enabled {
+            return;
+        }
+        if std::mem::take(&mut self.recorded) {
+            let mut counters = [0u64; STATISTICS_COUNT];
+            let result = unsafe {
+                device.get_query_pool_results(
+                    self.query_pool,
+                    0,
+                    1,
+                    std::slice::from_mut(&mut counters),
+                    vk::QueryResultFlags::TYPE_64,
+                )
+            };
+            if result.is_ok() {
+                let [input_vertices, input_primitives, vertex_invocations, clipping_invocations, clipping_primitives, fragment_invocations] =
+                    counters;
+                self.latest = Some(PipelineStatistics {
+                    input_vertices,
+                    input_primitives,
+                    vertex_invocations,
+                    clipping_invocations,
+                    clipping_primitives,
+                    fragment_invocations,
+                });
+            }
+        }
+        unsafe {
+            device.cmd_reset_query_pool(command_buffer, self.query_pool, 0, 1);
+        }
+    }
+
+    pub fn begin(&self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        if !self.enabled {
+            return;
+        }
+        unsafe {
+            device.cmd_begin_query(command_buffer, self.query_pool, 0, vk::QueryControlFlags::empty());
+        }
+    }
+
+    pub fn end(&mut self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        if !self.enabled {
+            return;
+        }
+        unsafe {
+            device.cmd_end_query(command_buffer, self.query_pool, 0);
+        }
+        self.recorded = true;
+    }
+
+    // Counters of the last frame that has finished, None until one has.
+    pub fn latest(&self) -> Option<PipelineStatistics> {
+        self.latest
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            device.destroy_query_pool(self.query_pool, None);
+        }
+    }
+}
+
+// One occlusion query per drawn object. Objects that passed no samples are skipped on the
+// following frame and drawn as depth-tested bounding boxes instead, whose queries tell when
+// they come back into view.
+pub struct OcclusionQueries {
+    query_pool: vk::QueryPool,
+    // Object id of each query issued this frame, by query index.
+    queried: Vec<u32>,
+    occluded: HashSet<u32>,
+    // Bounding box draws for the objects skipped this frame: object id and unit cube transform.
+    pub proxies: Vec<(u32, Matrix4<f32>)>,
+}
+
+impl OcclusionQueries {
+    pub fn new(device: &ash::Device) -> Self {
+        let pool_info = vk::QueryPoolCreateInfo::builder()
+            .query_type(vk::QueryType::OCCLUSION)
+            .query_count(MAX_OCCLUSION_QUERIES);
+        let query_pool = unsafe { device.create_query_pool(&pool_info, None).unwrap() };
+
+        Self {
+            query_pool,
+            queried: Vec::new(),
+            occluded: HashSet::new(),
+            proxies: Vec::new(),
+        }
+    }
+
+    // Turns the previous frame's results into the occluded set. If they are not available every
+    // object counts as visible.
+    pub fn collect(&mut self, device: &ash::Device) {
+        self.occluded.clear();
+        self.proxies.clear();
+        let queried = std::mem::take(&mut self.queried);
+        if queried.is_empty() {
+            return;
+        }
+
+        let mut samples = vec![0u64; queried.len()];
+        let result = unsafe {
+            device.get_query_pool_results(
+                self.query_pool,
+                0,
+                queried.len() as u32,
+                &mut samples,
+                vk::QueryResultFlags::TYPE_64,
+            )
+        };
+        if result.is_err() {
+            return;
+        }
+        self.occluded.extend(
+            queried
+                .iter()
+                .zip(samples.iter())
+                .filter(|&(_, &samples)| samples == 0)
+                .map(|(&object_id, _)| object_id),
+        );
+    }
+
+    // Forgets all results, for when object ids are about to be reused.
+    pub fn clear(&mut self) {
+        self.queried.clear();
+        self.occluded.clear();
+        self.proxies.clear();
+    }
+
+    pub fn is_occluded(&self, object_id: u32) -> bool {
+        self.occluded.contains(&object_id)
+    }
+
+    pub fn reset(&self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            device.cmd_reset_query_pool(command_buffer, self.query_pool, 0, MAX_OCCLUSION_QUERIES);
+        }
+    }
+
+    // Returns false once the pool is used up, in which case `end` must not be called.
+    pub fn begin(&mut self, device: &ash::Device, command_buffer: vk::CommandBuffer, object_id: u32) -> bool {
+        let query = self.queried.len() as u32;
+        if query >= MAX_OCCLUSION_QUERIES {
+            return false;
+        }
+        // a non-precise query is enough to tell zero samples from some
+        unsafe {
+            device.cmd_begin_query(command_buffer, self.query_pool, query, vk::QueryControlFlags::empty());
+        }
+        self.queried.push(object_id);
+        true
+    }
+
+    pub fn end(&self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            device.cmd_end_query(command_buffer, self.query_pool, self.queried.len() as u32 - 1);
+        }
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            device.destroy_query_pool(self.query_pool, None);
+        }
+    }
+}
+
+// Maps the [-1, 1] cube of occlusion.vert onto a world space box.
+pub fn proxy_transform(aabb: &Aabb) -> Matrix4<f32> {
+    let center = (aabb.min + aabb.max) * 0.5;
+    let half_extents = ((aabb.max - aabb.min) * 0.5).map(|extent| extent.max(MIN_PROXY_HALF_EXTENT));
+    Matrix4::from_translation(center) * Matrix4::from_nonuniform_scale(half_extents.x, half_extents.y, half_extents.z)
+}
+
+// A camera inside the box would clip away its faces, so such objects are never skipped.
+pub fn contains_point(aabb: &Aabb, point: Vector3<f32>) -> bool {
+    (0..3).all(|axis| point[axis] >= aabb.min[axis] && point[axis] <= aabb.max[axis])
+}
+
+impl VulkanApp {
+    pub fn pipeline_statistics(&self) -> Option<PipelineStatistics> {
+        self.statistics_query.latest()
+    }
+
+    pub fn occlusion_culling_enabled(&self) -> bool {
+        self.occlusion_culling_enabled
+    }
+
+    pub fn set_occlusion_culling(&mut self, enabled: bool) {
+        self.occlusion_culling_enabled = enabled;
+        self.occlusion_queries.clear();
+    }
+
+    // Depth-tested boxes for the objects skipped as occluded, each with its own query.
+    pub(super) fn record_occlusion_proxies(&mut self, command_buffer: vk::CommandBuffer, image_index: usize) {
+        if self.occlusion_queries.proxies.is_empty() {
+            return;
+        }
+
+        unsafe {
+            self.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.occlusion_pipeline,
+            );
+            self.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.occlusion_pipeline_layout,
+                0,
+                &[self.descriptor_sets[image_index]],
+                &[],
+            );
+        }
+        let proxies = std::mem::take(&mut self.occlusion_queries.proxies);
+        for (object_id, transform) in proxies.iter() {
+            if !self.occlusion_queries.begin(&self.device, command_buffer, *object_id) {
+                break;
+            }
+            unsafe {
+                self.device.cmd_push_constants(
+                    command_buffer,
+                    self.occlusion_pipeline_layout,
+                    vk::ShaderStageFlags::VERTEX,
+                    0,
+                    std::slice::from_raw_parts(
+                        transform as *const Matrix4<f32> as *const u8,
+                        std::mem::size_of::<Matrix4<f32>>(),
+                    ),
+                );
+                self.device.cmd_draw(command_buffer, 36, 1, 0, 0);
+            }
+            self.occlusion_queries.end(&self.device, command_buffer);
+        }
+        self.occlusion_queries.proxies = proxies;
+    }
+}
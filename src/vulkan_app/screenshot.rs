@@ -0,0 +1,192 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use ash::vk;
+
+use super::{buffers, swapchain, VulkanApp};
+
+#[derive(Debug)]
+pub struct ScreenshotError {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl fmt::Display for ScreenshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
+
+impl std::error::Error for ScreenshotError {}
+
+// A swapchain image copied into host memory at the end of a frame, read back once the frame's
+// fence has signaled.
+pub struct Screenshot {
+    path: PathBuf,
+    buffer: vk::Buffer,
+    memory: vk::DeviceMemory,
+    extent: vk::Extent2D,
+    format: vk::Format,
+}
+
+// Byte order of the 8-bit formats a screenshot can be taken of, None for the rest.
+fn channel_order(format: vk::Format) -> Option<[usize; 3]> {
+    match format {
+        vk::Format::B8G8R8A8_SRGB | vk::Format::B8G8R8A8_UNORM => Some([2, 1, 0]),
+        vk::Format::R8G8B8A8_SRGB | vk::Format::R8G8B8A8_UNORM => Some([0, 1, 2]),
+        _ => None,
+    }
+}
+
+impl VulkanApp {
+    // Saves the next frame that gets drawn as a PNG; `finish_screenshot` reports the outcome.
+    pub fn request_screenshot(&mut self, path: PathBuf) {
+        self.screenshot_request = Some(path);
+    }
+
+    pub fn screenshot_pending(&self) -> bool {
+        self.screenshot_request.is_some() || self.screenshot.is_some()
+    }
+
+    // Copies the rendered swapchain image, which the render pass left ready to present.
+    pub(super) fn record_screenshot(&mut self, command_buffer: vk::CommandBuffer, image_index: usize) {
+        let Some(path) = self.screenshot_request.take() else { return };
+
+        let error = |message: String| ScreenshotError { path: path.clone(), message };
+        if channel_order(self.swapchain_format).is_none() {
+            self.screenshot_error = Some(error(format!(
+                "screenshots of {:?} output are not supported",
+                self.swapchain_format
+            )));
+            return;
+        }
+        let support = swapchain::query_swapchain_support(&self.surface_loader, self.physical_device, self.surface);
+        if !support
+            .capabilities
+            .supported_usage_flags
+            .contains(vk::ImageUsageFlags::TRANSFER_SRC)
+        {
+            self.screenshot_error = Some(error("the surface does not allow copying its images".to_string()));
+            return;
+        }
+
+        let extent = self.swapchain_extent;
+        let (buffer, memory) = buffers::create_buffer(
+            &self.instance,
+            &self.device,
+            self.physical_device,
+            (extent.width * extent.height * 4) as vk::DeviceSize,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        );
+
+        let image = self.swapchain_images[image_index];
+        let subresource_range = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        let to_transfer = vk::ImageMemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+            .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+            .old_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+            .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(image)
+            .subresource_range(subresource_range);
+        let to_present = vk::ImageMemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::TRANSFER_READ)
+            .dst_access_mask(vk::AccessFlags::empty())
+            .old_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+            .new_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(image)
+            .subresource_range(subresource_range);
+        let region = vk::BufferImageCopy::builder()
+            .image_subresource(vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            })
+            .image_extent(vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            });
+
+        unsafe {
+            self.device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                std::slice::from_ref(&to_transfer),
+            );
+            self.device.cmd_copy_image_to_buffer(
+                command_buffer,
+                image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                buffer,
+                std::slice::from_ref(&region),
+            );
+            self.device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                std::slice::from_ref(&to_present),
+            );
+        }
+
+        self.screenshot = Some(Screenshot {
+            path,
+            buffer,
+            memory,
+            extent,
+            format: self.swapchain_format,
+        });
+    }
+
+    // Waits for the frame holding the copy and writes the file. None while nothing was recorded.
+    pub fn finish_screenshot(&mut self) -> Option<Result<PathBuf, ScreenshotError>> {
+        if let Some(error) = self.screenshot_error.take() {
+            return Some(Err(error));
+        }
+        let screenshot = self.screenshot.take()?;
+
+        let Screenshot { path, buffer, memory, extent, format } = screenshot;
+        let size = (extent.width * extent.height * 4) as usize;
+        let mut pixels = Vec::with_capacity(extent.width as usize * extent.height as usize * 3);
+        unsafe {
+            self.device
+                .wait_for_fences(std::slice::from_ref(&self.in_flight_fence), true, u64::MAX)
+                .unwrap();
+            let data = self
+                .device
+                .map_memory(memory, 0, size as vk::DeviceSize, vk::MemoryMapFlags::empty())
+                .unwrap() as *const u8;
+            let order = channel_order(format).unwrap();
+            // alpha is dropped, the swapchain is composited as opaque
+            for texel in std::slice::from_raw_parts(data, size).chunks_exact(4) {
+                pixels.extend(order.map(|channel| texel[channel]));
+            }
+            self.device.unmap_memory(memory);
+            self.device.destroy_buffer(buffer, None);
+            self.device.free_memory(memory, None);
+        }
+
+        let result = image::save_buffer(&path, &pixels, extent.width, extent.height, image::ExtendedColorType::Rgb8)
+            .map(|_| path.clone())
+            .map_err(|error| ScreenshotError { path, message: error.to_string() });
+        Some(result)
+    }
+}
@@ -0,0 +1,288 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use ash::vk;
+use cgmath::{InnerSpace, Vector3};
+
+use super::texture::Texture;
+use super::VulkanApp;
+
+// Accepted file stems for each face, in Vulkan's +X, -X, +Y, -Y, +Z, -Z layer order. Faces are
+// expected in the usual Y-up convention; skybox.frag maps our Z-up directions onto it.
+const FACE_NAMES: [[&str; 2]; 6] = [
+    ["px", "right"],
+    ["nx", "left"],
+    ["py", "top"],
+    ["ny", "bottom"],
+    ["pz", "front"],
+    ["nz", "back"],
+];
+const FACE_EXTENSIONS: [&str; 4] = ["png", "jpg", "jpeg", "hdr"];
+
+#[derive(Debug)]
+pub struct SkyboxError {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl fmt::Display for SkyboxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
+
+impl std::error::Error for SkyboxError {}
+
+// Decoded faces ready for `Texture::cubemap`.
+pub struct CubemapData {
+    pub size: u32,
+    pub pixels: Vec<u8>,
+    pub format: vk::Format,
+}
+
+pub struct Skybox {
+    pub texture: Texture,
+    // Allocated from the material pool, so it is bound like any other material at set 1.
+    pub descriptor_set: vk::DescriptorSet,
+}
+
+// A directory is read as six face images, a single file as an equirectangular panorama.
+pub fn load_cubemap(path: &Path) -> Result<CubemapData, SkyboxError> {
+    if path.is_dir() {
+        load_faces(path)
+    } else {
+        load_equirectangular(path)
+    }
+}
+
+fn load_faces(dir: &Path) -> Result<CubemapData, SkyboxError> {
+    let mut faces = Vec::with_capacity(6);
+    for names in FACE_NAMES.iter() {
+        let face_path = names
+            .iter()
+            .flat_map(|name| FACE_EXTENSIONS.iter().map(move |extension| dir.join(format!("{}.{}", name, extension))))
+            .find(|candidate| candidate.is_file())
+            .ok_or_else(|| SkyboxError {
+                path: dir.to_path_buf(),
+                message: format!("missing cube face '{}' (or '{}')", names[0], names[1]),
+            })?;
+        faces.push((open_image(&face_path)?, face_path));
+    }
+
+    let (first, _) = &faces[0];
+    let size = first.width();
+    let hdr = faces.iter().any(|(image, _)| is_hdr(image));
+    for (image, face_path) in faces.iter() {
+        if image.width() != size || image.height() != size {
+            return Err(SkyboxError {
+                path: face_path.clone(),
+                message: format!("cube faces must all be {0}x{0}, got {1}x{2}", size, image.width(), image.height()),
+            });
+        }
+    }
+
+    let images = faces.into_iter().map(|(image, _)| image);
+    Ok(if hdr {
+        let texels: Vec<[f32; 4]> = images
+            .flat_map(|image| image.to_rgba32f().pixels().map(|p| p.0).collect::<Vec<_>>())
+            .collect();
+        CubemapData {
+            size,
+            pixels: encode_rgba16f(&texels),
+            format: vk::Format::R16G16B16A16_SFLOAT,
+        }
+    } else {
+        CubemapData {
+            size,
+            pixels: images.flat_map(|image| image.to_rgba8().into_raw()).collect(),
+            format: vk::Format::R8G8B8A8_SRGB,
+        }
+    })
+}
+
+fn load_equirectangular(path: &Path) -> Result<CubemapData, SkyboxError> {
+    let image = open_image(path)?;
+    let hdr = is_hdr(&image);
+    let panorama = image.to_rgba32f();
+    let (width, height) = panorama.dimensions();
+    let source: Vec<[f32; 4]> = panorama.pixels().map(|p| p.0).collect();
+
+    // a quarter of the panorama width keeps roughly the source texel density at the horizon
+    let size = (width / 4).max(1);
+    let mut texels = Vec::with_capacity((6 * size * size) as usize);
+    for face in 0..6 {
+        for y in 0..size {
+            for x in 0..size {
+                let s = 2.0 * (x as f32 + 0.5) / size as f32 - 1.0;
+                let t = 2.0 * (y as f32 + 0.5) / size as f32 - 1.0;
+                let direction = face_direction(face, s, t).normalize();
+                let u = 0.5 + direction.x.atan2(-direction.z) / (2.0 * std::f32::consts::PI);
+                let v = direction.y.clamp(-1.0, 1.0).acos() / std::f32::consts::PI;
+                texels.push(sample_bilinear(&source, width, height, u, v));
+            }
+        }
+    }
+
+    Ok(if hdr {
+        CubemapData {
+            size,
+            pixels: encode_rgba16f(&texels),
+            format: vk::Format::R16G16B16A16_SFLOAT,
+        }
+    } else {
+        // to_rgba32f leaves sRGB-encoded values untouched, so they can go straight back to bytes
+        CubemapData {
+            size,
+            pixels: texels
+                .iter()
+                .flat_map(|texel| texel.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8))
+                .collect(),
+            format: vk::Format::R8G8B8A8_SRGB,
+        }
+    })
+}
+
+fn open_image(path: &Path) -> Result<image::DynamicImage, SkyboxError> {
+    image::open(path).map_err(|e| SkyboxError {
+        path: path.to_path_buf(),
+        message: e.to_string(),
+    })
+}
+
+fn is_hdr(image: &image::DynamicImage) -> bool {
+    matches!(
+        image,
+        image::DynamicImage::ImageRgb32F(_) | image::DynamicImage::ImageRgba32F(_)
+    )
+}
+
+// Direction through texel (s, t) of a cube face, s and t in [-1, 1], following the face
+// selection table of the Vulkan spec.
+fn face_direction(face: usize, s: f32, t: f32) -> Vector3<f32> {
+    match face {
+        0 => Vector3::new(1.0, -t, -s),
+        1 => Vector3::new(-1.0, -t, s),
+        2 => Vector3::new(s, 1.0, t),
+        3 => Vector3::new(s, -1.0, -t),
+        4 => Vector3::new(s, -t, 1.0),
+        _ => Vector3::new(-s, -t, -1.0),
+    }
+}
+
+// Wraps horizontally and clamps vertically, as the panorama's left and right edges meet.
+fn sample_bilinear(pixels: &[[f32; 4]], width: u32, height: u32, u: f32, v: f32) -> [f32; 4] {
+    let x = u * width as f32 - 0.5;
+    let y = (v * height as f32 - 0.5).clamp(0.0, (height - 1) as f32);
+    let x0 = x.floor();
+    let y0 = y.floor();
+    let fx = x - x0;
+    let fy = y - y0;
+
+    let texel = |x: f32, y: f32| {
+        let x = (x as i64).rem_euclid(width as i64) as u32;
+        let y = (y as u32).min(height - 1);
+        pixels[(y * width + x) as usize]
+    };
+    let (a, b, c, d) = (texel(x0, y0), texel(x0 + 1.0, y0), texel(x0, y0 + 1.0), texel(x0 + 1.0, y0 + 1.0));
+
+    let mut out = [0.0; 4];
+    for i in 0..4 {
+        let top = a[i] + (b[i] - a[i]) * fx;
+        let bottom = c[i] + (d[i] - c[i]) * fx;
+        out[i] = top + (bottom - top) * fy;
+    }
+    out
+}
+
+// Half floats keep HDR range while staying filterable on every device, unlike 32-bit floats.
+fn encode_rgba16f(texels: &[[f32; 4]]) -> Vec<u8> {
+    texels
+        .iter()
+        .flat_map(|texel| texel.iter().flat_map(|&c| f32_to_f16(c).to_ne_bytes()).collect::<Vec<_>>())
+        .collect()
+}
+
+// Round-to-nearest conversion; overflow saturates to infinity and tiny values flush to zero.
+fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exponent == 0xff {
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if exponent <= 0 {
+        if exponent < -10 {
+            return sign;
+        }
+        // subnormal: shift in the implicit leading bit
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        let half = (mantissa >> shift) + ((mantissa >> (shift - 1)) & 1);
+        return sign | half as u16;
+    }
+    // a carry out of the mantissa correctly bumps the exponent
+    let half = ((exponent as u32) << 10 | (mantissa >> 13)) + ((mantissa >> 12) & 1);
+    sign | half as u16
+}
+
+impl VulkanApp {
+    // Replaces the background with the given cube map, see `load_cubemap` for accepted layouts.
+    pub fn load_skybox(&mut self, path: &Path) -> Result<(), SkyboxError> {
+        let data = load_cubemap(path)?;
+        let texture = Texture::cubemap(
+            &self.instance,
+            &self.device,
+            self.physical_device,
+            self.command_pool,
+            self.graphics_queue,
+            data.size,
+            1,
+            &data.pixels,
+            data.format,
+        );
+        let descriptor_set = self.material_descriptors.allocate(&self.device, &texture);
+        self.clear_skybox();
+        self.skybox = Some(Skybox { texture, descriptor_set });
+        Ok(())
+    }
+
+    // Goes back to clearing the background to `clear_color`.
+    pub fn clear_skybox(&mut self) {
+        let Some(skybox) = self.skybox.take() else { return };
+        unsafe {
+            self.device.device_wait_idle().unwrap();
+        }
+        self.material_descriptors
+            .free(&self.device, std::slice::from_ref(&skybox.descriptor_set));
+        skybox.texture.destroy(&self.device);
+    }
+
+    // Drawn after the opaque geometry so depth testing at the far plane rejects covered pixels.
+    pub(super) fn record_skybox(&self, command_buffer: vk::CommandBuffer, image_index: usize) {
+        let Some(skybox) = &self.skybox else { return };
+
+        unsafe {
+            self.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.skybox_pipeline,
+            );
+            self.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.skybox_pipeline_layout,
+                0,
+                &[self.descriptor_sets[image_index], skybox.descriptor_set],
+                &[],
+            );
+            self.device.cmd_draw(command_buffer, 36, 1, 0, 0);
+        }
+    }
+}
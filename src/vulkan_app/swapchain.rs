@@ -1,8 +1,134 @@
 use ash::{vk};
+use serde::{Deserialize, Serialize};
 use winit::window::Window;
 
 use super::{utils::{QueueFamilyIndices, SwapchainSupportDetails}, images, pipeline, descriptors, buffers, VulkanApp};
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum PresentMode {
+    // Vsync, the only mode every surface supports.
+    Fifo,
+    // Adaptive vsync: frames that miss a vblank are shown immediately and may tear.
+    FifoRelaxed,
+    // No vsync, tears.
+    Immediate,
+    // No tearing and no frame rate cap, newer frames replace queued ones.
+    Mailbox,
+}
+
+// Requested output. HDR falls back to SDR when the surface lacks the color space.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputMode {
+    Sdr,
+    // Rec.2020 primaries with the ST 2084 (PQ) curve in a 10-bit format.
+    Hdr10,
+    // Linear extended sRGB in a half float format, 1.0 being 80 nits.
+    ScRgb,
+}
+
+impl OutputMode {
+    pub const ALL: [OutputMode; 3] = [OutputMode::Sdr, OutputMode::Hdr10, OutputMode::ScRgb];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputMode::Sdr => "SDR",
+            OutputMode::Hdr10 => "HDR10",
+            OutputMode::ScRgb => "scRGB",
+        }
+    }
+}
+
+// How the shaders encode their linear results for the chosen surface format.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputEncoding {
+    // An _SRGB format, the hardware applies the sRGB curve on write.
+    Srgb,
+    // A UNORM format shown as sRGB, so the shaders apply the curve themselves.
+    SrgbUnorm,
+    Hdr10,
+    ScRgb,
+}
+
+impl OutputEncoding {
+    // Matches the mode switch in encodeOutput, src/shaders/output.glsl.
+    pub fn shader_value(self) -> f32 {
+        match self {
+            OutputEncoding::Srgb => 0.0,
+            OutputEncoding::SrgbUnorm => 1.0,
+            OutputEncoding::Hdr10 => 2.0,
+            OutputEncoding::ScRgb => 3.0,
+        }
+    }
+
+    pub fn is_hdr(self) -> bool {
+        matches!(self, OutputEncoding::Hdr10 | OutputEncoding::ScRgb)
+    }
+}
+
+// Scene values of 1.0 are shown at paper white, highlights are tonemapped towards the peak.
+#[derive(Clone, Copy, Debug)]
+pub struct HdrSettings {
+    pub paper_white_nits: f32,
+    pub peak_nits: f32,
+}
+
+impl Default for HdrSettings {
+    fn default() -> Self {
+        Self {
+            paper_white_nits: 200.0,
+            peak_nits: 1000.0,
+        }
+    }
+}
+
+// Surface formats in order of preference for each encoding.
+const SRGB_FORMATS: [vk::Format; 3] = [
+    vk::Format::B8G8R8A8_SRGB,
+    vk::Format::R8G8B8A8_SRGB,
+    vk::Format::A8B8G8R8_SRGB_PACK32,
+];
+const UNORM_FORMATS: [vk::Format; 5] = [
+    vk::Format::B8G8R8A8_UNORM,
+    vk::Format::R8G8B8A8_UNORM,
+    vk::Format::A8B8G8R8_UNORM_PACK32,
+    vk::Format::A2B10G10R10_UNORM_PACK32,
+    vk::Format::A2R10G10B10_UNORM_PACK32,
+];
+const HDR10_FORMATS: [vk::Format; 2] = [
+    vk::Format::A2B10G10R10_UNORM_PACK32,
+    vk::Format::A2R10G10B10_UNORM_PACK32,
+];
+const SCRGB_FORMATS: [vk::Format; 1] = [vk::Format::R16G16B16A16_SFLOAT];
+
+impl PresentMode {
+    pub const ALL: [PresentMode; 4] = [
+        PresentMode::Fifo,
+        PresentMode::FifoRelaxed,
+        PresentMode::Immediate,
+        PresentMode::Mailbox,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PresentMode::Fifo => "Vsync (FIFO)",
+            PresentMode::FifoRelaxed => "Adaptive (FIFO relaxed)",
+            PresentMode::Immediate => "Off (immediate)",
+            PresentMode::Mailbox => "Mailbox",
+        }
+    }
+
+    fn to_vk(self) -> vk::PresentModeKHR {
+        match self {
+            PresentMode::Fifo => vk::PresentModeKHR::FIFO,
+            PresentMode::FifoRelaxed => vk::PresentModeKHR::FIFO_RELAXED,
+            PresentMode::Immediate => vk::PresentModeKHR::IMMEDIATE,
+            PresentMode::Mailbox => vk::PresentModeKHR::MAILBOX,
+        }
+    }
+}
+
 pub fn create_swapchain(
     instance: &ash::Instance,
     device: &ash::Device,
@@ -12,10 +138,12 @@ pub fn create_swapchain(
     indices: &QueueFamilyIndices,
     swapchain_loader: &ash::extensions::khr::Swapchain,
     window: &Window,
-) -> (vk::SwapchainKHR, vk::Format, vk::Extent2D) {
+    requested_present_mode: PresentMode,
+    output_mode: OutputMode,
+) -> (vk::SwapchainKHR, vk::Format, vk::Extent2D, OutputEncoding) {
     let swapchain_support = query_swapchain_support(surface_loader, pdevice, surface);
-    let surface_format = choose_swap_surface_format(&swapchain_support.formats);
-    let present_mode = choose_swap_present_mode(&swapchain_support.present_modes);
+    let (surface_format, output_encoding) = choose_swap_surface_format(&swapchain_support.formats, output_mode);
+    let present_mode = choose_swap_present_mode(&swapchain_support.present_modes, requested_present_mode);
     let extent = choose_swap_extent(&swapchain_support.capabilities, window);
 
     let mut image_count = swapchain_support.capabilities.min_image_count + 1;
@@ -25,6 +153,10 @@ pub fn create_swapchain(
         image_count = swapchain_support.capabilities.max_image_count;
     }
 
+    // transfer source for screenshots, where the surface allows it
+    let image_usage = vk::ImageUsageFlags::COLOR_ATTACHMENT
+        | (swapchain_support.capabilities.supported_usage_flags & vk::ImageUsageFlags::TRANSFER_SRC);
+
     let mut create_info = vk::SwapchainCreateInfoKHR::builder()
         .surface(surface)
         .min_image_count(image_count)
@@ -32,7 +164,7 @@ pub fn create_swapchain(
         .image_color_space(surface_format.color_space)
         .image_extent(extent)
         .image_array_layers(1)
-        .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT);
+        .image_usage(image_usage);
 
     let queue_family_indices = [indices.graphics_family.unwrap(), indices.present_family.unwrap()];
 
@@ -52,7 +184,7 @@ pub fn create_swapchain(
 
     let swapchain = unsafe { swapchain_loader.create_swapchain(&create_info, None).unwrap() };
 
-    (swapchain, surface_format.format, extent)
+    (swapchain, surface_format.format, extent, output_encoding)
 }
 
 pub fn query_swapchain_support(
@@ -83,23 +215,64 @@ pub fn query_swapchain_support(
     }
 }
 
-fn choose_swap_surface_format(available_formats: &[vk::SurfaceFormatKHR]) -> vk::SurfaceFormatKHR {
-    *available_formats
-        .iter()
-        .find(|format| {
-            format.format == vk::Format::B8G8R8A8_SRGB
-                && format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
+fn choose_swap_surface_format(
+    available_formats: &[vk::SurfaceFormatKHR],
+    output_mode: OutputMode,
+) -> (vk::SurfaceFormatKHR, OutputEncoding) {
+    let find = |formats: &[vk::Format], color_space: vk::ColorSpaceKHR, encoding: OutputEncoding| {
+        formats.iter().find_map(|&format| {
+            available_formats
+                .iter()
+                .find(|available| available.format == format && available.color_space == color_space)
+                .map(|&available| (available, encoding))
+        })
+    };
+
+    let hdr = match output_mode {
+        OutputMode::Sdr => None,
+        OutputMode::Hdr10 => find(&HDR10_FORMATS, vk::ColorSpaceKHR::HDR10_ST2084_EXT, OutputEncoding::Hdr10),
+        OutputMode::ScRgb => find(&SCRGB_FORMATS, vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT, OutputEncoding::ScRgb),
+    };
+    hdr.or_else(|| find(&SRGB_FORMATS, vk::ColorSpaceKHR::SRGB_NONLINEAR, OutputEncoding::Srgb))
+        .or_else(|| find(&UNORM_FORMATS, vk::ColorSpaceKHR::SRGB_NONLINEAR, OutputEncoding::SrgbUnorm))
+        .unwrap_or_else(|| {
+            // nothing we know, so take the first format and let the shaders encode unless it is sRGB
+            let format = available_formats[0];
+            let encoding = if SRGB_FORMATS.contains(&format.format) {
+                OutputEncoding::Srgb
+            } else {
+                OutputEncoding::SrgbUnorm
+            };
+            (format, encoding)
         })
-        .unwrap_or(&available_formats[0])
 }
 
-fn choose_swap_present_mode(available_present_modes: &[vk::PresentModeKHR]) -> vk::PresentModeKHR {
+// SDR and whichever HDR outputs the surface offers a format for.
+pub fn supported_output_modes(available_formats: &[vk::SurfaceFormatKHR]) -> Vec<OutputMode> {
+    OutputMode::ALL
+        .into_iter()
+        .filter(|&mode| {
+            let (_, encoding) = choose_swap_surface_format(available_formats, mode);
+            mode == OutputMode::Sdr || encoding.is_hdr()
+        })
+        .collect()
+}
+
+fn choose_swap_present_mode(available_present_modes: &[vk::PresentModeKHR], requested: PresentMode) -> vk::PresentModeKHR {
     *available_present_modes
         .iter()
-        .find(|mode| **mode == vk::PresentModeKHR::MAILBOX)
+        .find(|mode| **mode == requested.to_vk())
         .unwrap_or(&vk::PresentModeKHR::FIFO)
 }
 
+// The modes the surface supports, in the order of PresentMode::ALL.
+pub fn supported_present_modes(available_present_modes: &[vk::PresentModeKHR]) -> Vec<PresentMode> {
+    PresentMode::ALL
+        .into_iter()
+        .filter(|mode| available_present_modes.contains(&mode.to_vk()))
+        .collect()
+}
+
 fn choose_swap_extent(capabilities: &vk::SurfaceCapabilitiesKHR, window: &Window) -> vk::Extent2D {
     if capabilities.current_extent.width != u32::MAX {
         capabilities.current_extent
@@ -161,6 +334,26 @@ impl VulkanApp {
             }
             self.device.destroy_pipeline(self.graphics_pipeline, None);
             self.device.destroy_pipeline(self.wireframe_pipeline, None);
+            self.device.destroy_pipeline(self.indirect_pipeline, None);
+            self.device
+                .destroy_pipeline(self.triangle_wireframe_pipeline, None);
+            self.device
+                .destroy_pipeline(self.indirect_wireframe_pipeline, None);
+            self.device.destroy_pipeline(self.particle_pipeline, None);
+            self.device
+                .destroy_pipeline_layout(self.particle_pipeline_layout, None);
+            self.device.destroy_pipeline(self.skybox_pipeline, None);
+            self.device
+                .destroy_pipeline_layout(self.skybox_pipeline_layout, None);
+            self.device.destroy_pipeline(self.occlusion_pipeline, None);
+            self.device
+                .destroy_pipeline_layout(self.occlusion_pipeline_layout, None);
+            self.device.destroy_pipeline(self.overlay_pipeline, None);
+            self.device
+                .destroy_pipeline_layout(self.overlay_pipeline_layout, None);
+            self.device.destroy_pipeline(self.gui_pipeline, None);
+            self.device
+                .destroy_pipeline_layout(self.gui_pipeline_layout, None);
             self.device
                 .destroy_descriptor_pool(self.descriptor_pool, None);
             self.device
@@ -172,21 +365,27 @@ impl VulkanApp {
             self.device.destroy_image_view(self.depth_image_view, None);
             self.device.destroy_image(self.depth_image, None);
             self.device.free_memory(self.depth_image_memory, None);
+            self.device.destroy_image_view(self.color_image_view, None);
+            self.device.destroy_image(self.color_image, None);
+            self.device.free_memory(self.color_image_memory, None);
             self.swapchain_loader
                 .destroy_swapchain(self.swapchain, None);
         }
     }
 
+    // Encoding and paper white for the UI shaders, which get them as push constants instead of
+    // through the uniform buffer.
+    pub(super) fn output_push_constants(&self) -> [f32; 2] {
+        [self.output_encoding.shader_value(), self.hdr_settings.paper_white_nits]
+    }
+
     pub fn recreate_swapchain(&mut self, window: &Window) {
         unsafe {
             self.device.device_wait_idle().unwrap();
         }
         self.cleanup_swapchain();
 
-        let depth_format = images::find_depth_format(&self.instance, self.physical_device);
-        self.render_pass = pipeline::create_render_pass(&self.device, self.swapchain_format, depth_format);
-
-        let (swapchain, swapchain_format, swapchain_extent) = create_swapchain(
+        let (swapchain, swapchain_format, swapchain_extent, output_encoding) = create_swapchain(
             &self.instance,
             &self.device,
             self.physical_device,
@@ -195,42 +394,134 @@ impl VulkanApp {
             &self.queue_family_indices,
             &self.swapchain_loader,
             window,
+            self.present_mode,
+            self.output_mode,
         );
         self.swapchain = swapchain;
+        self.output_encoding = output_encoding;
         self.swapchain_images = unsafe { self.swapchain_loader.get_swapchain_images(swapchain).unwrap() };
         self.swapchain_format = swapchain_format;
         self.swapchain_extent = swapchain_extent;
         self.swapchain_image_views =
             create_image_views(&self.device, &self.swapchain_images, self.swapchain_format);
         let depth_format = images::find_depth_format(&self.instance, self.physical_device);
-        self.render_pass = pipeline::create_render_pass(&self.device, self.swapchain_format, depth_format);
+        self.render_pass =
+            pipeline::create_render_pass(&self.device, self.swapchain_format, depth_format, self.msaa_samples);
         let (graphics_pipeline, pipeline_layout) = pipeline::create_graphics_pipeline(
             &self.device,
             self.render_pass,
             self.swapchain_extent,
-            self.descriptor_set_layout,
+            self.msaa_samples,
+            &[
+                self.descriptor_set_layout,
+                self.material_descriptor_set_layout,
+                self.gpu_culling.descriptor_set_layout,
+                self.ibl_descriptor_set_layout,
+            ],
         );
         self.graphics_pipeline = graphics_pipeline;
         self.pipeline_layout = pipeline_layout;
         self.wireframe_pipeline = pipeline::create_wireframe_pipeline(
+            &self.device,
+            self.render_pass,
+            self.swapchain_extent,
+            self.msaa_samples,
+            self.pipeline_layout,
+        );
+        self.indirect_pipeline = pipeline::create_indirect_pipeline(
             &self.device,
             self.render_pass,
             self.swapchain_extent,
+            self.msaa_samples,
             self.pipeline_layout,
         );
+        if self.device_features.fill_mode_non_solid {
+            self.triangle_wireframe_pipeline = pipeline::create_triangle_wireframe_pipeline(
+                &self.device,
+                self.render_pass,
+                self.swapchain_extent,
+                self.msaa_samples,
+                self.pipeline_layout,
+            );
+            self.indirect_wireframe_pipeline = pipeline::create_indirect_wireframe_pipeline(
+                &self.device,
+                self.render_pass,
+                self.swapchain_extent,
+                self.msaa_samples,
+                self.pipeline_layout,
+            );
+        }
+        let (particle_pipeline, particle_pipeline_layout) = pipeline::create_particle_pipeline(
+            &self.device,
+            self.render_pass,
+            self.swapchain_extent,
+            self.msaa_samples,
+            &[self.descriptor_set_layout, self.particles.descriptor_set_layout],
+        );
+        self.particle_pipeline = particle_pipeline;
+        self.particle_pipeline_layout = particle_pipeline_layout;
+        let (skybox_pipeline, skybox_pipeline_layout) = pipeline::create_skybox_pipeline(
+            &self.device,
+            self.render_pass,
+            self.swapchain_extent,
+            self.msaa_samples,
+            &[self.descriptor_set_layout, self.material_descriptor_set_layout],
+        );
+        self.skybox_pipeline = skybox_pipeline;
+        self.skybox_pipeline_layout = skybox_pipeline_layout;
+        let (occlusion_pipeline, occlusion_pipeline_layout) = pipeline::create_occlusion_pipeline(
+            &self.device,
+            self.render_pass,
+            self.swapchain_extent,
+            self.msaa_samples,
+            &[self.descriptor_set_layout],
+        );
+        self.occlusion_pipeline = occlusion_pipeline;
+        self.occlusion_pipeline_layout = occlusion_pipeline_layout;
+        let (overlay_pipeline, overlay_pipeline_layout) = pipeline::create_overlay_pipeline(
+            &self.device,
+            self.render_pass,
+            self.swapchain_extent,
+            self.msaa_samples,
+            &[self.material_descriptor_set_layout],
+        );
+        self.overlay_pipeline = overlay_pipeline;
+        self.overlay_pipeline_layout = overlay_pipeline_layout;
+        let (gui_pipeline, gui_pipeline_layout) = pipeline::create_gui_pipeline(
+            &self.device,
+            self.render_pass,
+            self.swapchain_extent,
+            self.msaa_samples,
+            &[self.material_descriptor_set_layout],
+        );
+        self.gui_pipeline = gui_pipeline;
+        self.gui_pipeline_layout = gui_pipeline_layout;
         let (depth_image, depth_image_memory, depth_image_view) = images::create_depth_resources(
             &self.instance,
             &self.device,
             self.physical_device,
             self.swapchain_extent,
+            self.msaa_samples,
         );
         self.depth_image = depth_image;
         self.depth_image_memory = depth_image_memory;
         self.depth_image_view = depth_image_view;
+        let (color_image, color_image_memory, color_image_view) = images::create_color_resources(
+            &self.instance,
+            &self.device,
+            self.physical_device,
+            self.swapchain_extent,
+            self.swapchain_format,
+            self.msaa_samples,
+        );
+        self.color_image = color_image;
+        self.color_image_memory = color_image_memory;
+        self.color_image_view = color_image_view;
         self.framebuffers = pipeline::create_framebuffers(
             &self.device,
             &self.swapchain_image_views,
             self.depth_image_view,
+            self.color_image_view,
             self.render_pass,
             self.swapchain_extent,
         );
@@ -0,0 +1,272 @@
+use ash::vk;
+
+use super::{buffers, images};
+
+pub struct Texture {
+    pub image: vk::Image,
+    pub memory: vk::DeviceMemory,
+    pub view: vk::ImageView,
+    pub sampler: vk::Sampler,
+}
+
+impl Texture {
+    pub fn from_rgba8(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        pdevice: vk::PhysicalDevice,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+        format: vk::Format,
+    ) -> Self {
+        assert_eq!(pixels.len(), (width * height * 4) as usize, "Texture data does not match its size");
+        Self::from_pixels(
+            instance,
+            device,
+            pdevice,
+            command_pool,
+            queue,
+            width,
+            height,
+            pixels,
+            format,
+            vk::SamplerAddressMode::REPEAT,
+        )
+    }
+
+    // Like `from_rgba8` for any single-plane format, the texel size follows from the data length.
+    pub fn from_pixels(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        pdevice: vk::PhysicalDevice,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+        format: vk::Format,
+        address_mode: vk::SamplerAddressMode,
+    ) -> Self {
+        let image_size = pixels.len() as vk::DeviceSize;
+        assert_eq!(
+            image_size % (width * height) as vk::DeviceSize,
+            0,
+            "Texture data does not match its size"
+        );
+
+        let (staging_buffer, staging_memory) = buffers::create_buffer(
+            instance,
+            device,
+            pdevice,
+            image_size,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        );
+        unsafe {
+            let data_ptr = device
+                .map_memory(staging_memory, 0, image_size, vk::MemoryMapFlags::empty())
+                .unwrap();
+            let mut align = ash::util::Align::new(data_ptr, std::mem::align_of::<u8>() as _, image_size);
+            align.copy_from_slice(pixels);
+            device.unmap_memory(staging_memory);
+        }
+
+        let (image, memory) = images::create_image(
+            instance,
+            device,
+            pdevice,
+            width,
+            height,
+            format,
+            vk::SampleCountFlags::TYPE_1,
+            vk::ImageTiling::OPTIMAL,
+            vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        );
+
+        images::transition_image_layout(
+            device,
+            command_pool,
+            queue,
+            image,
+            1,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        );
+        images::copy_buffer_to_image(device, command_pool, queue, staging_buffer, image, width, height, 1);
+        images::transition_image_layout(
+            device,
+            command_pool,
+            queue,
+            image,
+            1,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        );
+
+        unsafe {
+            device.destroy_buffer(staging_buffer, None);
+            device.free_memory(staging_memory, None);
+        }
+
+        let view = images::create_image_view(device, image, format, vk::ImageAspectFlags::COLOR);
+        let sampler = create_texture_sampler(device, address_mode);
+
+        Self {
+            image,
+            memory,
+            view,
+            sampler,
+        }
+    }
+
+    // `pixels` holds each mip level in turn, and within a level the six faces back to back in
+    // +X, -X, +Y, -Y, +Z, -Z order. Level 0 is `size` x `size` texels of `format`.
+    pub fn cubemap(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        pdevice: vk::PhysicalDevice,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+        size: u32,
+        mip_levels: u32,
+        pixels: &[u8],
+        format: vk::Format,
+    ) -> Self {
+        let image_size = pixels.len() as vk::DeviceSize;
+        let texel_count: u32 = (0..mip_levels).map(|mip| 6 * (size >> mip).max(1).pow(2)).sum();
+        assert_eq!(
+            image_size % texel_count as vk::DeviceSize,
+            0,
+            "Cubemap data does not match its size"
+        );
+        let texel_size = (image_size / texel_count as vk::DeviceSize) as u32;
+
+        let (staging_buffer, staging_memory) = buffers::create_buffer(
+            instance,
+            device,
+            pdevice,
+            image_size,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        );
+        unsafe {
+            let data_ptr = device
+                .map_memory(staging_memory, 0, image_size, vk::MemoryMapFlags::empty())
+                .unwrap();
+            let mut align = ash::util::Align::new(data_ptr, std::mem::align_of::<u8>() as _, image_size);
+            align.copy_from_slice(pixels);
+            device.unmap_memory(staging_memory);
+        }
+
+        let (image, memory) = images::create_cube_image(
+            instance,
+            device,
+            pdevice,
+            size,
+            mip_levels,
+            format,
+            vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+        );
+
+        images::transition_image_layout(
+            device,
+            command_pool,
+            queue,
+            image,
+            6,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        );
+        images::copy_buffer_to_image_mips(
+            device,
+            command_pool,
+            queue,
+            staging_buffer,
+            image,
+            size,
+            mip_levels,
+            6,
+            texel_size,
+        );
+        images::transition_image_layout(
+            device,
+            command_pool,
+            queue,
+            image,
+            6,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        );
+
+        unsafe {
+            device.destroy_buffer(staging_buffer, None);
+            device.free_memory(staging_memory, None);
+        }
+
+        let view = images::create_cube_image_view(device, image, format);
+        // clamping keeps the face edges from bleeding into each other
+        let sampler = create_texture_sampler(device, vk::SamplerAddressMode::CLAMP_TO_EDGE);
+
+        Self {
+            image,
+            memory,
+            view,
+            sampler,
+        }
+    }
+
+    // 1x1 white texture bound for meshes that have no texture of their own
+    pub fn white(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        pdevice: vk::PhysicalDevice,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+    ) -> Self {
+        Self::from_rgba8(
+            instance,
+            device,
+            pdevice,
+            command_pool,
+            queue,
+            1,
+            1,
+            &[255, 255, 255, 255],
+            vk::Format::R8G8B8A8_UNORM,
+        )
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            device.destroy_sampler(self.sampler, None);
+            device.destroy_image_view(self.view, None);
+            device.destroy_image(self.image, None);
+            device.free_memory(self.memory, None);
+        }
+    }
+}
+
+pub fn create_texture_sampler(device: &ash::Device, address_mode: vk::SamplerAddressMode) -> vk::Sampler {
+    let sampler_info = vk::SamplerCreateInfo::builder()
+        .mag_filter(vk::Filter::LINEAR)
+        .min_filter(vk::Filter::LINEAR)
+        .address_mode_u(address_mode)
+        .address_mode_v(address_mode)
+        .address_mode_w(address_mode)
+        .anisotropy_enable(false)
+        .max_anisotropy(1.0)
+        .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
+        .unnormalized_coordinates(false)
+        .compare_enable(false)
+        .compare_op(vk::CompareOp::ALWAYS)
+        .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+        .mip_lod_bias(0.0)
+        .min_lod(0.0)
+        // clamped to the image's own mip count, so single-level textures are unaffected
+        .max_lod(vk::LOD_CLAMP_NONE);
+
+    unsafe { device.create_sampler(&sampler_info, None).unwrap() }
+}
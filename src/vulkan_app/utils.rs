@@ -6,6 +6,8 @@ use std::ffi::CStr;
 pub struct QueueFamilyIndices {
     pub graphics_family: Option<u32>,
     pub present_family: Option<u32>,
+    // A family with compute but no graphics support, when the device has one.
+    pub compute_family: Option<u32>,
 }
 
 impl QueueFamilyIndices {
@@ -13,6 +15,7 @@ impl QueueFamilyIndices {
         Self {
             graphics_family: None,
             present_family: None,
+            compute_family: None,
         }
     }
 
@@ -21,17 +24,42 @@ impl QueueFamilyIndices {
     }
 }
 
+// Optional device features, recording which ones create_logical_device managed to enable.
+#[derive(Clone, Copy, Default)]
+pub struct DeviceFeatures {
+    pub draw_indirect_first_instance: bool,
+    pub multi_draw_indirect: bool,
+    pub draw_indirect_count: bool,
+    pub memory_budget: bool,
+    pub fill_mode_non_solid: bool,
+    pub pipeline_statistics_query: bool,
+}
+
 pub struct SwapchainSupportDetails {
     pub capabilities: vk::SurfaceCapabilitiesKHR,
     pub formats: Vec<vk::SurfaceFormatKHR>,
     pub present_modes: Vec<vk::PresentModeKHR>,
 }
 
+// std140 layout of the UBO at set 0.
 #[derive(Copy, Clone)]
+#[repr(C)]
 pub struct UniformBufferObject {
-    pub model: Matrix4<f32>,
     pub view: Matrix4<f32>,
     pub proj: Matrix4<f32>,
+    pub camera_position: [f32; 4],
+    // x: image-based lighting enabled, y: intensity, z: roughness, w: metallic
+    pub lighting: [f32; 4],
+    // x: OutputEncoding::shader_value, y: paper white nits, z: peak nits
+    pub output: [f32; 4],
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct ObjectPushConstants {
+    pub model: Matrix4<f32>,
+    pub color: [f32; 4],
+    pub object_id: u32,
 }
 
 pub unsafe extern "system" fn vulkan_debug_callback(